@@ -0,0 +1,192 @@
+use std::{
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::error::{Result, StripError};
+
+/// Extensions of bundles that can be nested inside an app and are signed separately.
+const NESTED_BUNDLE_EXTENSIONS: &[&str] = &["app", "appex", "framework", "systemextension", "xpc"];
+
+/// Returns the signed bundles nested inside a bundle, such as app extensions, frameworks and XPC
+/// services, in sorted order.
+///
+/// This walks the whole bundle, so it works for both the macOS layout (`Contents/PlugIns`,
+/// `Contents/Frameworks`, ...) and the flat iOS layout. Symlinks are not followed, so framework
+/// `Versions/Current` links don't produce duplicates. A standalone binary has no nested bundles.
+pub fn find_nested_bundles(bundle_path: &Path) -> Result<Vec<PathBuf>> {
+    if bundle_path.is_file() {
+        return Ok(Vec::new());
+    }
+    let mut bundles = Vec::new();
+    find_nested_bundles_in(bundle_path, &mut bundles)?;
+    bundles.sort();
+    Ok(bundles)
+}
+
+/// Returns the `CFBundleIdentifier` in a bundle's `Info.plist`, checking both the macOS layout
+/// (`Contents/Info.plist`) and the flat iOS layout.
+///
+/// Returns `None` if the bundle has no `Info.plist` or it has no bundle identifier, such as for a
+/// standalone binary.
+pub fn read_bundle_identifier(bundle_path: &Path) -> Result<Option<String>> {
+    let Some(info_plist_path) = ["Contents/Info.plist", "Info.plist"]
+        .iter()
+        .map(|path| bundle_path.join(path))
+        .find(|path| path.is_file())
+    else {
+        return Ok(None);
+    };
+    let info_plist = plist::Value::from_file(&info_plist_path).map_err(|error| {
+        StripError::parse_with(
+            format!("Failed to parse {}", info_plist_path.display()),
+            error,
+        )
+    })?;
+    Ok(info_plist
+        .as_dictionary()
+        .and_then(|info_plist| info_plist.get("CFBundleIdentifier")?.as_string())
+        .map(String::from))
+}
+
+fn find_nested_bundles_in(dir: &Path, bundles: &mut Vec<PathBuf>) -> Result<()> {
+    let read_dir_error =
+        |error| StripError::io(format!("Failed to read directory {}", dir.display()), error);
+    for entry in fs::read_dir(dir).map_err(read_dir_error)? {
+        let entry = entry.map_err(read_dir_error)?;
+        let file_type = entry.file_type().map_err(|error| {
+            StripError::io(
+                format!("Failed to get file type of {}", entry.path().display()),
+                error,
+            )
+        })?;
+        if !file_type.is_dir() {
+            continue;
+        }
+
+        let path = entry.path();
+        if path
+            .extension()
+            .and_then(OsStr::to_str)
+            .is_some_and(|extension| NESTED_BUNDLE_EXTENSIONS.contains(&extension))
+        {
+            bundles.push(path.clone());
+        }
+        find_nested_bundles_in(&path, bundles)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "provisioned-entitlement-stripper-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_find_nested_bundles_macos() {
+        let dir = test_dir("nested-macos");
+        let app = dir.join("Example.app");
+        for path in [
+            "Contents/MacOS",
+            "Contents/PlugIns/Widget.appex/Contents/MacOS",
+            "Contents/Frameworks/Kit.framework/Versions/A/XPCServices/Helper.xpc",
+            "Contents/Resources/Assets.bundle",
+        ] {
+            fs::create_dir_all(app.join(path)).unwrap();
+        }
+        fs::write(app.join("Contents/MacOS/Example"), b"").unwrap();
+
+        let bundles = find_nested_bundles(&app);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            bundles.unwrap(),
+            [
+                app.join("Contents/Frameworks/Kit.framework"),
+                app.join("Contents/Frameworks/Kit.framework/Versions/A/XPCServices/Helper.xpc"),
+                app.join("Contents/PlugIns/Widget.appex"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_nested_bundles_ios() {
+        let dir = test_dir("nested-ios");
+        let app = dir.join("Example.app");
+        fs::create_dir_all(app.join("PlugIns/Widget.appex")).unwrap();
+        fs::create_dir_all(app.join("Frameworks/Kit.framework")).unwrap();
+
+        let bundles = find_nested_bundles(&app);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            bundles.unwrap(),
+            [
+                app.join("Frameworks/Kit.framework"),
+                app.join("PlugIns/Widget.appex"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_find_nested_bundles_binary() {
+        let dir = test_dir("nested-binary");
+        let binary = dir.join("tool");
+        fs::write(&binary, b"").unwrap();
+
+        let bundles = find_nested_bundles(&binary);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(bundles.unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_find_nested_bundles_missing() {
+        assert!(find_nested_bundles(Path::new("/nonexistent/Example.app")).is_err());
+    }
+
+    #[test]
+    fn test_read_bundle_identifier() {
+        let dir = test_dir("bundle-identifier");
+        let macos_app = dir.join("Example.app");
+        let ios_app = dir.join("Mobile.app");
+        let no_identifier_app = dir.join("Empty.app");
+        fs::create_dir_all(macos_app.join("Contents")).unwrap();
+        fs::create_dir_all(&ios_app).unwrap();
+        fs::create_dir_all(&no_identifier_app).unwrap();
+        let info_plist = |identifier: &str| {
+            format!(
+                r#"<plist version="1.0"><dict><key>CFBundleIdentifier</key><string>{identifier}</string></dict></plist>"#
+            )
+        };
+        fs::write(
+            macos_app.join("Contents/Info.plist"),
+            info_plist("com.example.example"),
+        )
+        .unwrap();
+        fs::write(ios_app.join("Info.plist"), info_plist("com.example.mobile")).unwrap();
+        fs::write(
+            no_identifier_app.join("Info.plist"),
+            r#"<plist version="1.0"><dict/></plist>"#,
+        )
+        .unwrap();
+
+        let macos = read_bundle_identifier(&macos_app);
+        let ios = read_bundle_identifier(&ios_app);
+        let no_identifier = read_bundle_identifier(&no_identifier_app);
+        let missing = read_bundle_identifier(&dir.join("Missing.app"));
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(macos.unwrap().as_deref(), Some("com.example.example"));
+        assert_eq!(ios.unwrap().as_deref(), Some("com.example.mobile"));
+        assert_eq!(no_identifier.unwrap(), None);
+        assert_eq!(missing.unwrap(), None);
+    }
+}
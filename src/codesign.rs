@@ -0,0 +1,1059 @@
+use std::{
+    collections::HashMap,
+    ffi::OsStr,
+    fs,
+    io::Read,
+    iter::Peekable,
+    path::{Path, PathBuf},
+    process::{Child, Command, Output, Stdio},
+    sync::{LazyLock, Mutex, PoisonError},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use serde::Serialize;
+
+use crate::{
+    check_plist_version,
+    duplicates::warn_suspicious_keys,
+    error::{Result, StripError},
+};
+
+/// The codesign binary used when no other path is given.
+pub const DEFAULT_CODESIGN_PATH: &str = "/usr/bin/codesign";
+
+/// The codesign options this crate sets itself, which can't be given to [`Codesign::args`].
+pub const RESERVED_ARGS: &[&str] = &[
+    "--display",
+    "-d",
+    "--xml",
+    "--entitlements",
+    "--arch",
+    "--architecture",
+    "-a",
+];
+
+/// How often to check whether codesign has exited when running it with a timeout.
+const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// What codesign prints when asked about an app that has no signature.
+const NOT_SIGNED_MESSAGE: &str = "code object is not signed at all";
+
+/// The entitlements read by [`Codesign::get_entitlements_cached`] in this process.
+static ENTITLEMENTS_CACHE: LazyLock<Mutex<HashMap<CacheKey, plist::Value>>> =
+    LazyLock::new(Default::default);
+
+/// What cached entitlements were read from: the app as of its modification time, and the
+/// options codesign was run with.
+#[derive(PartialEq, Eq, Hash)]
+struct CacheKey {
+    codesign_path: PathBuf,
+    arch: Option<String>,
+    args: Vec<String>,
+    app_path: PathBuf,
+    modified: SystemTime,
+}
+
+/// Who signed an app, as reported by `codesign --display --verbose=2`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct SigningInfo {
+    /// The signing identifier, usually the bundle ID.
+    pub identifier: Option<String>,
+    /// The team ID, which is missing for ad-hoc signatures.
+    pub team_identifier: Option<String>,
+    /// The certificate chain, from the signing certificate to the root.
+    pub authorities: Vec<String>,
+}
+
+/// How to run `codesign`.
+#[derive(Debug, Clone)]
+pub struct Codesign {
+    path: PathBuf,
+    timeout: Option<Duration>,
+    arch: Option<String>,
+    print_command: bool,
+    execute: bool,
+    strict_parse: bool,
+    fail_on_plist_version: bool,
+    args: Vec<String>,
+}
+
+impl Codesign {
+    /// Runs the codesign binary at `path`, with no timeout.
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            timeout: None,
+            arch: None,
+            print_command: false,
+            execute: true,
+            strict_parse: false,
+            fail_on_plist_version: false,
+            args: Vec::new(),
+        }
+    }
+
+    /// Kills codesign and fails if it runs for longer than `timeout`.
+    pub fn timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Reads the given architecture's slice of universal binaries instead of codesign's default.
+    pub fn arch(mut self, arch: Option<String>) -> Self {
+        self.arch = arch;
+        self
+    }
+
+    /// Prints each codesign command to stderr, quoted so it can be pasted into a shell, before
+    /// running it.
+    pub fn print_command(mut self, print_command: bool) -> Self {
+        self.print_command = print_command;
+        self
+    }
+
+    /// Whether to actually run codesign. If not, every method returns `None` instead of running
+    /// it, after printing the command if [`Codesign::print_command`] is enabled.
+    pub fn execute(mut self, execute: bool) -> Self {
+        self.execute = execute;
+        self
+    }
+
+    /// Warns about duplicate keys, and keys only differing in case or surrounding whitespace, in
+    /// the entitlements codesign prints, which the plist parser would silently collapse.
+    pub fn strict_parse(mut self, strict_parse: bool) -> Self {
+        self.strict_parse = strict_parse;
+        self
+    }
+
+    /// Fails with [`StripError::UnexpectedPlistVersion`], instead of warning, when the
+    /// entitlements codesign prints aren't plist version [`PLIST_VERSION`](crate::PLIST_VERSION).
+    pub fn fail_on_plist_version(mut self, fail_on_plist_version: bool) -> Self {
+        self.fail_on_plist_version = fail_on_plist_version;
+        self
+    }
+
+    /// Passes extra arguments to codesign, such as `--deep`, before the app path.
+    ///
+    /// Fails if any of them are [`RESERVED_ARGS`], which would conflict with the arguments that are
+    /// already passed.
+    pub fn args(mut self, args: Vec<String>) -> Result<Self> {
+        for arg in &args {
+            check_arg(arg)?;
+        }
+        self.args = args;
+        Ok(self)
+    }
+
+    /// Whether entitlements are checked for suspicious keys, see [`Codesign::strict_parse`].
+    pub fn strict_parses(&self) -> bool {
+        self.strict_parse
+    }
+
+    /// Whether an unexpected plist version is an error, see [`Codesign::fail_on_plist_version`].
+    pub fn fails_on_plist_version(&self) -> bool {
+        self.fail_on_plist_version
+    }
+
+    /// Whether codesign is actually run.
+    pub fn executes(&self) -> bool {
+        self.execute
+    }
+
+    /// The path of the codesign binary.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Gets the entitlements of an app by running codesign.
+    ///
+    /// `app_path` can also be any other bundle, or a standalone Mach-O executable or dylib, which
+    /// codesign reads the same way.
+    ///
+    /// Returns `None` without running codesign if [`Codesign::execute`] is disabled.
+    pub fn get_entitlements(&self, app_path: &Path) -> Result<Option<plist::Value>> {
+        let mut command = self.command();
+        command
+            .arg("--xml")
+            .arg("--entitlements")
+            .arg("-")
+            .args(&self.args)
+            .arg(app_path);
+        let Some(output) = self.run(&mut command)? else {
+            return Ok(None);
+        };
+        let entitlements = entitlements_output(&output);
+        let source = app_path.display().to_string();
+        if self.strict_parse {
+            warn_suspicious_keys(entitlements, &source);
+        }
+        check_plist_version(entitlements, &source, self.fail_on_plist_version)?;
+        parse_codesign_output(entitlements).map(Some)
+    }
+
+    /// Re-signs an app with `identity`, such as `-` for ad-hoc signing, and the entitlements in
+    /// `entitlements_path`, replacing its existing signature.
+    ///
+    /// Returns `None` without signing if [`Codesign::execute`] is disabled.
+    pub fn sign(
+        &self,
+        app_path: &Path,
+        identity: &str,
+        entitlements_path: &Path,
+    ) -> Result<Option<()>> {
+        let mut command = Command::new(&self.path);
+        command
+            .arg("--force")
+            .arg("--sign")
+            .arg(identity)
+            .arg("--entitlements")
+            .arg(entitlements_path)
+            .arg(app_path);
+        Ok(self.run(&mut command)?.map(drop))
+    }
+
+    /// Checks an app's signature with `codesign --verify --strict`, such as after re-signing it
+    /// with [`Codesign::sign`].
+    ///
+    /// An invalid signature is a [`StripError::InvalidSignature`] holding what codesign printed.
+    /// Returns `None` without verifying if [`Codesign::execute`] is disabled.
+    pub fn verify(&self, app_path: &Path) -> Result<Option<()>> {
+        let mut command = Command::new(&self.path);
+        command.arg("--verify").arg("--strict").arg(app_path);
+        match self.run(&mut command) {
+            Err(StripError::CodesignFailed { stderr, .. }) => {
+                Err(StripError::InvalidSignature(stderr))
+            }
+            result => result.map(|output| output.map(drop)),
+        }
+    }
+
+    /// Like [`Codesign::get_entitlements`], but reuses the entitlements already read from the
+    /// same app in this process, unless the app's modification time has changed since.
+    ///
+    /// Only the modification time of `app_path` itself is checked, not of the files inside a
+    /// bundle. Failures aren't cached.
+    pub fn get_entitlements_cached(&self, app_path: &Path) -> Result<Option<plist::Value>> {
+        let Ok(modified) = fs::metadata(app_path).and_then(|metadata| metadata.modified()) else {
+            return self.get_entitlements(app_path);
+        };
+        let key = CacheKey {
+            codesign_path: self.path.clone(),
+            arch: self.arch.clone(),
+            args: self.args.clone(),
+            app_path: app_path.to_path_buf(),
+            modified,
+        };
+        let cache = || {
+            ENTITLEMENTS_CACHE
+                .lock()
+                .unwrap_or_else(PoisonError::into_inner)
+        };
+        if let Some(entitlements) = cache().get(&key) {
+            log::debug!("Using cached entitlements of {}", app_path.display());
+            return Ok(Some(entitlements.clone()));
+        }
+        let Some(entitlements) = self.get_entitlements(app_path)? else {
+            return Ok(None);
+        };
+        cache().insert(key, entitlements.clone());
+        Ok(Some(entitlements))
+    }
+
+    /// Gets who signed an app by running codesign, or `None` if [`Codesign::execute`] is
+    /// disabled.
+    pub fn get_signing_info(&self, app_path: &Path) -> Result<Option<SigningInfo>> {
+        let mut command = self.command();
+        command.arg("--verbose=2").args(&self.args).arg(app_path);
+        let Some(output) = self.run(&mut command)? else {
+            return Ok(None);
+        };
+        // codesign prints the signature information to stderr
+        Ok(Some(parse_signing_info(&codesign_stderr(output)?)))
+    }
+
+    /// Gets the architectures of an app's main executable, such as `["x86_64", "arm64"]` for a
+    /// universal binary, by running codesign, or `None` if [`Codesign::execute`] is disabled.
+    pub fn get_architectures(&self, app_path: &Path) -> Result<Option<Vec<String>>> {
+        let mut command = self.command();
+        command.arg("--verbose").args(&self.args).arg(app_path);
+        let Some(output) = self.run(&mut command)? else {
+            return Ok(None);
+        };
+        // codesign prints the signature information to stderr
+        parse_architectures(&codesign_stderr(output)?).map(Some)
+    }
+
+    fn command(&self) -> Command {
+        let mut command = Command::new(&self.path);
+        command.arg("--display");
+        if let Some(arch) = &self.arch {
+            command.arg("--arch").arg(arch);
+        }
+        command
+    }
+
+    /// Runs a codesign command, failing if it doesn't exit successfully, or returns `None` if
+    /// [`Codesign::execute`] is disabled.
+    fn run(&self, command: &mut Command) -> Result<Option<Output>> {
+        if self.print_command {
+            eprintln!("{}", shell_command(command));
+        }
+        if !self.execute {
+            return Ok(None);
+        }
+        log::debug!("Running {:?}", command);
+        let execute_error = |error: std::io::Error| {
+            if error.kind() == std::io::ErrorKind::NotFound {
+                StripError::CodesignNotFound {
+                    path: self.path.clone(),
+                }
+            } else {
+                StripError::io(
+                    format!("Failed to execute codesign at {}", self.path.display()),
+                    error,
+                )
+            }
+        };
+        let output = match self.timeout {
+            Some(timeout) => output_with_timeout(command, timeout)
+                .map_err(execute_error)?
+                .ok_or(StripError::CodesignTimedOut(timeout))?,
+            None => command.output().map_err(execute_error)?,
+        };
+        check_codesign_output(output).map(Some)
+    }
+}
+
+impl Default for Codesign {
+    fn default() -> Self {
+        Self::new(DEFAULT_CODESIGN_PATH)
+    }
+}
+
+/// Gets the entitlements of an app by running `codesign`.
+pub fn get_entitlements(codesign_path: &Path, app_path: &Path) -> Result<plist::Value> {
+    Codesign::new(codesign_path)
+        .get_entitlements(app_path)
+        .map(|entitlements| entitlements.expect("codesign is executed by default"))
+}
+
+/// Like [`get_entitlements`], but reuses the entitlements already read from the same app, see
+/// [`Codesign::get_entitlements_cached`].
+pub fn get_entitlements_cached(codesign_path: &Path, app_path: &Path) -> Result<plist::Value> {
+    Codesign::new(codesign_path)
+        .get_entitlements_cached(app_path)
+        .map(|entitlements| entitlements.expect("codesign is executed by default"))
+}
+
+/// Fails if an extra codesign argument is one of the [`RESERVED_ARGS`], including in the
+/// `--option=value` form.
+pub fn check_arg(arg: &str) -> Result<()> {
+    let option = arg.split_once('=').map_or(arg, |(option, _)| option);
+    if RESERVED_ARGS.contains(&option) {
+        return Err(StripError::ReservedArg(option.to_string()));
+    }
+    Ok(())
+}
+
+/// Formats a command as a line that can be pasted into a POSIX shell.
+fn shell_command(command: &Command) -> String {
+    std::iter::once(command.get_program())
+        .chain(command.get_args())
+        .map(shell_quote)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Quotes an argument for a POSIX shell, leaving it as is if it's made of characters that are
+/// safe unquoted.
+fn shell_quote(arg: &OsStr) -> String {
+    let Some(arg) = arg.to_str() else {
+        return escape_quote(arg);
+    };
+    if !arg.is_empty()
+        && arg
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "%+,-./:=@_".contains(c))
+    {
+        arg.to_string()
+    } else {
+        format!("'{}'", arg.replace('\'', r"'\''"))
+    }
+}
+
+/// Quotes an argument that isn't valid UTF-8 as `$'...'`, with the invalid bytes as `\xHH`
+/// escapes, so pasting the command passes the same bytes rather than replacement characters.
+#[cfg(unix)]
+fn escape_quote(arg: &OsStr) -> String {
+    use std::{fmt::Write, os::unix::ffi::OsStrExt};
+
+    let mut quoted = String::from("$'");
+    for chunk in arg.as_bytes().utf8_chunks() {
+        for c in chunk.valid().chars() {
+            match c {
+                '\'' | '\\' => {
+                    quoted.push('\\');
+                    quoted.push(c);
+                }
+                c if c.is_ascii_control() => {
+                    write!(quoted, "\\x{:02x}", c as u8).expect("writing to a String can't fail")
+                }
+                c => quoted.push(c),
+            }
+        }
+        for byte in chunk.invalid() {
+            write!(quoted, "\\x{byte:02x}").expect("writing to a String can't fail");
+        }
+    }
+    quoted.push('\'');
+    quoted
+}
+
+/// Paths are always Unicode on other platforms, so this is never reached with real paths.
+#[cfg(not(unix))]
+fn escape_quote(arg: &OsStr) -> String {
+    format!("'{}'", arg.to_string_lossy().replace('\'', r"'\''"))
+}
+
+/// Like [`Command::output`], but kills the child and returns `None` if it runs for longer than
+/// `timeout`.
+fn output_with_timeout(
+    command: &mut Command,
+    timeout: Duration,
+) -> std::io::Result<Option<Output>> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()?;
+    // Read the pipes on other threads so the child can't block on a full pipe while we wait
+    let stdout = read_pipe(child.stdout.take());
+    let stderr = read_pipe(child.stderr.take());
+
+    let Some(status) = wait_with_deadline(&mut child, Instant::now() + timeout)? else {
+        log::debug!("Killing codesign after {:?}", timeout);
+        child.kill()?;
+        child.wait()?;
+        return Ok(None);
+    };
+    Ok(Some(Output {
+        status,
+        stdout: stdout.join().expect("stdout reader panicked")?,
+        stderr: stderr.join().expect("stderr reader panicked")?,
+    }))
+}
+
+fn read_pipe(
+    pipe: Option<impl Read + Send + 'static>,
+) -> thread::JoinHandle<std::io::Result<Vec<u8>>> {
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        if let Some(mut pipe) = pipe {
+            pipe.read_to_end(&mut bytes)?;
+        }
+        Ok(bytes)
+    })
+}
+
+fn wait_with_deadline(
+    child: &mut Child,
+    deadline: Instant,
+) -> std::io::Result<Option<std::process::ExitStatus>> {
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(Some(status));
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Ok(None);
+        }
+        thread::sleep(POLL_INTERVAL.min(deadline - now));
+    }
+}
+
+fn check_codesign_output(output: Output) -> Result<Output> {
+    log::debug!(
+        "codesign exited with {}, {} bytes of stdout",
+        output.status,
+        output.stdout.len()
+    );
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        if stderr.contains(NOT_SIGNED_MESSAGE) {
+            return Err(StripError::NotSigned);
+        }
+        return Err(StripError::CodesignFailed {
+            status: output.status,
+            stderr,
+        });
+    }
+
+    Ok(output)
+}
+
+/// Returns what codesign printed to stderr, which is where it prints signature information.
+fn codesign_stderr(output: Output) -> Result<String> {
+    String::from_utf8(output.stderr).map_err(|_| {
+        StripError::UnexpectedCodesignOutput("codesign stderr is not valid UTF-8".to_string())
+    })
+}
+
+/// Parses the architectures from the `Format=` line of `codesign --display --verbose`, such as
+/// `Format=app bundle with Mach-O universal (x86_64 arm64)`.
+fn parse_architectures(info: &str) -> Result<Vec<String>> {
+    let format = info
+        .lines()
+        .find_map(|line| line.strip_prefix("Format="))
+        .ok_or_else(|| {
+            StripError::UnexpectedCodesignOutput(
+                "codesign didn't report the format of the app".to_string(),
+            )
+        })?;
+    let architectures = format
+        .rsplit_once('(')
+        .and_then(|(_, architectures)| architectures.strip_suffix(')'))
+        .ok_or_else(|| {
+            StripError::UnexpectedCodesignOutput(format!(
+                "codesign didn't report any architectures in {:?}",
+                format
+            ))
+        })?;
+    Ok(architectures.split_whitespace().map(String::from).collect())
+}
+
+/// Parses the `Identifier=`, `TeamIdentifier=` and `Authority=` lines of
+/// `codesign --display --verbose=2`, ignoring any other lines.
+fn parse_signing_info(info: &str) -> SigningInfo {
+    let mut signing_info = SigningInfo::default();
+    for (key, value) in info.lines().filter_map(|line| line.split_once('=')) {
+        let value = value.trim();
+        if value.is_empty() {
+            continue;
+        }
+        match key.trim() {
+            "Identifier" => signing_info.identifier = Some(value.to_string()),
+            // Ad-hoc signatures have no team
+            "TeamIdentifier" if value != "not set" => {
+                signing_info.team_identifier = Some(value.to_string());
+            }
+            "Authority" => signing_info.authorities.push(value.to_string()),
+            _ => {}
+        }
+    }
+    signing_info
+}
+
+/// Returns the output codesign printed the entitlements plist to. That's stdout, but some codesign
+/// versions print it to stderr after the `Executable=` line, so stderr is used when stdout is
+/// empty and stderr contains a plist.
+fn entitlements_output(output: &Output) -> &[u8] {
+    if !output.stdout.iter().all(u8::is_ascii_whitespace) {
+        log::debug!("Reading entitlements from codesign's stdout");
+        return &output.stdout;
+    }
+    match find_plist(&output.stderr) {
+        Some(plist) => {
+            log::debug!("codesign's stdout is empty, reading entitlements from its stderr");
+            plist
+        }
+        None => &output.stdout,
+    }
+}
+
+/// Returns the XML or binary plist in `bytes`, without anything printed before or after it.
+fn find_plist(bytes: &[u8]) -> Option<&[u8]> {
+    let find = |needle: &[u8]| {
+        bytes
+            .windows(needle.len())
+            .position(|window| window == needle)
+    };
+    if let Some(start) = find(b"bplist00") {
+        return Some(&bytes[start..]);
+    }
+    let start = find(b"<?xml").or_else(|| find(b"<plist"))?;
+    let end_tag = b"</plist>";
+    let end = bytes
+        .windows(end_tag.len())
+        .rposition(|window| window == end_tag)
+        .map_or(bytes.len(), |end| end + end_tag.len());
+    (start < end).then(|| &bytes[start..end])
+}
+
+/// Parses the entitlements plist printed by codesign. Apps without entitlements produce no output,
+/// which is treated as an empty dictionary.
+fn parse_codesign_output(stdout: &[u8]) -> Result<plist::Value> {
+    if stdout.iter().all(u8::is_ascii_whitespace) {
+        return Ok(plist::Value::Dictionary(plist::Dictionary::new()));
+    }
+    let entitlements = plist::from_bytes(stdout).map_err(|error| {
+        StripError::parse_with(
+            "Failed to parse entitlements plist from codesign output",
+            error,
+        )
+    })?;
+    Ok(entitlements)
+}
+
+/// A line of codesign's text entitlements format, such as `[Key] com.apple.security.app-sandbox`.
+struct TextLine<'a> {
+    indent: usize,
+    content: &'a str,
+}
+
+/// Parses the entitlements codesign prints when `--xml` isn't given, which look like:
+///
+/// ```text
+/// Executable=/Applications/Example.app/Contents/MacOS/Example
+/// [Dict]
+///     [Key] com.apple.security.app-sandbox
+///     [Value]
+///         [Bool] true
+/// ```
+///
+/// Empty output, from apps without entitlements, is treated as an empty dictionary.
+pub fn parse_entitlements_text(text: &str) -> Result<plist::Value> {
+    let mut text_lines = Vec::new();
+    for line in text.lines() {
+        let content = line.trim_start();
+        if content.is_empty() || content.starts_with("Executable=") {
+            continue;
+        }
+        if !content.starts_with('[') {
+            return Err(StripError::parse(format!(
+                "Invalid entitlements line {:?}",
+                content
+            )));
+        }
+        text_lines.push(TextLine {
+            indent: line.len() - content.len(),
+            content: content.trim_end(),
+        });
+    }
+    let mut lines = text_lines.into_iter().peekable();
+    let Some(line) = lines.next() else {
+        return Ok(plist::Value::Dictionary(plist::Dictionary::new()));
+    };
+    let entitlements = parse_text_value(line, &mut lines)?;
+    if let Some(line) = lines.next() {
+        return Err(StripError::parse(format!(
+            "Unexpected {:?} after the entitlements",
+            line.content
+        )));
+    }
+    if entitlements.as_dictionary().is_none() {
+        return Err(StripError::not_a_dictionary(
+            "Entitlements root",
+            &entitlements,
+        ));
+    }
+    Ok(entitlements)
+}
+
+/// Parses the value starting at `line`, consuming the more indented lines after it that make up
+/// its contents.
+fn parse_text_value<'a>(
+    line: TextLine<'a>,
+    lines: &mut Peekable<impl Iterator<Item = TextLine<'a>>>,
+) -> Result<plist::Value> {
+    let (tag, rest) = line
+        .content
+        .strip_prefix('[')
+        .and_then(|content| content.split_once(']'))
+        .ok_or_else(|| {
+            StripError::parse(format!("Invalid entitlements line {:?}", line.content))
+        })?;
+    let rest = rest.strip_prefix(' ').unwrap_or(rest);
+    let is_child = |next: &TextLine| next.indent > line.indent;
+    match tag {
+        "Dict" => {
+            let mut dictionary = plist::Dictionary::new();
+            while let Some(key_line) = lines.next_if(is_child) {
+                let key = key_line.content.strip_prefix("[Key] ").ok_or_else(|| {
+                    StripError::parse(format!("Expected a key, found {:?}", key_line.content))
+                })?;
+                let value_line = lines
+                    .next_if(|next| is_child(next) && next.content == "[Value]")
+                    .ok_or_else(|| StripError::parse(format!("{} has no value", key)))?;
+                let value = lines
+                    .next_if(|next| next.indent > value_line.indent)
+                    .ok_or_else(|| StripError::parse(format!("{} has no value", key)))?;
+                dictionary.insert(key.to_string(), parse_text_value(value, lines)?);
+            }
+            Ok(plist::Value::Dictionary(dictionary))
+        }
+        "Array" => {
+            let mut array = Vec::new();
+            while let Some(item) = lines.next_if(is_child) {
+                array.push(parse_text_value(item, lines)?);
+            }
+            Ok(plist::Value::Array(array))
+        }
+        "Bool" => match rest {
+            "true" => Ok(plist::Value::Boolean(true)),
+            "false" => Ok(plist::Value::Boolean(false)),
+            _ => Err(StripError::parse(format!("Invalid boolean {:?}", rest))),
+        },
+        "String" => Ok(plist::Value::String(rest.to_string())),
+        "Number" | "Integer" => rest
+            .parse::<i64>()
+            .map(plist::Value::from)
+            .map_err(|error| StripError::parse_with(format!("Invalid number {:?}", rest), error)),
+        _ => Err(StripError::parse(format!(
+            "Unsupported entitlements value type {:?}",
+            tag
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_entitlements_text() {
+        let entitlements = parse_entitlements_text(
+            "Executable=/Applications/Example.app/Contents/MacOS/Example
+[Dict]
+	[Key] com.apple.security.app-sandbox
+	[Value]
+		[Bool] true
+	[Key] com.apple.security.application-groups
+	[Value]
+		[Array]
+			[String] group.com.example.a
+			[String] group.com.example.b
+	[Key] com.apple.developer.icloud-container-environment
+	[Value]
+		[String] Production
+	[Key] com.example.number
+	[Value]
+		[Number] 3
+",
+        )
+        .unwrap();
+        let mut expected = plist::Dictionary::new();
+        expected.insert("com.apple.security.app-sandbox".into(), true.into());
+        expected.insert(
+            "com.apple.security.application-groups".into(),
+            plist::Value::Array(vec![
+                "group.com.example.a".into(),
+                "group.com.example.b".into(),
+            ]),
+        );
+        expected.insert(
+            "com.apple.developer.icloud-container-environment".into(),
+            "Production".into(),
+        );
+        expected.insert("com.example.number".into(), 3.into());
+        assert_eq!(entitlements, plist::Value::Dictionary(expected));
+    }
+
+    #[test]
+    fn test_parse_entitlements_text_empty() {
+        assert_eq!(
+            parse_entitlements_text(
+                "Executable=/Applications/Example.app/Contents/MacOS/Example\n"
+            )
+            .unwrap(),
+            plist::Value::Dictionary(plist::Dictionary::new())
+        );
+    }
+
+    #[test]
+    fn test_parse_entitlements_text_missing_value() {
+        let error = parse_entitlements_text("[Dict]\n\t[Key] com.apple.security.app-sandbox\n")
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "com.apple.security.app-sandbox has no value"
+        );
+    }
+
+    #[test]
+    fn test_get_entitlements_missing_codesign() {
+        let error = get_entitlements(
+            Path::new("/nonexistent/codesign"),
+            Path::new("/Applications/Example.app"),
+        )
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "codesign not found at /nonexistent/codesign"
+        );
+        assert!(matches!(error, StripError::CodesignNotFound { .. }));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_get_entitlements_timeout() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let codesign_path = std::env::temp_dir().join(format!(
+            "provisioned-entitlement-stripper-slow-codesign-{}",
+            std::process::id()
+        ));
+        std::fs::write(&codesign_path, "#!/bin/sh\nexec sleep 10\n").unwrap();
+        std::fs::set_permissions(&codesign_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let start = Instant::now();
+        let result = Codesign::new(&codesign_path)
+            .timeout(Some(Duration::from_millis(100)))
+            .get_entitlements(Path::new("/Applications/Example.app"));
+        std::fs::remove_file(&codesign_path).unwrap();
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "codesign timed out after 100ms"
+        );
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_get_entitlements_cached() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!(
+            "provisioned-entitlement-stripper-cached-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("Example.app")).unwrap();
+        let codesign_path = dir.join("codesign");
+        fs::write(
+            &codesign_path,
+            format!(
+                "#!/bin/sh\necho run >> '{}'\necho '<plist version=\"1.0\"><dict/></plist>'\n",
+                dir.join("runs").display()
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&codesign_path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let app_path = dir.join("Example.app");
+        let first = get_entitlements_cached(&codesign_path, &app_path).unwrap();
+        let second = get_entitlements_cached(&codesign_path, &app_path).unwrap();
+        let runs = fs::read_to_string(dir.join("runs")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(first, second);
+        assert_eq!(runs, "run\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_get_entitlements_failed() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let codesign_path = std::env::temp_dir().join(format!(
+            "provisioned-entitlement-stripper-failing-codesign-{}",
+            std::process::id()
+        ));
+        fs::write(
+            &codesign_path,
+            "#!/bin/sh\necho 'no such file' >&2\nexit 1\n",
+        )
+        .unwrap();
+        fs::set_permissions(&codesign_path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let result = get_entitlements(&codesign_path, Path::new("/Applications/Example.app"));
+        fs::remove_file(&codesign_path).unwrap();
+        match result.unwrap_err() {
+            StripError::CodesignFailed { status, stderr } => {
+                assert_eq!(status.code(), Some(1));
+                assert_eq!(stderr, "no such file\n");
+            }
+            error => panic!("unexpected error: {error}"),
+        }
+    }
+
+    #[test]
+    fn test_shell_command() {
+        let mut command = Command::new("/usr/bin/codesign");
+        command
+            .args(["--display", "--entitlements", "-"])
+            .arg("/Applications/My App.app")
+            .arg("it's")
+            .arg("");
+        assert_eq!(
+            shell_command(&command),
+            r#"/usr/bin/codesign --display --entitlements - '/Applications/My App.app' 'it'\''s' ''"#
+        );
+    }
+
+    #[test]
+    fn test_shell_command_unicode() {
+        let mut command = Command::new("/usr/bin/codesign");
+        command.arg("/Users/me/My Apps/Café.app").arg("日本語.app");
+        assert_eq!(
+            shell_command(&command),
+            "/usr/bin/codesign '/Users/me/My Apps/Café.app' '日本語.app'"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_shell_command_non_utf8() {
+        use std::os::unix::ffi::OsStrExt;
+
+        let mut command = Command::new("/usr/bin/codesign");
+        command.arg(OsStr::from_bytes(b"/Apps/Caf\xe9 it's\\\n.app"));
+        assert_eq!(
+            shell_command(&command),
+            r"/usr/bin/codesign $'/Apps/Caf\xe9 it\'s\\\x0a.app'"
+        );
+    }
+
+    #[test]
+    fn test_get_entitlements_no_exec() {
+        let entitlements = Codesign::new("/nonexistent/codesign")
+            .execute(false)
+            .get_entitlements(Path::new("/Applications/Example.app"))
+            .unwrap();
+        assert_eq!(entitlements, None);
+    }
+
+    #[test]
+    fn test_check_arg() {
+        assert!(check_arg("--deep").is_ok());
+        assert!(check_arg("-R=anchor apple").is_ok());
+        assert_eq!(
+            check_arg("--xml").unwrap_err().to_string(),
+            "--xml is already passed to codesign"
+        );
+        assert_eq!(
+            check_arg("--arch=arm64").unwrap_err().to_string(),
+            "--arch is already passed to codesign"
+        );
+    }
+
+    #[test]
+    fn test_parse_architectures() {
+        assert_eq!(
+            parse_architectures("Executable=/Applications/Example.app/Contents/MacOS/Example\nIdentifier=com.example.example\nFormat=app bundle with Mach-O universal (x86_64 arm64)\nCodeDirectory v=20500 size=1234 flags=0x10000(runtime) hashes=28+7 location=embedded\n").unwrap(),
+            ["x86_64", "arm64"]
+        );
+        assert_eq!(
+            parse_architectures("Format=app bundle with Mach-O thin (arm64)\n").unwrap(),
+            ["arm64"]
+        );
+        assert!(parse_architectures("Format=bundle\n").is_err());
+        assert!(parse_architectures("Identifier=com.example.example\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_signing_info() {
+        assert_eq!(
+            parse_signing_info(
+                "Executable=/Applications/Example.app/Contents/MacOS/Example\nIdentifier=com.example.example\nFormat=app bundle with Mach-O thin (arm64)\nAuthority=Apple Development: Example (BBBBBBBBBB)\nAuthority=Apple Worldwide Developer Relations Certification Authority\nAuthority=Apple Root CA\nSigned Time=1 Jan 2025 at 00:00:00\nTeamIdentifier=AAAAAAAAAA\n"
+            ),
+            SigningInfo {
+                identifier: Some("com.example.example".to_string()),
+                team_identifier: Some("AAAAAAAAAA".to_string()),
+                authorities: vec![
+                    "Apple Development: Example (BBBBBBBBBB)".to_string(),
+                    "Apple Worldwide Developer Relations Certification Authority".to_string(),
+                    "Apple Root CA".to_string(),
+                ],
+            }
+        );
+        assert_eq!(
+            parse_signing_info(
+                "Identifier=com.example.example\nSignature=adhoc\nTeamIdentifier=not set\n"
+            ),
+            SigningInfo {
+                identifier: Some("com.example.example".to_string()),
+                ..SigningInfo::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_codesign_output_empty() {
+        let empty = plist::Value::Dictionary(plist::Dictionary::new());
+        assert_eq!(parse_codesign_output(b"").unwrap(), empty);
+        assert_eq!(parse_codesign_output(b"\n").unwrap(), empty);
+    }
+
+    #[test]
+    fn test_parse_codesign_output_malformed() {
+        let error = parse_codesign_output(b"not a plist").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Failed to parse entitlements plist from codesign output"
+        );
+
+        let array = parse_codesign_output(br#"<?xml version="1.0" encoding="UTF-8"?><plist version="1.0"><array><string>a</string></array></plist>"#).unwrap();
+        assert!(crate::get_provisioned_entitlements(&array, &[]).is_err());
+    }
+
+    #[cfg(unix)]
+    fn get_entitlements_with_script(name: &str, script: &str) -> Result<plist::Value> {
+        use std::os::unix::fs::PermissionsExt;
+
+        let codesign_path = std::env::temp_dir().join(format!(
+            "provisioned-entitlement-stripper-{}-codesign-{}",
+            name,
+            std::process::id()
+        ));
+        fs::write(&codesign_path, script).unwrap();
+        fs::set_permissions(&codesign_path, fs::Permissions::from_mode(0o755)).unwrap();
+        let result = get_entitlements(&codesign_path, Path::new("/Applications/Example.app"));
+        fs::remove_file(&codesign_path).unwrap();
+        result
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_get_entitlements_stdout_or_stderr() {
+        const PLIST: &str = r#"<?xml version="1.0" encoding="UTF-8"?><plist version="1.0"><dict><key>com.apple.security.device.camera</key><true/></dict></plist>"#;
+        let mut expected = plist::Dictionary::new();
+        expected.insert(
+            "com.apple.security.device.camera".to_string(),
+            plist::Value::Boolean(true),
+        );
+        let expected = plist::Value::Dictionary(expected);
+
+        let stdout = get_entitlements_with_script(
+            "stdout",
+            &format!(
+                "#!/bin/sh\necho 'Executable=/Applications/Example.app/Contents/MacOS/Example' >&2\necho '{PLIST}'\n"
+            ),
+        );
+        assert_eq!(stdout.unwrap(), expected);
+
+        let stderr = get_entitlements_with_script(
+            "stderr",
+            &format!(
+                "#!/bin/sh\n(echo 'Executable=/Applications/Example.app/Contents/MacOS/Example'; echo '{PLIST}') >&2\n"
+            ),
+        );
+        assert_eq!(stderr.unwrap(), expected);
+
+        let neither = get_entitlements_with_script(
+            "neither",
+            "#!/bin/sh\necho 'Executable=/Applications/Example.app/Contents/MacOS/Example' >&2\n",
+        );
+        assert_eq!(
+            neither.unwrap(),
+            plist::Value::Dictionary(plist::Dictionary::new())
+        );
+    }
+
+    #[test]
+    fn test_find_plist() {
+        assert_eq!(
+            find_plist(b"Executable=/a\n<?xml version=\"1.0\"?><plist><dict/></plist>\nmore\n"),
+            Some(b"<?xml version=\"1.0\"?><plist><dict/></plist>".as_slice())
+        );
+        assert_eq!(
+            find_plist(b"<plist><dict/></plist>"),
+            Some(b"<plist><dict/></plist>".as_slice())
+        );
+        assert_eq!(
+            find_plist(b"Executable=/a\nbplist00\x00\x01"),
+            Some(b"bplist00\x00\x01".as_slice())
+        );
+        assert_eq!(find_plist(b"Executable=/a\n"), None);
+    }
+}
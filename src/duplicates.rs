@@ -0,0 +1,192 @@
+use std::collections::HashMap;
+
+use crate::error::{Result, StripError};
+use quick_xml::events::Event;
+
+/// A dictionary key that is the same as, or only differs in case or surrounding whitespace from,
+/// an earlier key in the same dictionary.
+///
+/// The plist parser silently collapses exact duplicates, keeping the last value at the position of
+/// the first key, so only one of them is reported by the parsed dictionary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SuspiciousKey {
+    pub key: String,
+    pub previous: String,
+}
+
+impl SuspiciousKey {
+    /// Whether the key is exactly the same as the earlier key.
+    pub fn is_duplicate(&self) -> bool {
+        self.key == self.previous
+    }
+}
+
+impl std::fmt::Display for SuspiciousKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_duplicate() {
+            write!(f, "duplicate key {:?}", self.key)
+        } else {
+            write!(
+                f,
+                "key {:?} only differs in case or whitespace from {:?}",
+                self.key, self.previous
+            )
+        }
+    }
+}
+
+/// Scans an XML plist for [`SuspiciousKey`]s, which can't be seen once it's been parsed. Binary
+/// plists can't have them, so anything that doesn't look like XML has none.
+pub fn find_suspicious_keys(xml: &[u8]) -> Result<Vec<SuspiciousKey>> {
+    if !xml.trim_ascii_start().starts_with(b"<") {
+        return Ok(Vec::new());
+    }
+    let mut reader = quick_xml::Reader::from_reader(xml);
+    let mut buf = Vec::new();
+    // The keys of each dictionary being read, innermost last, by their normalized form
+    let mut dictionaries: Vec<HashMap<String, String>> = Vec::new();
+    let mut key: Option<String> = None;
+    let mut suspicious_keys = Vec::new();
+    loop {
+        let event = reader
+            .read_event_into(&mut buf)
+            .map_err(|error| StripError::parse_with("Failed to scan plist XML", error))?;
+        let finished_key = match event {
+            Event::Start(start) if start.name().as_ref() == b"dict" => {
+                dictionaries.push(HashMap::new());
+                None
+            }
+            Event::End(end) if end.name().as_ref() == b"dict" => {
+                dictionaries.pop();
+                None
+            }
+            Event::Start(start) if start.name().as_ref() == b"key" => {
+                key = Some(String::new());
+                None
+            }
+            Event::Empty(empty) if empty.name().as_ref() == b"key" => Some(String::new()),
+            Event::End(end) if end.name().as_ref() == b"key" => key.take(),
+            // Keep references escaped so the whole key can be unescaped at once
+            Event::Text(text) if key.is_some() => {
+                let text = text
+                    .decode()
+                    .map_err(|error| StripError::parse_with("Failed to scan plist XML", error))?;
+                key.as_mut().unwrap().push_str(&text);
+                None
+            }
+            Event::GeneralRef(reference) if key.is_some() => {
+                let reference = reference
+                    .decode()
+                    .map_err(|error| StripError::parse_with("Failed to scan plist XML", error))?;
+                key.as_mut().unwrap().push_str(&format!("&{};", reference));
+                None
+            }
+            Event::Eof => break,
+            _ => None,
+        };
+        if let Some(raw_key) = finished_key
+            && let Some(keys) = dictionaries.last_mut()
+        {
+            let key = quick_xml::escape::unescape(&raw_key)
+                .map_err(|error| StripError::parse_with("Failed to scan plist XML", error))?
+                .into_owned();
+            match keys.get(&normalize_key(&key)) {
+                Some(previous) => suspicious_keys.push(SuspiciousKey {
+                    key,
+                    previous: previous.clone(),
+                }),
+                None => {
+                    keys.insert(normalize_key(&key), key);
+                }
+            }
+        }
+        buf.clear();
+    }
+    Ok(suspicious_keys)
+}
+
+/// Logs a warning for each [`SuspiciousKey`] in an XML plist read from `source`.
+pub fn warn_suspicious_keys(xml: &[u8], source: &str) {
+    match find_suspicious_keys(xml) {
+        Ok(suspicious_keys) => {
+            for suspicious_key in suspicious_keys {
+                log::warn!("Entitlements from {} have a {}", source, suspicious_key);
+            }
+        }
+        Err(error) => log::warn!("Failed to scan entitlements from {}: {:#}", source, error),
+    }
+}
+
+fn normalize_key(key: &str) -> String {
+    key.trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_suspicious_keys() {
+        let xml = br#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+	<key>com.apple.security.app-sandbox</key>
+	<true/>
+	<key>com.apple.security.application-groups</key>
+	<array>
+		<dict>
+			<key>com.apple.security.app-sandbox</key>
+			<true/>
+		</dict>
+	</array>
+	<key>com.apple.security.App-Sandbox </key>
+	<true/>
+	<key>a&amp;b</key>
+	<false/>
+	<key>a&amp;b</key>
+	<true/>
+</dict>
+</plist>"#;
+        assert_eq!(
+            find_suspicious_keys(xml).unwrap(),
+            [
+                SuspiciousKey {
+                    key: "com.apple.security.App-Sandbox ".to_string(),
+                    previous: "com.apple.security.app-sandbox".to_string(),
+                },
+                SuspiciousKey {
+                    key: "a&b".to_string(),
+                    previous: "a&b".to_string(),
+                },
+            ]
+        );
+
+        // The parser keeps the last value of a duplicate key, at the position of the first
+        let entitlements: plist::Value = plist::from_bytes(xml).unwrap();
+        let keys: Vec<&String> = entitlements.as_dictionary().unwrap().keys().collect();
+        assert_eq!(
+            keys,
+            [
+                "com.apple.security.app-sandbox",
+                "com.apple.security.application-groups",
+                "com.apple.security.App-Sandbox ",
+                "a&b"
+            ]
+        );
+        assert_eq!(
+            entitlements.as_dictionary().unwrap()["a&b"],
+            plist::Value::Boolean(true)
+        );
+    }
+
+    #[test]
+    fn test_find_suspicious_keys_binary() {
+        let mut binary = Vec::new();
+        plist::to_writer_binary(
+            &mut binary,
+            &plist::Value::Dictionary(plist::Dictionary::new()),
+        )
+        .unwrap();
+        assert!(find_suspicious_keys(&binary).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,150 @@
+use std::{path::PathBuf, process::ExitStatus, time::Duration};
+
+/// A `Result` with a [`StripError`].
+pub type Result<T, E = StripError> = std::result::Result<T, E>;
+
+/// The ways the functions in this crate can fail.
+#[derive(Debug, thiserror::Error)]
+#[non_exhaustive]
+pub enum StripError {
+    /// The codesign binary doesn't exist, such as when not running on macOS.
+    #[error("codesign not found at {}", path.display())]
+    CodesignNotFound { path: PathBuf },
+
+    /// codesign exited unsuccessfully, other than for [`StripError::NotSigned`].
+    #[error("codesign failed with status {status}, stderr: {stderr}")]
+    CodesignFailed { status: ExitStatus, stderr: String },
+
+    /// codesign reported that the app isn't signed, so it has no entitlements to read.
+    #[error(
+        "The app is not signed, sign it first, such as with codesign --sign - --entitlements \
+         <ENTITLEMENTS> <APP>"
+    )]
+    NotSigned,
+
+    /// `codesign --verify` found the signature to be invalid. Holds what codesign printed.
+    #[error("The signature is invalid: {}", .0.trim_end())]
+    InvalidSignature(String),
+
+    /// codesign ran for longer than [`Codesign::timeout`](crate::codesign::Codesign::timeout)
+    /// and was killed.
+    #[error("codesign timed out after {0:?}")]
+    CodesignTimedOut(Duration),
+
+    /// An extra codesign argument is one of the
+    /// [`RESERVED_ARGS`](crate::codesign::RESERVED_ARGS).
+    #[error("{0} is already passed to codesign")]
+    ReservedArg(String),
+
+    /// codesign printed something that couldn't be understood.
+    #[error("{0}")]
+    UnexpectedCodesignOutput(String),
+
+    /// A plist, or entitlements in codesign's text format, couldn't be parsed.
+    #[error("{message}")]
+    PlistParse {
+        message: String,
+        #[source]
+        source: Option<Box<dyn std::error::Error + Send + Sync>>,
+    },
+
+    /// An XML plist declares a version other than
+    /// [`PLIST_VERSION`](crate::PLIST_VERSION), with
+    /// [`Codesign::fail_on_plist_version`](crate::codesign::Codesign::fail_on_plist_version).
+    #[error("Entitlements from {input} are plist version {version:?}, expected 1.0")]
+    UnexpectedPlistVersion { input: String, version: String },
+
+    /// Entitlements, or another value that has to be a dictionary, aren't one. `name` is what the
+    /// value is, such as `Entitlements root`, and `found` is its type from
+    /// [`value_type_name`](crate::value_type_name).
+    #[error("{name} is {} {found}, expected Dictionary", article(found))]
+    NotADictionary { name: String, found: &'static str },
+
+    /// An entitlement that has to be an array isn't one.
+    #[error("{0} is not an array")]
+    NotAnArray(String),
+
+    /// A macOS version couldn't be parsed.
+    #[error("{0:?} is not a macOS version, expected one like 13 or 10.15")]
+    InvalidVersion(String),
+
+    /// A list of entitlement keys is empty or malformed.
+    #[error("{message}")]
+    InvalidEntitlementList {
+        message: String,
+        #[source]
+        source: Option<serde_json::Error>,
+    },
+
+    /// An `.ipa` couldn't be read or doesn't contain exactly one app.
+    #[error("{message}")]
+    InvalidIpa {
+        message: String,
+        #[source]
+        source: Option<zip::result::ZipError>,
+    },
+
+    /// The pkgutil binary doesn't exist, such as when not running on macOS.
+    #[error("pkgutil not found at {}", path.display())]
+    PkgutilNotFound { path: PathBuf },
+
+    /// A `.pkg` couldn't be expanded or doesn't contain a single primary app.
+    #[error("{0}")]
+    InvalidPkg(String),
+
+    /// Reading or writing a file, or running codesign or pkgutil, failed.
+    #[error("{message}")]
+    Io {
+        message: String,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+impl StripError {
+    pub(crate) fn io(message: impl Into<String>, source: std::io::Error) -> Self {
+        StripError::Io {
+            message: message.into(),
+            source,
+        }
+    }
+
+    pub(crate) fn parse(message: impl Into<String>) -> Self {
+        StripError::PlistParse {
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    pub(crate) fn parse_with(
+        message: impl Into<String>,
+        source: impl std::error::Error + Send + Sync + 'static,
+    ) -> Self {
+        StripError::PlistParse {
+            message: message.into(),
+            source: Some(Box::new(source)),
+        }
+    }
+
+    pub(crate) fn not_a_dictionary(name: impl Into<String>, value: &plist::Value) -> Self {
+        StripError::NotADictionary {
+            name: name.into(),
+            found: crate::value_type_name(value),
+        }
+    }
+
+    pub(crate) fn ipa(message: impl Into<String>) -> Self {
+        StripError::InvalidIpa {
+            message: message.into(),
+            source: None,
+        }
+    }
+}
+
+fn article(word: &str) -> &'static str {
+    if word.starts_with(['A', 'E', 'I', 'O', 'U']) {
+        "an"
+    } else {
+        "a"
+    }
+}
@@ -0,0 +1,133 @@
+use std::{
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::error::{Result, StripError};
+
+/// Returns whether a path looks like an iOS, tvOS or watchOS app archive.
+pub fn is_ipa(path: &Path) -> bool {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("ipa"))
+}
+
+/// An app extracted from an `.ipa` into a temporary directory, which is removed when this is
+/// dropped.
+#[derive(Debug)]
+pub struct ExtractedApp {
+    temp_dir: PathBuf,
+    app_path: PathBuf,
+}
+
+impl ExtractedApp {
+    /// The path of the extracted `Payload/*.app` bundle.
+    pub fn app_path(&self) -> &Path {
+        &self.app_path
+    }
+}
+
+impl Drop for ExtractedApp {
+    fn drop(&mut self) {
+        if let Err(error) = fs::remove_dir_all(&self.temp_dir) {
+            log::warn!(
+                "Failed to remove temporary directory {}: {}",
+                self.temp_dir.display(),
+                error
+            );
+        }
+    }
+}
+
+/// Extracts an `.ipa` to a temporary directory and locates the app inside its `Payload`
+/// directory.
+pub fn extract_ipa(ipa_path: &Path) -> Result<ExtractedApp> {
+    static EXTRACTED: AtomicUsize = AtomicUsize::new(0);
+
+    let file =
+        fs::File::open(ipa_path).map_err(|error| StripError::io("Failed to open ipa", error))?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|error| StripError::InvalidIpa {
+        message: "Failed to read ipa".to_string(),
+        source: Some(error),
+    })?;
+    let temp_dir = std::env::temp_dir().join(format!(
+        "provisioned-entitlement-stripper-ipa-{}-{}",
+        std::process::id(),
+        EXTRACTED.fetch_add(1, Ordering::Relaxed)
+    ));
+    log::debug!(
+        "Extracting {} to {}",
+        ipa_path.display(),
+        temp_dir.display()
+    );
+    // Construct this before extracting so the directory is cleaned up if anything fails
+    let mut extracted = ExtractedApp {
+        temp_dir,
+        app_path: PathBuf::new(),
+    };
+    archive
+        .extract(&extracted.temp_dir)
+        .map_err(|error| StripError::InvalidIpa {
+            message: "Failed to extract ipa".to_string(),
+            source: Some(error),
+        })?;
+    extracted.app_path = find_payload_app(&extracted.temp_dir.join("Payload"))?;
+    Ok(extracted)
+}
+
+fn find_payload_app(payload_dir: &Path) -> Result<PathBuf> {
+    let mut apps = Vec::new();
+    let entries = fs::read_dir(payload_dir).map_err(|error| StripError::InvalidIpa {
+        message: "ipa has no Payload directory".to_string(),
+        source: Some(error.into()),
+    })?;
+    for entry in entries {
+        let path = entry
+            .map_err(|error| StripError::io("Failed to read Payload directory", error))?
+            .path();
+        if path.is_dir() && path.extension() == Some(OsStr::new("app")) {
+            apps.push(path);
+        }
+    }
+    match <[PathBuf; 1]>::try_from(apps) {
+        Ok([app]) => Ok(app),
+        Err(apps) if apps.is_empty() => {
+            Err(StripError::ipa("ipa has no app in its Payload directory"))
+        }
+        Err(apps) => Err(StripError::ipa(format!(
+            "ipa has {} apps in its Payload directory",
+            apps.len()
+        ))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_ipa() {
+        assert!(is_ipa(Path::new("Example.ipa")));
+        assert!(is_ipa(Path::new("Example.IPA")));
+        assert!(!is_ipa(Path::new("Example.app")));
+    }
+
+    #[test]
+    fn test_extract_ipa() {
+        let extracted = extract_ipa(Path::new("tests/fixtures/Example.ipa")).unwrap();
+        let app_path = extracted.app_path().to_path_buf();
+        assert!(app_path.ends_with("Payload/Example.app"));
+        assert!(app_path.join("Info.plist").is_file());
+
+        drop(extracted);
+        assert!(!app_path.exists());
+    }
+
+    #[test]
+    fn test_extract_ipa_not_zip() {
+        let error = extract_ipa(Path::new("tests/fixtures/provisioned.xml")).unwrap_err();
+        assert_eq!(error.to_string(), "Failed to read ipa");
+    }
+}
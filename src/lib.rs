@@ -0,0 +1,1404 @@
+//! Strip provisioned entitlements from an app's entitlements so it can be re-signed without a
+//! provisioning profile.
+//!
+//! ```
+//! # fn main() -> anyhow::Result<()> {
+//! # let bytes = br#"<?xml version="1.0" encoding="UTF-8"?><plist version="1.0"><dict><key>com.apple.developer.team-identifier</key><string>AAAAAAAAAA</string></dict></plist>"#;
+//! let mut entitlements: plist::Value = plist::from_bytes(bytes)?;
+//! provisioned_entitlement_stripper::remove_provisioned_entitlements(&mut entitlements, &[])?;
+//! # assert!(entitlements.as_dictionary().unwrap().is_empty());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Getting the entitlements of an app with `codesign` lives in the [`codesign`] module, and
+//! [`strip_app`] does both in one call. Everything that can fail returns a [`StripError`].
+
+use std::{path::Path, sync::LazyLock};
+
+pub mod bundle;
+pub mod codesign;
+pub mod duplicates;
+pub mod error;
+pub mod ipa;
+pub mod pkg;
+pub mod profile;
+
+pub use error::StripError;
+
+use error::Result;
+
+/// The built-in list of [`PROVISIONED_ENTITLEMENTS`], one per line as the entitlement followed by
+/// the macOS version it was introduced in, with blank lines and lines starting with `#` ignored.
+pub const PROVISIONED_ENTITLEMENTS_LIST: &str = include_str!("provisioned_entitlements.txt");
+
+/// A macOS version, such as `10.15` or `13`, compared numerically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MacosVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+}
+
+impl MacosVersion {
+    pub const fn new(major: u32, minor: u32, patch: u32) -> Self {
+        MacosVersion {
+            major,
+            minor,
+            patch,
+        }
+    }
+}
+
+impl std::str::FromStr for MacosVersion {
+    type Err = StripError;
+
+    /// Parses one to three numbers separated by dots, with missing ones being 0.
+    fn from_str(version: &str) -> Result<Self> {
+        let invalid = || StripError::InvalidVersion(version.to_string());
+        let parts: Vec<&str> = version.split('.').collect();
+        if parts.len() > 3
+            || parts
+                .iter()
+                .any(|part| part.is_empty() || !part.bytes().all(|b| b.is_ascii_digit()))
+        {
+            return Err(invalid());
+        }
+        let mut numbers = [0; 3];
+        for (number, part) in numbers.iter_mut().zip(parts) {
+            *number = part.parse().map_err(|_| invalid())?;
+        }
+        let [major, minor, patch] = numbers;
+        Ok(MacosVersion::new(major, minor, patch))
+    }
+}
+
+impl std::fmt::Display for MacosVersion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)?;
+        if self.patch != 0 {
+            write!(f, ".{}", self.patch)?;
+        }
+        Ok(())
+    }
+}
+
+/// One of the [`PROVISIONED_ENTITLEMENTS`] and the macOS version it was introduced in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProvisionedEntitlement {
+    pub name: &'static str,
+    pub introduced: MacosVersion,
+}
+
+/// The entitlements and versions read from [`PROVISIONED_ENTITLEMENTS_LIST`].
+pub static PROVISIONED_ENTITLEMENT_TABLE: LazyLock<Vec<ProvisionedEntitlement>> =
+    LazyLock::new(|| {
+        PROVISIONED_ENTITLEMENTS_LIST
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| {
+                let (name, introduced) = line
+                    .split_once(char::is_whitespace)
+                    .unwrap_or_else(|| panic!("{line} has no version in the built-in list"));
+                ProvisionedEntitlement {
+                    name,
+                    introduced: introduced
+                        .trim()
+                        .parse()
+                        .unwrap_or_else(|error| panic!("{name}: {error}")),
+                }
+            })
+            .collect()
+    });
+
+/// Entitlements that are added by provisioning profiles and must be removed before re-signing
+/// without one, read from [`PROVISIONED_ENTITLEMENTS_LIST`].
+pub static PROVISIONED_ENTITLEMENTS: LazyLock<Vec<&'static str>> = LazyLock::new(|| {
+    PROVISIONED_ENTITLEMENT_TABLE
+        .iter()
+        .map(|entitlement| entitlement.name)
+        .collect()
+});
+
+/// Returns the [`PROVISIONED_ENTITLEMENTS`] introduced in `version` or earlier, for apps that
+/// target an older macOS that doesn't know about the newer ones.
+pub fn provisioned_entitlements_since(version: MacosVersion) -> Vec<&'static str> {
+    PROVISIONED_ENTITLEMENT_TABLE
+        .iter()
+        .filter(|entitlement| entitlement.introduced <= version)
+        .map(|entitlement| entitlement.name)
+        .collect()
+}
+
+/// Descriptions of what each of the [`PROVISIONED_ENTITLEMENTS`] grants, one per line as the
+/// entitlement followed by its description, with blank lines and lines starting with `#` ignored.
+pub const ENTITLEMENT_DESCRIPTIONS_LIST: &str = include_str!("entitlement_descriptions.txt");
+
+/// The entitlements and descriptions read from [`ENTITLEMENT_DESCRIPTIONS_LIST`].
+pub static ENTITLEMENT_DESCRIPTIONS: LazyLock<Vec<(&'static str, &'static str)>> =
+    LazyLock::new(|| {
+        ENTITLEMENT_DESCRIPTIONS_LIST
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .filter_map(|line| line.split_once(char::is_whitespace))
+            .map(|(entitlement, description)| (entitlement, description.trim()))
+            .collect()
+    });
+
+/// Returns the built-in description of an entitlement, if there is one.
+pub fn describe_entitlement(entitlement: &str) -> Option<&'static str> {
+    ENTITLEMENT_DESCRIPTIONS
+        .iter()
+        .find(|(key, _)| *key == entitlement)
+        .map(|(_, description)| *description)
+}
+
+/// [`PROVISIONED_ENTITLEMENTS`] that some apps legitimately manage themselves, so stripping them
+/// can break features like universal links or shared keychains.
+pub const AMBIGUOUS_ENTITLEMENTS: &[&str] = &[
+    "com.apple.developer.associated-domains",
+    "com.apple.developer.associated-domains.applinks.read-write",
+    "com.apple.security.application-groups",
+    "keychain-access-groups",
+];
+
+/// Entitlements that debug builds have so debuggers can attach, which distribution builds usually
+/// shouldn't. Unlike [`PROVISIONED_ENTITLEMENTS`], an app re-signed without a provisioning profile
+/// can keep these, so they're only stripped when asked for.
+pub const DEBUG_ENTITLEMENTS: &[&str] = &["com.apple.security.get-task-allow", "get-task-allow"];
+
+/// Removes all [`PROVISIONED_ENTITLEMENTS`], and any `extra` entitlements, from an entitlements
+/// dictionary.
+pub fn remove_provisioned_entitlements(
+    entitlements: &mut plist::Value,
+    extra: &[&str],
+) -> Result<()> {
+    remove_entitlements(
+        entitlements,
+        &strip_set(&PROVISIONED_ENTITLEMENTS, extra, &[]),
+    )
+}
+
+/// Returns the entitlements to strip: the `base` entitlements, usually
+/// [`PROVISIONED_ENTITLEMENTS`], and any `extra` entitlements, without any entitlements in `keep`.
+pub fn strip_set<'a>(base: &[&'a str], extra: &[&'a str], keep: &[&str]) -> Vec<&'a str> {
+    let mut strip_set = Vec::new();
+    for entitlement in base.iter().chain(extra) {
+        if !keep.contains(entitlement) && !strip_set.contains(entitlement) {
+            strip_set.push(*entitlement);
+        }
+    }
+    strip_set
+}
+
+/// Parses a list of entitlement keys, either as a JSON array of strings or with one key per line.
+///
+/// In the line-based form, surrounding whitespace is trimmed and blank lines and lines starting
+/// with `#` are ignored. An empty list is an error.
+pub fn parse_entitlement_list(contents: &str) -> Result<Vec<String>> {
+    let entitlements: Vec<String> = if contents.trim_start().starts_with('[') {
+        serde_json::from_str(contents).map_err(|source| StripError::InvalidEntitlementList {
+            message: "Entitlement list is not a JSON array of strings".to_string(),
+            source: Some(source),
+        })?
+    } else {
+        contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(String::from)
+            .collect()
+    };
+    if entitlements.is_empty() {
+        return Err(StripError::InvalidEntitlementList {
+            message: "Entitlement list is empty".to_string(),
+            source: None,
+        });
+    }
+    Ok(entitlements)
+}
+
+/// The version every XML plist declares, such as in `<plist version="1.0">`.
+pub const PLIST_VERSION: &str = "1.0";
+
+/// Returns the version declared by the `<plist>` element of an XML plist.
+///
+/// Returns `None` for binary plists, and for XML without a `<plist>` element or a version, which
+/// parsing the plist reports instead.
+pub fn plist_version(xml: &[u8]) -> Option<String> {
+    if !xml.trim_ascii_start().starts_with(b"<") {
+        return None;
+    }
+    let mut reader = quick_xml::Reader::from_reader(xml);
+    let mut buf = Vec::new();
+    loop {
+        match reader.read_event_into(&mut buf).ok()? {
+            quick_xml::events::Event::Start(start) | quick_xml::events::Event::Empty(start) => {
+                if start.name().as_ref() != b"plist" {
+                    return None;
+                }
+                let version = start.try_get_attribute("version").ok()??;
+                return Some(version.unescape_value().ok()?.into_owned());
+            }
+            quick_xml::events::Event::Eof => return None,
+            _ => buf.clear(),
+        }
+    }
+}
+
+/// Checks that an XML plist read from `source` declares [`PLIST_VERSION`], logging a warning if
+/// it doesn't, or returning an error if `strict`.
+pub fn check_plist_version(xml: &[u8], source: &str, strict: bool) -> Result<()> {
+    let Some(version) = plist_version(xml).filter(|version| version != PLIST_VERSION) else {
+        return Ok(());
+    };
+    let error = StripError::UnexpectedPlistVersion {
+        input: source.to_string(),
+        version,
+    };
+    if strict {
+        return Err(error);
+    }
+    log::warn!("{}", error);
+    Ok(())
+}
+
+/// Removes the given entitlements from an entitlements dictionary.
+///
+/// The remaining entitlements keep their original order.
+pub fn remove_entitlements(entitlements: &mut plist::Value, to_remove: &[&str]) -> Result<()> {
+    let dictionary = dictionary_mut(entitlements)?;
+    dictionary.retain(|key, _| !to_remove.contains(&key.as_str()));
+    Ok(())
+}
+
+/// Returns a new entitlements dictionary with only the given entitlements, the inverse of
+/// [`remove_entitlements`], such as to keep a record of the provisioned entitlements an app had.
+///
+/// The entitlements keep their original order and values. Missing entitlements are skipped.
+pub fn select_entitlements(
+    entitlements: &plist::Value,
+    to_select: &[&str],
+) -> Result<plist::Value> {
+    let selected: plist::Dictionary = dictionary(entitlements)?
+        .iter()
+        .filter(|(key, _)| to_select.contains(&key.as_str()))
+        .map(|(key, value)| (key.clone(), value.clone()))
+        .collect();
+    Ok(plist::Value::Dictionary(selected))
+}
+
+/// Removes the given entitlements from dictionaries nested inside the values of an entitlements
+/// dictionary, at any depth.
+///
+/// Only dictionary values are walked into. Arrays aren't, so dictionaries inside arrays are left
+/// alone, and top-level keys aren't removed, which is what [`remove_entitlements`] is for. Returns
+/// the path of each removed key, with the keys leading to it separated by `/`, such as
+/// `com.example.settings/com.apple.developer.team-identifier`.
+pub fn remove_nested_entitlements(
+    entitlements: &mut plist::Value,
+    to_remove: &[&str],
+) -> Result<Vec<String>> {
+    let dictionary = dictionary_mut(entitlements)?;
+    let mut removed = Vec::new();
+    for (key, value) in dictionary.iter_mut() {
+        if let Some(nested) = value.as_dictionary_mut() {
+            remove_nested_keys(nested, to_remove, key, &mut removed);
+        }
+    }
+    Ok(removed)
+}
+
+fn remove_nested_keys(
+    dictionary: &mut plist::Dictionary,
+    to_remove: &[&str],
+    path: &str,
+    removed: &mut Vec<String>,
+) {
+    removed.extend(
+        dictionary
+            .keys()
+            .filter(|key| to_remove.contains(&key.as_str()))
+            .map(|key| format!("{}/{}", path, key)),
+    );
+    dictionary.retain(|key, _| !to_remove.contains(&key.as_str()));
+    for (key, value) in dictionary.iter_mut() {
+        if let Some(nested) = value.as_dictionary_mut() {
+            remove_nested_keys(nested, to_remove, &format!("{}/{}", path, key), removed);
+        }
+    }
+}
+
+/// Returns the name of the type of a plist value, such as `Dictionary` or `Array`, for errors.
+pub fn value_type_name(value: &plist::Value) -> &'static str {
+    match value {
+        plist::Value::Array(_) => "Array",
+        plist::Value::Dictionary(_) => "Dictionary",
+        plist::Value::Boolean(_) => "Boolean",
+        plist::Value::Data(_) => "Data",
+        plist::Value::Date(_) => "Date",
+        plist::Value::Real(_) => "Real",
+        plist::Value::Integer(_) => "Integer",
+        plist::Value::String(_) => "String",
+        plist::Value::Uid(_) => "UID",
+        _ => "unknown value",
+    }
+}
+
+fn dictionary(entitlements: &plist::Value) -> Result<&plist::Dictionary> {
+    entitlements
+        .as_dictionary()
+        .ok_or_else(|| StripError::not_a_dictionary("Entitlements root", entitlements))
+}
+
+fn dictionary_mut(entitlements: &mut plist::Value) -> Result<&mut plist::Dictionary> {
+    if entitlements.as_dictionary().is_none() {
+        return Err(StripError::not_a_dictionary(
+            "Entitlements root",
+            entitlements,
+        ));
+    }
+    Ok(entitlements.as_dictionary_mut().unwrap())
+}
+
+/// Replaces the values of string entitlements with placeholders instead of removing them, so the
+/// structure of the entitlements is kept.
+///
+/// `redactions` is a list of `(entitlement, replacement)` pairs. Entitlements that are missing or
+/// aren't strings are left alone. Returns the entitlements that were redacted.
+pub fn redact_entitlements<'a>(
+    entitlements: &mut plist::Value,
+    redactions: &[(&'a str, &str)],
+) -> Result<Vec<&'a str>> {
+    let dictionary = dictionary_mut(entitlements)?;
+    let mut redacted = Vec::new();
+    for (entitlement, replacement) in redactions {
+        if let Some(plist::Value::String(value)) = dictionary.get_mut(entitlement) {
+            *value = replacement.to_string();
+            redacted.push(*entitlement);
+        }
+    }
+    Ok(redacted)
+}
+
+/// Returns the entitlements whose values differ between the given entitlements dictionaries,
+/// including entitlements missing from some of them, in the order they're first seen.
+pub fn get_differing_entitlements(entitlements: &[&plist::Value]) -> Result<Vec<String>> {
+    let dictionaries = entitlements
+        .iter()
+        .map(|entitlements| dictionary(entitlements))
+        .collect::<Result<Vec<_>>>()?;
+    let mut differing: Vec<String> = Vec::new();
+    for dictionary in &dictionaries {
+        for key in dictionary.keys() {
+            if differing.contains(key) {
+                continue;
+            }
+            let value = dictionary.get(key);
+            if dictionaries.iter().any(|other| other.get(key) != value) {
+                differing.push(key.clone());
+            }
+        }
+    }
+    Ok(differing)
+}
+
+/// The entitlement listing an app's shared app group containers.
+pub const APPLICATION_GROUPS: &str = "com.apple.security.application-groups";
+
+/// The entitlements holding an app's `<TEAM ID>.<BUNDLE ID>` application identifier, on macOS and
+/// on iOS.
+pub const APPLICATION_IDENTIFIERS: &[&str] =
+    &["com.apple.application-identifier", "application-identifier"];
+
+/// Splits the application identifier in the entitlements into its team ID prefix and bundle
+/// identifier.
+///
+/// The team ID is `None` if the application identifier doesn't start with a 10 character team ID,
+/// in which case all of it is the bundle identifier. Returns `None` if there's no application
+/// identifier.
+pub fn get_application_identifier(
+    entitlements: &plist::Value,
+) -> Result<Option<(Option<&str>, &str)>> {
+    let dictionary = dictionary(entitlements)?;
+    let Some(application_identifier) = APPLICATION_IDENTIFIERS
+        .iter()
+        .find_map(|entitlement| dictionary.get(entitlement)?.as_string())
+    else {
+        return Ok(None);
+    };
+    Ok(Some(match application_identifier.split_once('.') {
+        Some((team, bundle_identifier))
+            if team.len() == 10 && team.chars().all(|c| c.is_ascii_alphanumeric()) =>
+        {
+            (Some(team), bundle_identifier)
+        }
+        _ => (None, application_identifier),
+    }))
+}
+
+/// Returns the bundle identifier from the application identifier in the entitlements, without its
+/// team ID prefix, see [`get_application_identifier`].
+///
+/// Returns `None` if there's no application identifier or it's a wildcard, such as
+/// `AAAAAAAAAA.*`.
+pub fn get_bundle_identifier(entitlements: &plist::Value) -> Result<Option<String>> {
+    Ok(get_application_identifier(entitlements)?
+        .map(|(_, bundle_identifier)| bundle_identifier)
+        .filter(|bundle_identifier| {
+            !bundle_identifier.is_empty() && !bundle_identifier.ends_with('*')
+        })
+        .map(String::from))
+}
+
+/// Removes the strings starting with any of `prefixes` from an array entitlement such as
+/// [`APPLICATION_GROUPS`], removing the entitlement entirely if that leaves it empty.
+///
+/// Returns whether the entitlement is still present.
+pub fn filter_array_entitlement(
+    entitlements: &mut plist::Value,
+    entitlement: &str,
+    prefixes: &[&str],
+) -> Result<bool> {
+    let dictionary = dictionary_mut(entitlements)?;
+    let Some(value) = dictionary.get_mut(entitlement) else {
+        return Ok(false);
+    };
+    let array = value
+        .as_array_mut()
+        .ok_or_else(|| StripError::NotAnArray(entitlement.to_string()))?;
+    array.retain(|item| {
+        !item
+            .as_string()
+            .is_some_and(|item| prefixes.iter().any(|prefix| item.starts_with(prefix)))
+    });
+    if array.is_empty() {
+        dictionary.retain(|key, _| key != entitlement);
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Removes duplicate strings from array entitlements, such as an [`APPLICATION_GROUPS`] listing
+/// the same group twice, keeping the first of each.
+///
+/// Other values in the arrays are left alone. Returns the entitlements that had duplicates.
+pub fn dedupe_array_entitlements(entitlements: &mut plist::Value) -> Result<Vec<String>> {
+    let dictionary = dictionary_mut(entitlements)?;
+    let mut deduped = Vec::new();
+    for (key, value) in dictionary.iter_mut() {
+        let Some(array) = value.as_array_mut() else {
+            continue;
+        };
+        let mut seen = std::collections::HashSet::new();
+        let len = array.len();
+        array.retain(|value| match value.as_string() {
+            Some(string) => seen.insert(string.to_string()),
+            None => true,
+        });
+        if array.len() != len {
+            deduped.push(key.clone());
+        }
+    }
+    Ok(deduped)
+}
+
+/// Removes entitlements whose value is an empty array, dictionary or string, such as an
+/// [`APPLICATION_GROUPS`] array that stripping left empty.
+///
+/// Only top-level entitlements are checked. Returns the entitlements that were removed.
+pub fn remove_empty_entitlements(entitlements: &mut plist::Value) -> Result<Vec<String>> {
+    let dictionary = dictionary_mut(entitlements)?;
+    let empty: Vec<String> = dictionary
+        .iter()
+        .filter(|(_, value)| match value {
+            plist::Value::Array(array) => array.is_empty(),
+            plist::Value::Dictionary(dictionary) => dictionary.is_empty(),
+            plist::Value::String(string) => string.is_empty(),
+            _ => false,
+        })
+        .map(|(key, _)| key.clone())
+        .collect();
+    for key in &empty {
+        dictionary.remove(key);
+    }
+    Ok(empty)
+}
+
+/// Sorts the keys of every dictionary in a plist, including nested ones, by their UTF-8 bytes.
+///
+/// Dictionaries otherwise keep the order their keys were read or inserted in, so this makes the
+/// written plist independent of the order of the input.
+pub fn sort_keys(value: &mut plist::Value) {
+    match value {
+        plist::Value::Dictionary(dictionary) => {
+            dictionary.sort_keys();
+            for (_, value) in dictionary.iter_mut() {
+                sort_keys(value);
+            }
+        }
+        plist::Value::Array(array) => array.iter_mut().for_each(sort_keys),
+        _ => {}
+    }
+}
+
+/// Overlays the entitlements in `base` onto an entitlements dictionary, with `base` winning when
+/// both have the same key.
+///
+/// Overridden entitlements keep their position and new ones are added at the end, in the order
+/// they appear in `base`.
+pub fn merge_entitlements(entitlements: &mut plist::Value, base: &plist::Value) -> Result<()> {
+    let base = base
+        .as_dictionary()
+        .ok_or_else(|| StripError::not_a_dictionary("Base entitlements root", base))?;
+    let dictionary = dictionary_mut(entitlements)?;
+    for (key, value) in base {
+        dictionary.insert(key.clone(), value.clone());
+    }
+    Ok(())
+}
+
+/// Returns the keys in an entitlements dictionary that start with any of `prefixes`, matched
+/// case-sensitively.
+///
+/// The keys are returned owned so the dictionary can be modified afterwards, e.g. with
+/// [`remove_entitlements`].
+pub fn get_prefixed_entitlements(
+    entitlements: &plist::Value,
+    prefixes: &[&str],
+) -> Result<Vec<String>> {
+    let dictionary = dictionary(entitlements)?;
+    let prefixed_entitlements = dictionary
+        .keys()
+        .filter(|key| prefixes.iter().any(|prefix| key.starts_with(prefix)))
+        .cloned()
+        .collect();
+    Ok(prefixed_entitlements)
+}
+
+/// Returns the entitlements in `candidates` that are present in an entitlements dictionary, in the
+/// order of `candidates`.
+pub fn get_present_entitlements<'a>(
+    entitlements: &plist::Value,
+    candidates: &[&'a str],
+) -> Result<Vec<&'a str>> {
+    let dictionary = dictionary(entitlements)?;
+    let mut present_entitlements = Vec::new();
+    for entitlement in candidates {
+        if dictionary.contains_key(entitlement) && !present_entitlements.contains(entitlement) {
+            present_entitlements.push(*entitlement);
+        }
+    }
+    Ok(present_entitlements)
+}
+
+/// Returns the values of the given entitlements, in the order of `keys`, skipping any that are
+/// missing from the entitlements dictionary.
+pub fn get_entitlement_values<'a>(
+    entitlements: &plist::Value,
+    keys: &[&'a str],
+) -> Result<Vec<(&'a str, plist::Value)>> {
+    let dictionary = dictionary(entitlements)?;
+    let values = keys
+        .iter()
+        .filter_map(|key| dictionary.get(key).map(|value| (*key, value.clone())))
+        .collect();
+    Ok(values)
+}
+
+/// Like [`get_provisioned_entitlements`], but also returns the value of each entitlement.
+pub fn get_provisioned_entitlements_with_values<'a>(
+    entitlements: &plist::Value,
+    extra: &[&'a str],
+) -> Result<Vec<(&'a str, plist::Value)>> {
+    get_entitlement_values(
+        entitlements,
+        &get_provisioned_entitlements(entitlements, extra)?,
+    )
+}
+
+/// Returns the [`PROVISIONED_ENTITLEMENTS`] present in an entitlements dictionary, in sorted order,
+/// followed by any present `extra` entitlements in the order given.
+pub fn get_provisioned_entitlements<'a>(
+    entitlements: &plist::Value,
+    extra: &[&'a str],
+) -> Result<Vec<&'a str>> {
+    get_present_entitlements(
+        entitlements,
+        &strip_set(&PROVISIONED_ENTITLEMENTS, extra, &[]),
+    )
+}
+
+/// Gets the entitlements of an app with the default codesign and strips the
+/// [`PROVISIONED_ENTITLEMENTS`] from them.
+pub fn strip_app(app_path: &Path) -> Result<plist::Value> {
+    strip_app_with_codesign(Path::new(codesign::DEFAULT_CODESIGN_PATH), app_path)
+}
+
+/// Like [`strip_app`], but with the given codesign binary.
+pub fn strip_app_with_codesign(codesign_path: &Path, app_path: &Path) -> Result<plist::Value> {
+    let mut entitlements = codesign::get_entitlements(codesign_path, app_path)?;
+    remove_provisioned_entitlements(&mut entitlements, &[])?;
+    Ok(entitlements)
+}
+
+/// Gets the entitlements of an app with the default codesign and returns the
+/// [`PROVISIONED_ENTITLEMENTS`] present, in sorted order.
+pub fn list_provisioned_app(app_path: &Path) -> Result<Vec<&'static str>> {
+    list_provisioned_app_with_codesign(Path::new(codesign::DEFAULT_CODESIGN_PATH), app_path)
+}
+
+/// Like [`list_provisioned_app`], but with the given codesign binary.
+pub fn list_provisioned_app_with_codesign(
+    codesign_path: &Path,
+    app_path: &Path,
+) -> Result<Vec<&'static str>> {
+    let entitlements = codesign::get_entitlements(codesign_path, app_path)?;
+    get_provisioned_entitlements(&entitlements, &[])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::collections::HashSet;
+
+    fn xml_to_plist_value(entitlements_xml: &[u8]) -> plist::Value {
+        plist::from_bytes(entitlements_xml).unwrap()
+    }
+
+    fn remove_provisioned_entitlements_to_string(entitlements_xml: &[u8]) -> String {
+        let mut entitlements = xml_to_plist_value(entitlements_xml);
+        remove_provisioned_entitlements(&mut entitlements, &[]).unwrap();
+        let mut writer = Vec::new();
+        let write_options = plist::XmlWriteOptions::default().indent(0, 0);
+        plist::to_writer_xml_with_options(&mut writer, &entitlements, &write_options).unwrap();
+        String::from_utf8(writer).unwrap()
+    }
+
+    #[test]
+    fn test_ambiguous_entitlements_are_provisioned() {
+        for entitlement in AMBIGUOUS_ENTITLEMENTS {
+            assert!(PROVISIONED_ENTITLEMENTS.contains(entitlement));
+        }
+    }
+
+    #[test]
+    fn test_remove_provisioned_entitlements() {
+        let entitlements_xml = br#"<?xml version="1.0" encoding="UTF-8"?><!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "https://www.apple.com/DTDs/PropertyList-1.0.dtd"><plist version="1.0"><dict><key>com.apple.application-identifier</key><string>AAAAAAAAAA.com.example.example</string><key>com.apple.developer.aps-environment</key><string>production</string><key>com.apple.developer.team-identifier</key><string>AAAAAAAAAA</string><key>com.apple.security.automation.apple-events</key><true/><key>com.apple.security.device.audio-input</key><true/><key>com.apple.security.device.camera</key><true/></dict></plist>"#;
+        let stripped_xml =
+            remove_provisioned_entitlements_to_string(entitlements_xml).replace('\n', "");
+        let expected = r#"<?xml version="1.0" encoding="UTF-8"?><!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd"><plist version="1.0"><dict><key>com.apple.security.automation.apple-events</key><true/><key>com.apple.security.device.audio-input</key><true/><key>com.apple.security.device.camera</key><true/></dict></plist>"#;
+        assert_eq!(stripped_xml, expected);
+    }
+
+    /// Writes a fake codesign that prints `entitlements_xml` for any app.
+    #[cfg(unix)]
+    fn fake_codesign(name: &str, entitlements_xml: &str) -> std::path::PathBuf {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = std::env::temp_dir().join(format!(
+            "provisioned-entitlement-stripper-{}-{}",
+            name,
+            std::process::id()
+        ));
+        std::fs::write(
+            &path,
+            format!("#!/bin/sh\ncat <<'EOF'\n{}\nEOF\n", entitlements_xml),
+        )
+        .unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        path
+    }
+
+    #[cfg(unix)]
+    const FAKE_APP_ENTITLEMENTS: &str = r#"<?xml version="1.0" encoding="UTF-8"?><plist version="1.0"><dict><key>com.apple.developer.team-identifier</key><string>AAAAAAAAAA</string><key>com.apple.application-identifier</key><string>AAAAAAAAAA.com.example.example</string><key>com.apple.security.device.camera</key><true/></dict></plist>"#;
+
+    #[cfg(unix)]
+    #[test]
+    fn test_strip_app() {
+        let codesign_path = fake_codesign("strip-app-codesign", FAKE_APP_ENTITLEMENTS);
+        let stripped = strip_app_with_codesign(&codesign_path, Path::new("Example.app"));
+        std::fs::remove_file(&codesign_path).unwrap();
+        let keys: Vec<String> = stripped
+            .unwrap()
+            .into_dictionary()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect();
+        assert_eq!(keys, ["com.apple.security.device.camera"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_list_provisioned_app() {
+        let codesign_path = fake_codesign("list-app-codesign", FAKE_APP_ENTITLEMENTS);
+        let provisioned =
+            list_provisioned_app_with_codesign(&codesign_path, Path::new("Example.app"));
+        std::fs::remove_file(&codesign_path).unwrap();
+        assert_eq!(
+            provisioned.unwrap(),
+            [
+                "com.apple.application-identifier",
+                "com.apple.developer.team-identifier"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remove_nested_entitlements() {
+        let entitlements_xml = br#"<?xml version="1.0" encoding="UTF-8"?><plist version="1.0"><dict><key>com.apple.developer.team-identifier</key><string>AAAAAAAAAA</string><key>com.example.settings</key><dict><key>com.apple.developer.team-identifier</key><string>AAAAAAAAAA</string><key>com.example.inner</key><dict><key>com.apple.application-identifier</key><string>AAAAAAAAAA.com.example.example</string><key>com.example.kept</key><true/></dict></dict><key>com.example.list</key><array><dict><key>com.apple.developer.team-identifier</key><string>AAAAAAAAAA</string></dict></array></dict></plist>"#;
+        let mut entitlements = xml_to_plist_value(entitlements_xml);
+        let removed =
+            remove_nested_entitlements(&mut entitlements, &PROVISIONED_ENTITLEMENTS).unwrap();
+        assert_eq!(
+            removed,
+            [
+                "com.example.settings/com.apple.developer.team-identifier",
+                "com.example.settings/com.example.inner/com.apple.application-identifier",
+            ]
+        );
+
+        let expected_xml = br#"<?xml version="1.0" encoding="UTF-8"?><plist version="1.0"><dict><key>com.apple.developer.team-identifier</key><string>AAAAAAAAAA</string><key>com.example.settings</key><dict><key>com.example.inner</key><dict><key>com.example.kept</key><true/></dict></dict><key>com.example.list</key><array><dict><key>com.apple.developer.team-identifier</key><string>AAAAAAAAAA</string></dict></array></dict></plist>"#;
+        assert_eq!(entitlements, xml_to_plist_value(expected_xml));
+    }
+
+    #[test]
+    fn test_dedupe_array_entitlements() {
+        let entitlements_xml = br#"<?xml version="1.0" encoding="UTF-8"?><plist version="1.0"><dict><key>com.apple.security.application-groups</key><array><string>AAAAAAAAAA.com.example.a</string><string>AAAAAAAAAA.com.example.b</string><string>AAAAAAAAAA.com.example.a</string></array><key>com.example.numbers</key><array><integer>1</integer><integer>1</integer></array><key>com.apple.security.device.camera</key><true/></dict></plist>"#;
+        let mut entitlements = xml_to_plist_value(entitlements_xml);
+        assert_eq!(
+            dedupe_array_entitlements(&mut entitlements).unwrap(),
+            [APPLICATION_GROUPS]
+        );
+
+        let expected_xml = br#"<?xml version="1.0" encoding="UTF-8"?><plist version="1.0"><dict><key>com.apple.security.application-groups</key><array><string>AAAAAAAAAA.com.example.a</string><string>AAAAAAAAAA.com.example.b</string></array><key>com.example.numbers</key><array><integer>1</integer><integer>1</integer></array><key>com.apple.security.device.camera</key><true/></dict></plist>"#;
+        assert_eq!(entitlements, xml_to_plist_value(expected_xml));
+    }
+
+    #[test]
+    fn test_plist_version() {
+        assert_eq!(
+            plist_version(
+                br#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0"><dict/></plist>"#
+            )
+            .as_deref(),
+            Some("1.0")
+        );
+        assert_eq!(
+            plist_version(br#"<plist version="2.0"><dict/></plist>"#).as_deref(),
+            Some("2.0")
+        );
+        assert_eq!(plist_version(b"<plist><dict/></plist>"), None);
+        assert_eq!(plist_version(b"<dict/>"), None);
+        assert_eq!(plist_version(b"bplist00"), None);
+    }
+
+    #[test]
+    fn test_check_plist_version() {
+        let xml = br#"<plist version="0.9"><dict/></plist>"#;
+        assert!(check_plist_version(xml, "test", false).is_ok());
+        let error = check_plist_version(xml, "test", true).unwrap_err();
+        assert!(matches!(
+            &error,
+            StripError::UnexpectedPlistVersion { version, .. } if version == "0.9"
+        ));
+        assert_eq!(
+            error.to_string(),
+            "Entitlements from test are plist version \"0.9\", expected 1.0"
+        );
+        assert!(
+            check_plist_version(br#"<plist version="1.0"><dict/></plist>"#, "test", true).is_ok()
+        );
+    }
+
+    #[test]
+    fn test_get_bundle_identifier() {
+        let bundle_identifier = |entitlements_xml: &str| {
+            get_bundle_identifier(&xml_to_plist_value(
+                format!(r#"<plist version="1.0"><dict>{entitlements_xml}</dict></plist>"#)
+                    .as_bytes(),
+            ))
+            .unwrap()
+        };
+        assert_eq!(
+            bundle_identifier(
+                "<key>com.apple.application-identifier</key><string>AAAAAAAAAA.com.example.example</string>"
+            )
+            .as_deref(),
+            Some("com.example.example")
+        );
+        assert_eq!(
+            bundle_identifier(
+                "<key>application-identifier</key><string>AAAAAAAAAA.com.example.ios</string>"
+            )
+            .as_deref(),
+            Some("com.example.ios")
+        );
+        assert_eq!(
+            bundle_identifier(
+                "<key>com.apple.application-identifier</key><string>com.example.example</string>"
+            )
+            .as_deref(),
+            Some("com.example.example")
+        );
+        assert_eq!(
+            bundle_identifier(
+                "<key>com.apple.application-identifier</key><string>AAAAAAAAAA.*</string>"
+            ),
+            None
+        );
+        assert_eq!(
+            bundle_identifier("<key>com.apple.security.device.camera</key><true/>"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_get_application_identifier() {
+        let application_identifier = |entitlements_xml: &str| {
+            let entitlements = xml_to_plist_value(
+                format!(r#"<plist version="1.0"><dict>{entitlements_xml}</dict></plist>"#)
+                    .as_bytes(),
+            );
+            get_application_identifier(&entitlements)
+                .unwrap()
+                .map(|(team, bundle_identifier)| {
+                    (team.map(String::from), bundle_identifier.to_string())
+                })
+        };
+        assert_eq!(
+            application_identifier(
+                "<key>com.apple.application-identifier</key><string>AAAAAAAAAA.com.example.example</string>"
+            ),
+            Some((
+                Some("AAAAAAAAAA".to_string()),
+                "com.example.example".to_string()
+            ))
+        );
+        // The macOS entitlement wins over the iOS one
+        assert_eq!(
+            application_identifier(
+                "<key>application-identifier</key><string>BBBBBBBBBB.com.example.ios</string><key>com.apple.application-identifier</key><string>AAAAAAAAAA.com.example.macos</string>"
+            ),
+            Some((
+                Some("AAAAAAAAAA".to_string()),
+                "com.example.macos".to_string()
+            ))
+        );
+        assert_eq!(
+            application_identifier(
+                "<key>com.apple.application-identifier</key><string>com.example.example</string>"
+            ),
+            Some((None, "com.example.example".to_string()))
+        );
+        assert_eq!(
+            application_identifier("<key>com.apple.security.device.camera</key><true/>"),
+            None
+        );
+    }
+
+    #[test]
+    fn test_select_entitlements() {
+        let entitlements_xml = br#"<?xml version="1.0" encoding="UTF-8"?><plist version="1.0"><dict><key>com.apple.application-identifier</key><string>AAAAAAAAAA.com.example.example</string><key>com.apple.developer.team-identifier</key><string>AAAAAAAAAA</string><key>com.apple.security.device.camera</key><true/></dict></plist>"#;
+        let entitlements = xml_to_plist_value(entitlements_xml);
+        let selected = select_entitlements(
+            &entitlements,
+            &get_provisioned_entitlements(&entitlements, &[]).unwrap(),
+        )
+        .unwrap();
+
+        let expected_xml = br#"<?xml version="1.0" encoding="UTF-8"?><plist version="1.0"><dict><key>com.apple.application-identifier</key><string>AAAAAAAAAA.com.example.example</string><key>com.apple.developer.team-identifier</key><string>AAAAAAAAAA</string></dict></plist>"#;
+        assert_eq!(selected, xml_to_plist_value(expected_xml));
+        assert_eq!(
+            select_entitlements(&entitlements, &["com.example.missing"]).unwrap(),
+            plist::Value::Dictionary(plist::Dictionary::new())
+        );
+    }
+
+    #[test]
+    fn test_sort_keys() {
+        let entitlements_xml = br#"<?xml version="1.0" encoding="UTF-8"?><plist version="1.0"><dict><key>com.example.b</key><dict><key>z</key><true/><key>a</key><true/></dict><key>com.example.a</key><array><dict><key>y</key><true/><key>x</key><true/></dict></array><key>Z</key><true/></dict></plist>"#;
+        let mut entitlements = xml_to_plist_value(entitlements_xml);
+        let keys = |value: &plist::Value| -> Vec<String> {
+            value.as_dictionary().unwrap().keys().cloned().collect()
+        };
+        assert_eq!(keys(&entitlements), ["com.example.b", "com.example.a", "Z"]);
+
+        sort_keys(&mut entitlements);
+        assert_eq!(keys(&entitlements), ["Z", "com.example.a", "com.example.b"]);
+        assert_eq!(
+            keys(&entitlements.as_dictionary().unwrap()["com.example.b"]),
+            ["a", "z"]
+        );
+        assert_eq!(
+            keys(
+                &entitlements.as_dictionary().unwrap()["com.example.a"]
+                    .as_array()
+                    .unwrap()[0]
+            ),
+            ["x", "y"]
+        );
+
+        let sorted = entitlements.clone();
+        sort_keys(&mut entitlements);
+        assert_eq!(keys(&entitlements), keys(&sorted));
+    }
+
+    #[test]
+    fn test_remove_empty_entitlements() {
+        let entitlements_xml = br#"<?xml version="1.0" encoding="UTF-8"?><plist version="1.0"><dict><key>com.example.array</key><array/><key>com.example.dictionary</key><dict/><key>com.example.string</key><string></string><key>com.example.false</key><false/><key>com.example.zero</key><integer>0</integer><key>com.example.nested</key><dict><key>com.example.array</key><array/></dict><key>com.apple.security.application-groups</key><array><string>AAAAAAAAAA.com.example.a</string></array></dict></plist>"#;
+        let mut entitlements = xml_to_plist_value(entitlements_xml);
+        assert_eq!(
+            remove_empty_entitlements(&mut entitlements).unwrap(),
+            [
+                "com.example.array",
+                "com.example.dictionary",
+                "com.example.string"
+            ]
+        );
+
+        let expected_xml = br#"<?xml version="1.0" encoding="UTF-8"?><plist version="1.0"><dict><key>com.example.false</key><false/><key>com.example.zero</key><integer>0</integer><key>com.example.nested</key><dict><key>com.example.array</key><array/></dict><key>com.apple.security.application-groups</key><array><string>AAAAAAAAAA.com.example.a</string></array></dict></plist>"#;
+        assert_eq!(entitlements, xml_to_plist_value(expected_xml));
+        assert!(
+            remove_empty_entitlements(&mut entitlements)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_redact_entitlements() {
+        let entitlements_xml = br#"<?xml version="1.0" encoding="UTF-8"?><plist version="1.0"><dict><key>com.apple.application-identifier</key><string>AAAAAAAAAA.com.example.example</string><key>com.apple.developer.team-identifier</key><string>AAAAAAAAAA</string><key>com.apple.security.device.camera</key><true/></dict></plist>"#;
+        let mut entitlements = xml_to_plist_value(entitlements_xml);
+        let redacted = redact_entitlements(
+            &mut entitlements,
+            &[
+                ("com.apple.developer.team-identifier", "XXXXXXXXXX"),
+                (
+                    "com.apple.application-identifier",
+                    "XXXXXXXXXX.com.example.example",
+                ),
+                ("com.apple.security.device.camera", "XXXXXXXXXX"),
+                ("com.apple.developer.aps-environment", "XXXXXXXXXX"),
+            ],
+        )
+        .unwrap();
+        assert_eq!(
+            redacted,
+            [
+                "com.apple.developer.team-identifier",
+                "com.apple.application-identifier"
+            ]
+        );
+        let dictionary = entitlements.as_dictionary().unwrap();
+        assert_eq!(
+            dictionary["com.apple.developer.team-identifier"].as_string(),
+            Some("XXXXXXXXXX")
+        );
+        assert_eq!(
+            dictionary["com.apple.application-identifier"].as_string(),
+            Some("XXXXXXXXXX.com.example.example")
+        );
+        assert_eq!(
+            dictionary["com.apple.security.device.camera"].as_boolean(),
+            Some(true)
+        );
+    }
+
+    #[test]
+    fn test_get_differing_entitlements() {
+        let x86_64 = xml_to_plist_value(
+            br#"<?xml version="1.0" encoding="UTF-8"?><plist version="1.0"><dict><key>com.apple.security.app-sandbox</key><true/><key>com.apple.security.device.camera</key><true/><key>com.apple.security.network.client</key><true/></dict></plist>"#,
+        );
+        let arm64 = xml_to_plist_value(
+            br#"<?xml version="1.0" encoding="UTF-8"?><plist version="1.0"><dict><key>com.apple.security.app-sandbox</key><true/><key>com.apple.security.device.camera</key><false/><key>com.apple.security.device.audio-input</key><true/></dict></plist>"#,
+        );
+        assert_eq!(
+            get_differing_entitlements(&[&x86_64, &arm64]).unwrap(),
+            [
+                "com.apple.security.device.camera",
+                "com.apple.security.network.client",
+                "com.apple.security.device.audio-input",
+            ]
+        );
+        assert!(
+            get_differing_entitlements(&[&x86_64, &x86_64])
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_filter_array_entitlement() {
+        let entitlements_xml = br#"<?xml version="1.0" encoding="UTF-8"?><plist version="1.0"><dict><key>com.apple.security.application-groups</key><array><string>AAAAAAAAAA.com.example.group</string><string>group.com.example.custom</string></array><key>com.apple.security.device.camera</key><true/></dict></plist>"#;
+        let mut entitlements = xml_to_plist_value(entitlements_xml);
+        assert!(
+            filter_array_entitlement(&mut entitlements, APPLICATION_GROUPS, &["AAAAAAAAAA."])
+                .unwrap()
+        );
+        assert_eq!(
+            entitlements.as_dictionary().unwrap()[APPLICATION_GROUPS],
+            plist::Value::Array(vec![plist::Value::String(
+                "group.com.example.custom".to_string()
+            )])
+        );
+
+        assert!(
+            !filter_array_entitlement(&mut entitlements, APPLICATION_GROUPS, &["group."]).unwrap()
+        );
+        let keys: Vec<&str> = entitlements
+            .as_dictionary()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        assert_eq!(keys, ["com.apple.security.device.camera"]);
+
+        assert!(
+            !filter_array_entitlement(&mut entitlements, APPLICATION_GROUPS, &["group."]).unwrap()
+        );
+        assert!(
+            filter_array_entitlement(
+                &mut entitlements,
+                "com.apple.security.device.camera",
+                &["group."]
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_merge_entitlements() {
+        let mut entitlements = xml_to_plist_value(
+            br#"<?xml version="1.0" encoding="UTF-8"?><plist version="1.0"><dict><key>com.apple.security.app-sandbox</key><true/><key>com.apple.security.device.camera</key><false/></dict></plist>"#,
+        );
+        let base = xml_to_plist_value(
+            br#"<?xml version="1.0" encoding="UTF-8"?><plist version="1.0"><dict><key>com.apple.security.network.client</key><true/><key>com.apple.security.device.camera</key><true/></dict></plist>"#,
+        );
+        merge_entitlements(&mut entitlements, &base).unwrap();
+        let entries: Vec<(&str, bool)> = entitlements
+            .as_dictionary()
+            .unwrap()
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_boolean().unwrap()))
+            .collect();
+        assert_eq!(
+            entries,
+            [
+                ("com.apple.security.app-sandbox", true),
+                ("com.apple.security.device.camera", true),
+                ("com.apple.security.network.client", true),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remove_entitlements_preserves_order() {
+        let entitlements_xml = br#"<?xml version="1.0" encoding="UTF-8"?><plist version="1.0"><dict><key>d</key><true/><key>a</key><true/><key>c</key><true/><key>b</key><true/><key>e</key><true/></dict></plist>"#;
+        let mut entitlements = xml_to_plist_value(entitlements_xml);
+        remove_entitlements(&mut entitlements, &["d", "b"]).unwrap();
+        let keys: Vec<&str> = entitlements
+            .as_dictionary()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        assert_eq!(keys, ["a", "c", "e"]);
+    }
+
+    #[test]
+    fn test_list_provisioned_entitlements() {
+        let entitlements_xml = br#"<?xml version="1.0" encoding="UTF-8"?><!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "https://www.apple.com/DTDs/PropertyList-1.0.dtd"><plist version="1.0"><dict><key>com.apple.application-identifier</key><string>AAAAAAAAAA.com.example.example</string><key>com.apple.developer.aps-environment</key><string>production</string><key>com.apple.developer.team-identifier</key><string>AAAAAAAAAA</string><key>com.apple.security.automation.apple-events</key><true/><key>com.apple.security.device.audio-input</key><true/><key>com.apple.security.device.camera</key><true/></dict></plist>"#;
+        let entitlements = xml_to_plist_value(entitlements_xml);
+        assert_eq!(
+            get_provisioned_entitlements(&entitlements, &[]).unwrap(),
+            [
+                "com.apple.application-identifier",
+                "com.apple.developer.aps-environment",
+                "com.apple.developer.team-identifier",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_list_provisioned_entitlements_with_values() {
+        let entitlements_xml = br#"<?xml version="1.0" encoding="UTF-8"?><!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "https://www.apple.com/DTDs/PropertyList-1.0.dtd"><plist version="1.0"><dict><key>com.apple.developer.team-identifier</key><string>AAAAAAAAAA</string><key>com.apple.security.application-groups</key><array><string>AAAAAAAAAA.com.example.group</string></array><key>com.apple.security.device.camera</key><true/></dict></plist>"#;
+        let entitlements = xml_to_plist_value(entitlements_xml);
+        assert_eq!(
+            get_provisioned_entitlements_with_values(&entitlements, &[]).unwrap(),
+            [
+                (
+                    "com.apple.developer.team-identifier",
+                    plist::Value::String("AAAAAAAAAA".to_string())
+                ),
+                (
+                    "com.apple.security.application-groups",
+                    plist::Value::Array(vec![plist::Value::String(
+                        "AAAAAAAAAA.com.example.group".to_string()
+                    )])
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_remove_extra_entitlements() {
+        let entitlements_xml = br#"<?xml version="1.0" encoding="UTF-8"?><!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "https://www.apple.com/DTDs/PropertyList-1.0.dtd"><plist version="1.0"><dict><key>com.apple.developer.team-identifier</key><string>AAAAAAAAAA</string><key>com.example.provisioned</key><true/><key>com.apple.security.device.camera</key><true/></dict></plist>"#;
+        let mut entitlements = xml_to_plist_value(entitlements_xml);
+        remove_provisioned_entitlements(&mut entitlements, &["com.example.provisioned"]).unwrap();
+        let keys: Vec<&String> = entitlements.as_dictionary().unwrap().keys().collect();
+        assert_eq!(keys, ["com.apple.security.device.camera"]);
+    }
+
+    #[test]
+    fn test_list_extra_entitlements() {
+        let entitlements_xml = br#"<?xml version="1.0" encoding="UTF-8"?><!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "https://www.apple.com/DTDs/PropertyList-1.0.dtd"><plist version="1.0"><dict><key>com.apple.developer.team-identifier</key><string>AAAAAAAAAA</string><key>com.example.provisioned</key><true/><key>com.apple.security.device.camera</key><true/></dict></plist>"#;
+        let entitlements = xml_to_plist_value(entitlements_xml);
+        assert_eq!(
+            get_provisioned_entitlements(
+                &entitlements,
+                &[
+                    "com.example.provisioned",
+                    "com.example.missing",
+                    "com.apple.developer.team-identifier",
+                ],
+            )
+            .unwrap(),
+            [
+                "com.apple.developer.team-identifier",
+                "com.example.provisioned",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_strip_set_keep() {
+        let strip_set = strip_set(
+            &PROVISIONED_ENTITLEMENTS,
+            &["com.example.provisioned"],
+            &[
+                "com.apple.security.application-groups",
+                "com.example.provisioned",
+            ],
+        );
+        assert!(!strip_set.contains(&"com.apple.security.application-groups"));
+        assert!(!strip_set.contains(&"com.example.provisioned"));
+        assert!(strip_set.contains(&"com.apple.developer.team-identifier"));
+        assert_eq!(strip_set.len(), PROVISIONED_ENTITLEMENTS.len() - 1);
+    }
+
+    #[test]
+    fn test_remove_entitlements_keep() {
+        let entitlements_xml = br#"<?xml version="1.0" encoding="UTF-8"?><!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "https://www.apple.com/DTDs/PropertyList-1.0.dtd"><plist version="1.0"><dict><key>com.apple.developer.team-identifier</key><string>AAAAAAAAAA</string><key>com.apple.security.application-groups</key><array><string>AAAAAAAAAA.com.example.group</string></array><key>com.apple.security.device.camera</key><true/></dict></plist>"#;
+        let mut entitlements = xml_to_plist_value(entitlements_xml);
+        let strip_set = strip_set(
+            &PROVISIONED_ENTITLEMENTS,
+            &[],
+            &["com.apple.security.application-groups"],
+        );
+        remove_entitlements(&mut entitlements, &strip_set).unwrap();
+        let dictionary = entitlements.as_dictionary().unwrap();
+        assert!(dictionary.contains_key("com.apple.security.application-groups"));
+        assert!(dictionary.contains_key("com.apple.security.device.camera"));
+        assert!(!dictionary.contains_key("com.apple.developer.team-identifier"));
+    }
+
+    #[test]
+    fn test_get_prefixed_entitlements() {
+        let entitlements_xml = br#"<?xml version="1.0" encoding="UTF-8"?><!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "https://www.apple.com/DTDs/PropertyList-1.0.dtd"><plist version="1.0"><dict><key>com.apple.developer.team-identifier</key><string>AAAAAAAAAA</string><key>com.apple.developer.new-thing</key><true/><key>com.apple.security.device.camera</key><true/></dict></plist>"#;
+        let entitlements = xml_to_plist_value(entitlements_xml);
+        assert_eq!(
+            get_prefixed_entitlements(&entitlements, &["com.apple.developer."]).unwrap(),
+            [
+                "com.apple.developer.team-identifier",
+                "com.apple.developer.new-thing",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_get_prefixed_entitlements_no_match() {
+        let entitlements_xml = br#"<?xml version="1.0" encoding="UTF-8"?><!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "https://www.apple.com/DTDs/PropertyList-1.0.dtd"><plist version="1.0"><dict><key>com.apple.developer.team-identifier</key><string>AAAAAAAAAA</string><key>com.apple.security.device.camera</key><true/></dict></plist>"#;
+        let entitlements = xml_to_plist_value(entitlements_xml);
+        assert!(
+            get_prefixed_entitlements(&entitlements, &["com.example."])
+                .unwrap()
+                .is_empty()
+        );
+        assert!(
+            get_prefixed_entitlements(&entitlements, &["COM.APPLE.DEVELOPER."])
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn test_strip_set_custom_base() {
+        assert_eq!(
+            strip_set(
+                &["com.example.a", "com.example.b"],
+                &["com.example.c"],
+                &["com.example.b"]
+            ),
+            ["com.example.a", "com.example.c"]
+        );
+    }
+
+    #[test]
+    fn test_parse_entitlement_list_lines() {
+        assert_eq!(
+            parse_entitlement_list(
+                "# Provisioned entitlements\ncom.example.a\n\n  com.example.b  \n"
+            )
+            .unwrap(),
+            ["com.example.a", "com.example.b"]
+        );
+    }
+
+    #[test]
+    fn test_parse_entitlement_list_json() {
+        assert_eq!(
+            parse_entitlement_list(r#"["com.example.a", "com.example.b"]"#).unwrap(),
+            ["com.example.a", "com.example.b"]
+        );
+        assert!(parse_entitlement_list(r#"["com.example.a", 1]"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_entitlement_list_empty() {
+        assert_eq!(
+            parse_entitlement_list("# Nothing\n\n")
+                .unwrap_err()
+                .to_string(),
+            "Entitlement list is empty"
+        );
+        assert_eq!(
+            parse_entitlement_list("[]").unwrap_err().to_string(),
+            "Entitlement list is empty"
+        );
+    }
+
+    #[test]
+    fn test_not_a_dictionary() {
+        let mut entitlements = plist::Value::Array(Vec::new());
+        let error = remove_entitlements(&mut entitlements, &[]).unwrap_err();
+        assert!(matches!(
+            &error,
+            StripError::NotADictionary { name, found: "Array" } if name == "Entitlements root"
+        ));
+        assert_eq!(
+            error.to_string(),
+            "Entitlements root is an Array, expected Dictionary"
+        );
+        let entitlements = plist::Value::String("a".to_string());
+        assert_eq!(
+            get_provisioned_entitlements(&entitlements, &[])
+                .unwrap_err()
+                .to_string(),
+            "Entitlements root is a String, expected Dictionary"
+        );
+    }
+
+    #[test]
+    fn test_entitlement_descriptions() {
+        let entitlements: Vec<&str> = ENTITLEMENT_DESCRIPTIONS
+            .iter()
+            .map(|(entitlement, _)| *entitlement)
+            .collect();
+        assert!(entitlements.is_sorted());
+        assert_eq!(
+            ENTITLEMENT_DESCRIPTIONS_LIST
+                .lines()
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .count(),
+            entitlements.len()
+        );
+        for entitlement in PROVISIONED_ENTITLEMENTS.iter().chain(DEBUG_ENTITLEMENTS) {
+            assert!(
+                describe_entitlement(entitlement).is_some(),
+                "{entitlement} has no description"
+            );
+        }
+        assert!(
+            ENTITLEMENT_DESCRIPTIONS
+                .iter()
+                .all(|(_, description)| description.ends_with('.'))
+        );
+        assert_eq!(describe_entitlement("com.example.unknown"), None);
+    }
+
+    #[test]
+    fn test_provisioned_entitlements_sorted() {
+        assert!(PROVISIONED_ENTITLEMENTS.is_sorted());
+    }
+
+    #[test]
+    fn test_provisioned_entitlements_unique() {
+        let unique: HashSet<&&str> = PROVISIONED_ENTITLEMENTS.iter().collect();
+        assert_eq!(unique.len(), PROVISIONED_ENTITLEMENTS.len());
+    }
+
+    #[test]
+    fn test_provisioned_entitlements_list() {
+        assert_eq!(
+            parse_entitlement_list(PROVISIONED_ENTITLEMENTS_LIST)
+                .unwrap()
+                .len(),
+            PROVISIONED_ENTITLEMENTS.len()
+        );
+        for entitlement in PROVISIONED_ENTITLEMENTS.iter() {
+            assert!(!entitlement.contains(char::is_whitespace));
+            assert!(!DEBUG_ENTITLEMENTS.contains(entitlement));
+        }
+        for entitlement in PROVISIONED_ENTITLEMENT_TABLE.iter() {
+            assert!(entitlement.introduced >= MacosVersion::new(10, 0, 0));
+        }
+    }
+
+    #[test]
+    fn test_macos_version() {
+        assert_eq!(
+            "13".parse::<MacosVersion>().unwrap(),
+            MacosVersion::new(13, 0, 0)
+        );
+        assert_eq!(
+            "10.15.7".parse::<MacosVersion>().unwrap(),
+            MacosVersion::new(10, 15, 7)
+        );
+        assert!(MacosVersion::new(10, 15, 0) < MacosVersion::new(11, 0, 0));
+        assert!(MacosVersion::new(10, 9, 0) < MacosVersion::new(10, 15, 0));
+        assert_eq!(MacosVersion::new(13, 0, 0).to_string(), "13.0");
+        assert_eq!(MacosVersion::new(10, 15, 7).to_string(), "10.15.7");
+        for invalid in ["", "13.", "v13", "1.2.3.4", "13.x", "-1"] {
+            assert_eq!(
+                invalid.parse::<MacosVersion>().unwrap_err().to_string(),
+                format!("{invalid:?} is not a macOS version, expected one like 13 or 10.15")
+            );
+        }
+    }
+
+    #[test]
+    fn test_provisioned_entitlements_since() {
+        assert_eq!(
+            provisioned_entitlements_since(MacosVersion::new(99, 0, 0)),
+            *PROVISIONED_ENTITLEMENTS
+        );
+        assert_eq!(
+            provisioned_entitlements_since(MacosVersion::new(10, 6, 0)),
+            ["application-identifier", "aps-environment"]
+        );
+        let ventura = provisioned_entitlements_since(MacosVersion::new(13, 0, 0));
+        assert!(ventura.contains(&"com.apple.developer.weatherkit"));
+        assert!(!ventura.contains(&"com.apple.developer.web-browser.public-key-credential"));
+        let monterey = provisioned_entitlements_since(MacosVersion::new(12, 6, 0));
+        assert!(!monterey.contains(&"com.apple.developer.weatherkit"));
+        assert!(monterey.contains(&"com.apple.developer.usernotifications.time-sensitive"));
+        assert!(monterey.is_sorted());
+    }
+}
@@ -1,213 +1,5041 @@
-use std::{fs, io::BufWriter, path::PathBuf};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fs,
+    io::{BufWriter, LineWriter, Read, Write},
+    path::{Path, PathBuf},
+    process::ExitCode,
+};
 
 use anyhow::{Context, Result, bail};
-use clap::{CommandFactory, Parser, Subcommand};
+use clap::{ArgAction, Args, CommandFactory, FromArgMatches, Parser, Subcommand, ValueEnum};
+use indicatif::{ProgressBar, ProgressFinish, ProgressStyle};
+use provisioned_entitlement_stripper::{
+    AMBIGUOUS_ENTITLEMENTS, APPLICATION_GROUPS, DEBUG_ENTITLEMENTS, MacosVersion,
+    PROVISIONED_ENTITLEMENTS, StripError,
+    bundle::{find_nested_bundles, read_bundle_identifier},
+    check_plist_version,
+    codesign::{
+        Codesign, DEFAULT_CODESIGN_PATH, SigningInfo, check_arg as check_codesign_arg,
+        parse_entitlements_text,
+    },
+    dedupe_array_entitlements, describe_entitlement,
+    duplicates::warn_suspicious_keys,
+    filter_array_entitlement, get_application_identifier, get_bundle_identifier,
+    get_differing_entitlements, get_entitlement_values, get_prefixed_entitlements,
+    get_present_entitlements, get_provisioned_entitlements,
+    ipa::{extract_ipa, is_ipa},
+    merge_entitlements, parse_entitlement_list,
+    pkg::{DEFAULT_PKGUTIL_PATH, expand_pkg, is_pkg},
+    profile::get_profile_entitlements,
+    provisioned_entitlements_since, redact_entitlements, remove_empty_entitlements,
+    remove_entitlements, remove_nested_entitlements, select_entitlements, sort_keys, strip_set,
+    value_type_name,
+};
+use serde::{Deserialize, Serialize};
 
 #[derive(Parser)]
 #[command(version, author, about, long_about = None)]
 struct Cli {
+    /// The codesign binary to use
+    #[arg(
+        long,
+        global = true,
+        value_name = "PATH",
+        env = "STRIPPER_CODESIGN",
+        default_value = DEFAULT_CODESIGN_PATH
+    )]
+    codesign_path: PathBuf,
+
+    /// Kill codesign and fail if it takes longer than this many seconds
+    #[arg(long, global = true, value_name = "SECS", env = "STRIPPER_TIMEOUT")]
+    timeout: Option<u64>,
+
+    /// The architecture of universal binaries to read the entitlements of, passed to codesign
+    #[arg(long, global = true)]
+    arch: Option<String>,
+
+    /// An extra argument to pass to codesign before the app path, such as --deep, can be given
+    /// multiple times
+    #[arg(
+        long,
+        global = true,
+        value_name = "ARG",
+        allow_hyphen_values = true,
+        value_parser = parse_codesign_arg
+    )]
+    codesign_arg: Vec<String>,
+
+    /// Print each codesign command to stderr before running it
+    #[arg(long, global = true)]
+    print_command: bool,
+
+    /// Don't run codesign, only print the commands that would be run
+    #[arg(long, global = true, requires = "print_command")]
+    no_exec: bool,
+
+    /// Warn about duplicate keys, and keys only differing in case or surrounding whitespace, in the
+    /// input entitlements. Otherwise the plist parser silently keeps the last value of duplicate
+    /// keys
+    #[arg(long, global = true)]
+    strict_parse: bool,
+
+    /// Don't print informational output, only results, warnings and errors
+    #[arg(short, long, global = true)]
+    quiet: bool,
+
+    /// Log what the tool is doing to stderr, -v for debug and -vv for trace logging
+    #[arg(short, long, global = true, action = ArgAction::Count, conflicts_with = "quiet")]
+    verbose: u8,
+
+    /// When to color output, auto colors it when stdout is a terminal and NO_COLOR isn't set
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        env = "STRIPPER_COLOR",
+        default_value_t = ColorMode::Auto
+    )]
+    color: ColorMode,
+
+    /// The format to print errors to stderr in. JSON errors are an object with the root cause as
+    /// error and the messages describing what failed, outermost first, as context
+    #[arg(
+        long,
+        global = true,
+        value_enum,
+        env = "STRIPPER_ERROR_FORMAT",
+        default_value_t = OutputFormat::Text
+    )]
+    error_format: OutputFormat,
+
+    /// A TOML file setting the defaults of --codesign-path, --extra, --keep, --output-format and
+    /// --color. Defaults to .stripper.toml in the current directory if it exists
+    #[arg(long, global = true, value_name = "FILE")]
+    config: Option<PathBuf>,
+
     #[command(subcommand)]
     command: Commands,
 }
 
 #[derive(Subcommand)]
+// Only one is ever created, so boxing Strip's arguments wouldn't save anything
+#[allow(clippy::large_enum_variant)]
 enum Commands {
     /// Generate an entitlements.xml for an app with provisioned entitlements removed
     Strip {
-        /// The app to strip entitlements from
+        #[command(flatten)]
+        input: StripInputArgs,
+
+        #[command(flatten)]
+        entitlement_args: EntitlementArgs,
+
+        /// File to write the stripped entitlements to, or - for stdout. Can be given multiple
+        /// times to write several files in one pass, each in the format its extension implies:
+        /// .xml for XML, .plist for a binary plist and .json for JSON, unless --output-format is
+        /// given
+        #[arg(
+            short = 'o',
+            long = "output",
+            required_unless_present_any = ["output_dir", "merge_output", "print", "in_place"]
+        )]
+        output_paths: Vec<PathBuf>,
+
+        /// Directory to write each app's stripped entitlements to, named by --name-template
+        #[arg(
+            long,
+            value_name = "DIR",
+            group = "batch_output",
+            conflicts_with_all = ["output_paths", "from_file", "from_stdin"]
+        )]
+        output_dir: Option<PathBuf>,
+
+        /// File to write the union of every app's stripped entitlements to, or - for stdout.
+        /// Entitlements that have different values in different apps are an error
+        #[arg(
+            long,
+            value_name = "PATH",
+            group = "batch_output",
+            conflicts_with_all = ["output_paths", "from_file", "from_stdin"]
+        )]
+        merge_output: Option<PathBuf>,
+
+        /// Also strip the entitlements of bundles nested inside each app, such as app extensions
+        /// and frameworks
+        #[arg(long, requires = "batch_output")]
+        recursive: bool,
+
+        /// The file name to write each app's entitlements to in --output-dir. {app} is the app's
+        /// name, {bundle_id} and {team} are read from its entitlements, and {ext} is xml or plist
+        /// depending on --output-format
+        #[arg(
+            long,
+            value_name = "TEMPLATE",
+            value_parser = NameTemplate::parse,
+            default_value = "{app}.entitlements.{ext}",
+            requires = "output_dir"
+        )]
+        name_template: NameTemplate,
+
+        #[command(flatten)]
+        plist_output: PlistOutputArgs,
+
+        /// A provisioned entitlement to keep instead of stripping, can be given multiple times
+        #[arg(long, value_name = "ENTITLEMENT")]
+        keep: Vec<String>,
+
+        /// Strip only this entitlement, ignoring the built-in list, and warn if it isn't present
+        #[arg(
+            long,
+            value_name = "ENTITLEMENT",
+            conflicts_with_all = [
+                "keep", "extra", "strip_prefix", "list_file", "redact", "group_prefix",
+                "strip_debug"
+            ]
+        )]
+        only: Option<String>,
+
+        /// Strip every entitlement except these, ignoring the built-in list. Each value is a file
+        /// listing entitlements one per line or as a JSON array, or else a single entitlement, and
+        /// it can be given multiple times
+        #[arg(
+            long,
+            value_name = "FILE_OR_ENTITLEMENT",
+            conflicts_with_all = [
+                "keep", "only", "extra", "strip_prefix", "list_file", "redact", "group_prefix",
+                "deep", "strip_debug"
+            ]
+        )]
+        keep_only: Vec<String>,
+
+        /// Print the entitlements that would be stripped without writing any output
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Append a JSON object describing the result for each app to this file, one per line
+        #[arg(long, value_name = "PATH", requires = "output_dir")]
+        json_lines: Option<PathBuf>,
+
+        /// Write a JSON summary of every app's result, and how many apps each entitlement was
+        /// removed from, to this file once all apps are stripped
+        #[arg(long, value_name = "PATH", requires = "output_dir")]
+        summary_json: Option<PathBuf>,
+
+        /// How many apps to run codesign on at once, defaulting to the number of CPUs. Output
+        /// and messages are in the order the apps were given whatever the number
+        #[arg(long, value_name = "N", requires = "output_dir")]
+        threads: Option<std::num::NonZeroUsize>,
+
+        /// Show app and output paths relative to this directory in messages and --json-lines, or
+        /// as given if they aren't inside it
+        #[arg(long, value_name = "DIR", requires = "batch_output")]
+        relative_to: Option<PathBuf>,
+
+        /// Warn about com.apple.developer.* entitlements left after stripping, which might be
+        /// provisioned entitlements missing from the list
+        #[arg(long)]
+        warn_unknown: bool,
+
+        /// Print how many top-level entitlements were removed and how many remain to stderr, and
+        /// how many nested ones were removed with --deep, summed across all apps in batch mode
+        #[arg(long, conflicts_with = "dry_run")]
+        stats: bool,
+
+        /// Also print the stripped entitlements to stderr, or to stdout if there's no --output.
+        /// Binary plists are printed as XML
+        #[arg(long, conflicts_with_all = ["dry_run", "output_dir"])]
+        print: bool,
+
+        /// Write the original entitlements to this file before stripping them
+        #[arg(
+            long,
+            value_name = "PATH",
+            conflicts_with_all = ["dry_run", "batch_output"]
+        )]
+        backup: Option<PathBuf>,
+
+        /// Overwrite the --backup file if it already exists
+        #[arg(long, requires = "backup")]
+        force: bool,
+
+        /// Warn before removing provisioned entitlements that some apps manage themselves, such as
+        /// com.apple.developer.associated-domains
+        #[arg(long)]
+        warn_ambiguous: bool,
+
+        /// Fail instead of removing the entitlements --warn-ambiguous warns about, unless they're
+        /// given to --keep, and on input plists with a version other than 1.0
+        #[arg(long)]
+        strict: bool,
+
+        /// Fail if an app has no entitlements at all, which usually means it's the wrong build,
+        /// instead of writing an empty entitlements file
+        #[arg(long)]
+        fail_if_no_entitlements: bool,
+
+        /// Remove duplicate strings from array entitlements left after stripping, such as
+        /// application groups kept with --keep
+        #[arg(long)]
+        dedupe_arrays: bool,
+
+        /// Remove entitlements left with an empty array, dictionary or string after stripping,
+        /// such as a dictionary emptied by --deep
+        #[arg(long)]
+        strip_empty: bool,
+
+        /// Write only the provisioned entitlements that would be stripped, with their original
+        /// values, instead of the stripped entitlements, to keep a record of what provisioning
+        /// added. Entitlements nested inside others with --deep aren't included
+        #[arg(long, conflicts_with_all = ["in_place", "stats", "warn_unknown"])]
+        only_provisioned: bool,
+
+        /// Re-sign the app with the stripped entitlements using the --sign identity
+        #[arg(
+            long,
+            requires = "sign",
+            conflicts_with_all = ["dry_run", "batch_output", "from_file", "from_stdin"]
+        )]
+        in_place: bool,
+
+        /// The identity to re-sign the app with for --in-place, such as - for ad-hoc signing
+        #[arg(
+            long,
+            value_name = "IDENTITY",
+            requires = "in_place",
+            value_parser = clap::builder::NonEmptyStringValueParser::new()
+        )]
+        sign: Option<String>,
+
+        /// Check the re-signed app's signature with codesign --verify --strict after --in-place
+        #[arg(long, requires = "in_place")]
+        verify: bool,
+    },
+
+    /// List provisioned entitlements for an app
+    DryRun {
+        #[command(flatten)]
+        input: InputArgs,
+
+        #[command(flatten)]
+        entitlement_args: EntitlementArgs,
+
+        /// The format to print the provisioned entitlements in
+        #[arg(long, value_enum, env = "STRIPPER_FORMAT", default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+
+        /// Also list the provisioned entitlements of bundles nested inside the app, such as app
+        /// extensions and frameworks
+        #[arg(long, conflicts_with_all = ["from_file", "from_stdin"])]
+        recursive: bool,
+
+        /// Exit with status 1 if any provisioned entitlements, or debug entitlements with
+        /// --strip-debug, are found and 0 if none are
+        #[arg(long)]
+        exit_code: bool,
+
+        /// Also print the value of each provisioned entitlement
+        #[arg(long)]
+        show_values: bool,
+
+        /// List the provisioned entitlements of each architecture of a universal binary and warn
+        /// about any entitlements that differ between them
+        #[arg(long, conflicts_with_all = ["from_file", "from_stdin", "recursive"])]
+        all_archs: bool,
+
+        /// Also print the signing identifier, team and certificate authorities of the app
+        #[arg(long, conflicts_with_all = ["from_file", "from_stdin", "all_archs"])]
+        show_signing_info: bool,
+
+        /// Only print the number of provisioned entitlements, summed across bundles with
+        /// --recursive, whatever the --format
+        #[arg(long, conflicts_with_all = ["show_values", "all_archs", "show_signing_info"])]
+        count_only: bool,
+
+        /// Print the entitlements as an XML plist with a comment above each entitlement that
+        /// would be stripped instead of listing them, whatever the --format
+        #[arg(
+            long,
+            conflicts_with_all = ["recursive", "show_values", "all_archs", "show_signing_info", "count_only"]
+        )]
+        annotate: bool,
+
+        /// Print only the provisioned entitlements, each followed by a NUL byte instead of a
+        /// newline, for piping into xargs -0. Can't be used with --format json
+        #[arg(
+            short = '0',
+            long,
+            conflicts_with_all = [
+                "recursive", "show_values", "all_archs", "show_signing_info", "count_only",
+                "annotate",
+            ]
+        )]
+        null: bool,
+    },
+
+    /// Show the changes stripping would make to an app's entitlements
+    Diff {
+        #[command(flatten)]
+        input: InputArgs,
+
+        #[command(flatten)]
+        entitlement_args: EntitlementArgs,
+    },
+
+    /// Check that a file already contains an app's stripped entitlements, exiting with status 1
+    /// and showing the differences if it doesn't
+    Check {
+        #[command(flatten)]
+        input: InputArgs,
+
+        #[command(flatten)]
+        entitlement_args: EntitlementArgs,
+
+        /// The file that should contain the stripped entitlements
+        #[arg(long, value_name = "PATH")]
+        expected: PathBuf,
+    },
+
+    /// Strip an app's entitlements and overlay the entitlements from a base file, with the base
+    /// file winning when both have the same entitlement
+    Merge {
+        #[command(flatten)]
+        input: InputArgs,
+
+        #[command(flatten)]
+        entitlement_args: EntitlementArgs,
+
+        /// The plist file of entitlements to add to the stripped entitlements
+        #[arg(long, value_name = "PATH")]
+        base: PathBuf,
+
+        /// File to write the merged entitlements to, or - for stdout
+        #[arg(short = 'o', long = "output")]
+        output_path: PathBuf,
+
+        #[command(flatten)]
+        plist_output: PlistOutputArgs,
+    },
+
+    /// Show the entitlements that differ between two apps, marking the provisioned ones
+    Compare {
+        /// The first app, or an .ipa or .pkg containing it
+        first: PathBuf,
+
+        /// The second app, or an .ipa or .pkg containing it
+        second: PathBuf,
+
+        /// The format to print the differences in
+        #[arg(long, value_enum, env = "STRIPPER_FORMAT", default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// Write an app's entitlements to a file without stripping them
+    Extract {
+        #[command(flatten)]
+        input: InputArgs,
+
+        /// File to write the entitlements to, or - for stdout
+        #[arg(short = 'o', long = "output")]
+        output_path: PathBuf,
+
+        #[command(flatten)]
+        plist_output: PlistOutputArgs,
+    },
+
+    /// Strip an app's entitlements, then strip them again whenever the app changes, until
+    /// interrupted
+    Watch {
+        /// The app to watch, or an .ipa or .pkg containing it
         app_path: PathBuf,
 
         /// File to write the stripped entitlements to
         #[arg(short = 'o', long = "output")]
         output_path: PathBuf,
+
+        #[command(flatten)]
+        entitlement_args: EntitlementArgs,
+
+        #[command(flatten)]
+        plist_output: PlistOutputArgs,
+
+        /// How long to wait for changes to settle before stripping again
+        #[arg(long, value_name = "MILLISECONDS", default_value_t = 500)]
+        debounce: u64,
     },
 
-    /// List provisioned entitlements for an app
-    DryRun {
-        /// The app to strip entitlements from
+    /// List the provisioned entitlements in an entitlements file, exiting with status 1 if there
+    /// are any
+    Validate {
+        /// The entitlements plist to check, such as one written by another tool
+        path: PathBuf,
+
+        /// The format to print the provisioned entitlements in
+        #[arg(long, value_enum, env = "STRIPPER_FORMAT", default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// Check an app's signature with codesign --verify --strict, such as after strip --in-place,
+    /// exiting with status 1 if it's invalid
+    Verify {
+        /// The app to verify, or another bundle or a standalone binary
         app_path: PathBuf,
     },
 
+    /// List the entitlements granted by a provisioning profile
+    Profile {
+        /// The provisioning profile, such as an app's embedded.mobileprovision
+        profile_path: PathBuf,
+
+        /// The format to print the entitlements in
+        #[arg(long, value_enum, env = "STRIPPER_FORMAT", default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// List the entitlements that are considered provisioned
+    KnownEntitlements {
+        /// Only list the entitlements introduced in this macOS version or earlier
+        #[arg(long, value_name = "VERSION")]
+        since: Option<MacosVersion>,
+
+        /// The format to print the entitlements in
+        #[arg(long, value_enum, env = "STRIPPER_FORMAT", default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// Describe what entitlements grant and whether they're provisioned
+    Explain {
+        /// The entitlements to describe
+        #[arg(required = true, value_name = "ENTITLEMENT")]
+        entitlements: Vec<String>,
+
+        /// The format to print the descriptions in
+        #[arg(long, value_enum, env = "STRIPPER_FORMAT", default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// List entitlements in Apple's entitlements reference that look provisioned but aren't
+    /// considered provisioned, exiting with status 1 if there are any, to check whether the list
+    /// is out of date
+    #[command(hide = true)]
+    AuditList {
+        /// Fetch the reference from Apple's documentation with curl instead of using the snapshot
+        /// of it bundled with this version
+        #[arg(long)]
+        online: bool,
+
+        /// Where to fetch the reference from with --online
+        #[arg(long, value_name = "URL", default_value = APPLE_ENTITLEMENTS_URL)]
+        url: String,
+
+        /// The format to print the entitlements in
+        #[arg(long, value_enum, env = "STRIPPER_FORMAT", default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
+    /// Print the version and codesign path for scripts
+    #[command(hide = true)]
+    Version {
+        /// The format to print the version in
+        #[arg(long, value_enum, env = "STRIPPER_FORMAT", default_value_t = OutputFormat::Text)]
+        format: OutputFormat,
+    },
+
     /// Generate shell completions
     Completions {
         /// The shell to generate the completions for
         #[arg(value_enum)]
         shell: clap_complete_command::Shell,
+
+        /// Directory to write the completions to, named as the shell expects, instead of stdout
+        #[arg(long, value_name = "DIR")]
+        output: Option<PathBuf>,
+    },
+
+    /// Generate a man page
+    Man {
+        /// Directory to write the man page, and a page for each subcommand, to instead of stdout
+        #[arg(long, value_name = "DIR")]
+        output: Option<PathBuf>,
     },
 }
 
-const PROVISIONED_ENTITLEMENTS: &[&str] = &[
-    "application-identifier",
-    "aps-environment",
-    "beta-reports-active",
-    "com.apple.application-identifier",
-    "com.apple.developer.aps-environment",
-    "com.apple.developer.associated-domains",
-    "com.apple.developer.associated-domains.applinks.read-write",
-    "com.apple.developer.contacts.notes",
-    "com.apple.developer.hid.virtual.device",
-    "com.apple.developer.icloud-container-environment",
-    "com.apple.developer.icloud-container-identifiers",
-    "com.apple.developer.icloud-services",
-    "com.apple.developer.maps",
-    "com.apple.developer.networking.custom-protocol",
-    "com.apple.developer.networking.networkextension",
-    "com.apple.developer.system-extension.install",
-    "com.apple.developer.team-identifier",
-    "com.apple.developer.ubiquity-container-identifiers",
-    "com.apple.developer.ubiquity-kvstore-identifier",
-    "com.apple.developer.usernotifications.communication",
-    "com.apple.developer.usernotifications.time-sensitive",
-    "com.apple.developer.weatherkit",
-    "com.apple.developer.web-browser.public-key-credential",
-    "com.apple.security.application-groups",
-    "com.apple.vm.device-access",
-    "com.apple.vm.networking",
-    "get-task-allow",
-    "keychain-access-groups",
-];
-
-fn remove_provisioned_entitlements(entitlements: &mut plist::Value) -> Result<()> {
-    let dictionary = entitlements
-        .as_dictionary_mut()
-        .context("Entitlements is not a dictionary")?;
-    for entitlement in PROVISIONED_ENTITLEMENTS {
-        dictionary.remove(entitlement);
-    }
-    Ok(())
+#[derive(Args)]
+struct InputArgs {
+    /// The app to strip entitlements from, or an .ipa or .pkg containing it
+    #[arg(required_unless_present_any = ["from_file", "from_stdin"])]
+    app_path: Option<PathBuf>,
+
+    /// Read the entitlements from a plist file, or codesign's text output without --xml, instead
+    /// of running codesign on an app
+    #[arg(long, value_name = "PATH", conflicts_with = "app_path")]
+    from_file: Option<PathBuf>,
+
+    /// Read the entitlements from stdin in the same formats as --from-file instead of running
+    /// codesign on an app
+    #[arg(long, conflicts_with_all = ["app_path", "from_file"])]
+    from_stdin: bool,
+
+    /// The format of the --from-file or --from-stdin entitlements
+    #[arg(long, value_enum, default_value_t = InputFormat::Auto)]
+    input_format: InputFormat,
+
+    /// What the app path is expected to be, checked before running codesign
+    #[arg(long, value_enum, default_value_t = TargetType::Auto)]
+    target_type: TargetType,
 }
 
-fn get_provisioned_entitlements(entitlements: &plist::Value) -> Result<Vec<&'static str>> {
-    let dictionary = entitlements
-        .as_dictionary()
-        .context("Entitlements is not a dictionary")?;
-    let mut provisioned_entitlements = Vec::new();
-    for entitlement in PROVISIONED_ENTITLEMENTS {
-        if dictionary.contains_key(entitlement) {
-            provisioned_entitlements.push(*entitlement);
+impl InputArgs {
+    fn input(&self) -> Result<Input> {
+        if let Some(input) = Input::plist(
+            self.from_file.as_deref(),
+            self.from_stdin,
+            self.input_format,
+        ) {
+            return Ok(input);
         }
+        let app_path = self.app_path.as_ref().context("No app path provided")?;
+        self.target_type.check(app_path)?;
+        Ok(Input::App(app_path.clone()))
+    }
+
+    fn load_entitlements(&self, codesign: &Codesign) -> Result<Option<plist::Value>> {
+        self.input()?.load_entitlements(codesign)
     }
-    Ok(provisioned_entitlements)
 }
 
-fn get_entitlements(app_path: &PathBuf) -> Result<plist::Value> {
-    let output = std::process::Command::new("/usr/bin/codesign")
-        .arg("--display")
-        .arg("--xml")
-        .arg("--entitlements")
-        .arg("-")
-        .arg(app_path)
-        .output()
-        .context("Failed to execute codesign")?;
+#[derive(Args)]
+struct StripInputArgs {
+    /// The apps to strip entitlements from, or .ipa or .pkg files containing them
+    #[arg(required_unless_present_any = ["from_file", "from_stdin"])]
+    app_paths: Vec<PathBuf>,
 
-    if !output.status.success() {
-        let stdout =
-            String::from_utf8(output.stdout).context("codesign stdout is not valid UTF-8")?;
-        let stderr =
-            String::from_utf8(output.stderr).context("codesign stderr is not valid UTF-8")?;
-        bail!(
-            "codesign failed with status {}, stdout: {}, stderr: {}",
-            output.status,
-            stdout,
-            stderr
-        );
+    /// Read the entitlements from a plist file, or codesign's text output without --xml, instead
+    /// of running codesign on an app
+    #[arg(long, value_name = "PATH", conflicts_with = "app_paths")]
+    from_file: Option<PathBuf>,
+
+    /// Read the entitlements from stdin in the same formats as --from-file instead of running
+    /// codesign on an app
+    #[arg(long, conflicts_with_all = ["app_paths", "from_file"])]
+    from_stdin: bool,
+
+    /// The format of the --from-file or --from-stdin entitlements
+    #[arg(long, value_enum, default_value_t = InputFormat::Auto)]
+    input_format: InputFormat,
+
+    /// What the app paths are expected to be, checked before running codesign
+    #[arg(long, value_enum, default_value_t = TargetType::Auto)]
+    target_type: TargetType,
+}
+
+impl StripInputArgs {
+    /// Checks every app path against the --target-type.
+    fn check_target_types(&self) -> Result<()> {
+        for app_path in &self.app_paths {
+            self.target_type.check(app_path)?;
+        }
+        Ok(())
     }
 
-    let entitlements = plist::from_bytes(&output.stdout)
-        .context("Failed to parse entitlements plist from codesign output")?;
-    Ok(entitlements)
+    fn single_input(&self) -> Result<Input> {
+        if let Some(input) = Input::plist(
+            self.from_file.as_deref(),
+            self.from_stdin,
+            self.input_format,
+        ) {
+            return Ok(input);
+        }
+        match self.app_paths.as_slice() {
+            [app_path] => Ok(Input::App(app_path.clone())),
+            [] => bail!("No app path provided"),
+            _ => bail!("--output-dir is required when stripping multiple apps"),
+        }
+    }
 }
 
-fn main() -> Result<()> {
-    let cli = Cli::parse();
+enum Input {
+    App(PathBuf),
+    File(PathBuf, InputFormat),
+    Stdin(InputFormat),
+}
 
-    match cli.command {
-        Commands::Strip {
-            app_path,
-            output_path,
-        } => {
-            let mut entitlements =
-                get_entitlements(&app_path).context("Failed to get entitlements from app")?;
-            remove_provisioned_entitlements(&mut entitlements)
-                .context("Failed to remove provisioned entitlements")?;
-
-            let writer = fs::File::create(output_path).context("Failed to create output file")?;
-            let buf_writer = BufWriter::new(writer);
-            plist::to_writer_xml(buf_writer, &entitlements)
-                .context("Failed to write stripped entitlements to file")?;
-        }
-        Commands::DryRun { app_path } => {
-            let entitlements =
-                get_entitlements(&app_path).context("Failed to get entitlements from app")?;
-            let provisioned_entitlements = get_provisioned_entitlements(&entitlements)
-                .context("Failed to get provisioned entitlements")?;
-
-            if provisioned_entitlements.is_empty() {
-                println!("No provisioned entitlements found");
-            } else {
-                println!("Provisioned entitlements:");
-                for entitlement in provisioned_entitlements {
-                    println!("- {}", entitlement);
+/// What kind of signed code an app path is. codesign reads bundles and standalone Mach-O files,
+/// such as command-line tools and dylibs, the same way, so this only catches the wrong path being
+/// given.
+#[derive(Clone, Copy, ValueEnum)]
+enum TargetType {
+    /// Accept anything, detecting .ipa and .pkg files by their extension
+    Auto,
+    /// A bundle directory, such as an app, app extension or framework
+    Bundle,
+    /// A standalone Mach-O executable or dylib
+    Binary,
+}
+
+impl TargetType {
+    fn check(self, path: &Path) -> Result<()> {
+        match self {
+            TargetType::Auto => {}
+            TargetType::Bundle => {
+                if !path.is_dir() {
+                    bail!("{} is not a bundle directory", path.display());
+                }
+            }
+            TargetType::Binary => {
+                if is_ipa(path) || is_pkg(path) {
+                    bail!("{} is an archive, not a binary", path.display());
+                }
+                if !path.is_file() {
+                    bail!("{} is not a binary file", path.display());
                 }
             }
         }
-        Commands::Completions { shell } => {
-            shell.generate(&mut Cli::command(), &mut std::io::stdout());
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum InputFormat {
+    /// Detect XML and binary plists, falling back to codesign's text output
+    Auto,
+    /// An XML plist
+    Xml,
+    /// A binary plist
+    Binary,
+}
+
+impl Input {
+    /// The --from-file or --from-stdin input, if either was given, which takes the place of running
+    /// codesign on an app for every command that reads entitlements.
+    fn plist(from_file: Option<&Path>, from_stdin: bool, format: InputFormat) -> Option<Input> {
+        if let Some(from_file) = from_file {
+            Some(Input::File(from_file.to_path_buf(), format))
+        } else if from_stdin {
+            Some(Input::Stdin(format))
+        } else {
+            None
+        }
+    }
+
+    fn app_path(&self) -> Option<&Path> {
+        match self {
+            Input::App(app_path) => Some(app_path),
+            Input::File(..) | Input::Stdin(_) => None,
+        }
+    }
+
+    /// Loads the entitlements, or returns `None` if they come from an app and codesign isn't
+    /// executed.
+    fn load_entitlements(&self, codesign: &Codesign) -> Result<Option<plist::Value>> {
+        match self {
+            Input::App(app_path) if is_ipa(app_path) => extract_ipa(app_path)
+                .and_then(|extracted| codesign.get_entitlements(extracted.app_path()))
+                .context("Failed to get entitlements from ipa"),
+            Input::App(app_path) if is_pkg(app_path) => expand_pkg(&pkgutil_path(), app_path)
+                .and_then(|expanded| codesign.get_entitlements(expanded.app_path()))
+                .context("Failed to get entitlements from pkg"),
+            Input::App(app_path) => codesign
+                .get_entitlements_cached(app_path)
+                .context("Failed to get entitlements from app"),
+            Input::File(path, format) => fs::read(path)
+                .context("Failed to read entitlements file")
+                .and_then(|bytes| {
+                    parse_input_entitlements(&bytes, *format, &path.display().to_string(), codesign)
+                })
+                .map(Some)
+                .context("Failed to get entitlements from file"),
+            Input::Stdin(format) => read_stdin()
+                .and_then(|bytes| parse_input_entitlements(&bytes, *format, "stdin", codesign))
+                .map(Some)
+                .context("Failed to get entitlements from stdin"),
         }
     }
-    Ok(())
+
+    fn signing_info(&self, codesign: &Codesign) -> Result<Option<SigningInfo>> {
+        let signing_info = match self {
+            Input::App(app_path) if is_ipa(app_path) => extract_ipa(app_path)
+                .and_then(|extracted| codesign.get_signing_info(extracted.app_path()))?,
+            Input::App(app_path) if is_pkg(app_path) => expand_pkg(&pkgutil_path(), app_path)
+                .and_then(|expanded| codesign.get_signing_info(expanded.app_path()))?,
+            Input::App(app_path) => codesign.get_signing_info(app_path)?,
+            Input::File(..) | Input::Stdin(_) => bail!("Only apps have signing info"),
+        };
+        Ok(signing_info)
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+#[derive(Args)]
+struct EntitlementArgs {
+    /// An additional entitlement to strip, can be given multiple times
+    #[arg(long, value_name = "ENTITLEMENT")]
+    extra: Vec<String>,
+
+    /// Also strip any entitlement starting with this prefix, can be given multiple times
+    #[arg(long, value_name = "PREFIX")]
+    strip_prefix: Vec<String>,
+
+    /// Strip the entitlements listed in this file instead of the built-in provisioned
+    /// entitlements, either one per line or as a JSON array
+    #[arg(long, value_name = "PATH")]
+    list_file: Option<PathBuf>,
+
+    /// Also strip the entitlements debug builds have so debuggers can attach, such as
+    /// com.apple.security.get-task-allow, which aren't stripped by default
+    #[arg(long)]
+    strip_debug: bool,
 
-    use std::collections::HashSet;
+    /// Only consider the built-in provisioned entitlements introduced in this macOS version or
+    /// earlier, such as 12 or 10.15, leaving newer ones alone
+    #[arg(long, value_name = "VERSION", conflicts_with = "list_file")]
+    since: Option<MacosVersion>,
 
-    fn xml_to_plist_value(entitlements_xml: &[u8]) -> plist::Value {
-        plist::from_bytes(entitlements_xml).unwrap()
+    /// Replace the value of a string entitlement instead of stripping it, can be given multiple
+    /// times
+    #[arg(long, value_name = "ENTITLEMENT=REPLACEMENT", value_parser = parse_redaction)]
+    redact: Vec<(String, String)>,
+
+    /// Fail if any entitlement left after stripping isn't listed in this file, either one per
+    /// line or as a JSON array
+    #[arg(long, value_name = "ALLOWLIST")]
+    fail_on_unknown_key: Option<PathBuf>,
+
+    /// Only strip the application groups starting with this prefix, such as the team ID, instead
+    /// of the whole com.apple.security.application-groups entitlement, can be given multiple times
+    #[arg(long, value_name = "PREFIX")]
+    group_prefix: Vec<String>,
+
+    /// Also strip entitlements from dictionaries nested inside entitlement values, at any depth.
+    /// Dictionaries inside arrays are left alone
+    #[arg(long)]
+    deep: bool,
+}
+
+fn parse_codesign_arg(arg: &str) -> Result<String, String> {
+    check_codesign_arg(arg).map_err(|error| error.to_string())?;
+    Ok(arg.to_string())
+}
+
+/// Resolves the `--keep-only` values, reading the ones that are files as entitlement lists.
+fn resolve_keep_only(values: &[String]) -> Result<Vec<String>> {
+    let mut keep_only = Vec::new();
+    for value in values {
+        if Path::new(value).is_file() {
+            let contents = fs::read_to_string(value)
+                .with_context(|| format!("Failed to read --keep-only file {}", value))?;
+            keep_only.extend(
+                parse_entitlement_list(&contents)
+                    .with_context(|| format!("Failed to parse --keep-only file {}", value))?,
+            );
+        } else {
+            keep_only.push(value.clone());
+        }
     }
+    Ok(keep_only)
+}
+
+fn parse_redaction(redaction: &str) -> Result<(String, String), String> {
+    let (entitlement, replacement) = redaction
+        .split_once('=')
+        .ok_or_else(|| "expected ENTITLEMENT=REPLACEMENT".to_string())?;
+    Ok((entitlement.to_string(), replacement.to_string()))
+}
 
-    fn remove_provisioned_entitlements_to_string(entitlements_xml: &[u8]) -> String {
-        let mut entitlements = xml_to_plist_value(entitlements_xml);
-        remove_provisioned_entitlements(&mut entitlements).unwrap();
-        let mut writer = Vec::new();
-        let write_options = plist::XmlWriteOptions::default().indent(0, 0);
-        plist::to_writer_xml_with_options(&mut writer, &entitlements, &write_options).unwrap();
-        String::from_utf8(writer).unwrap()
+impl EntitlementArgs {
+    fn rules(&self) -> Result<StripRules> {
+        let base = match &self.list_file {
+            Some(list_file) => {
+                let contents = fs::read_to_string(list_file)
+                    .context("Failed to read entitlement list file")?;
+                parse_entitlement_list(&contents)
+                    .context("Failed to parse entitlement list file")?
+            }
+            None => match self.since {
+                Some(since) => provisioned_entitlements_since(since),
+                None => PROVISIONED_ENTITLEMENTS.to_vec(),
+            }
+            .into_iter()
+            .map(String::from)
+            .collect(),
+        };
+        let allowlist = match &self.fail_on_unknown_key {
+            Some(allowlist_file) => {
+                let contents =
+                    fs::read_to_string(allowlist_file).context("Failed to read allowlist file")?;
+                Some(parse_entitlement_list(&contents).context("Failed to parse allowlist file")?)
+            }
+            None => None,
+        };
+        let debug = if self.strip_debug {
+            DEBUG_ENTITLEMENTS
+                .iter()
+                .map(|entitlement| entitlement.to_string())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        Ok(StripRules {
+            base,
+            allowlist,
+            extra: self.extra.clone(),
+            debug,
+            strip_prefixes: self.strip_prefix.clone(),
+            redactions: self.redact.clone(),
+            group_prefixes: self.group_prefix.clone(),
+            deep: self.deep,
+            keep_only: None,
+            only: false,
+            ambiguous: AmbiguousPolicy::Allow,
+            require_entitlements: false,
+            dedupe_arrays: false,
+            strip_empty: false,
+            only_provisioned: false,
+        })
     }
+}
 
-    #[test]
-    fn test_remove_provisioned_entitlements() {
-        let entitlements_xml = br#"<?xml version="1.0" encoding="UTF-8"?><!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "https://www.apple.com/DTDs/PropertyList-1.0.dtd"><plist version="1.0"><dict><key>com.apple.application-identifier</key><string>AAAAAAAAAA.com.example.example</string><key>com.apple.developer.aps-environment</key><string>production</string><key>com.apple.developer.team-identifier</key><string>AAAAAAAAAA</string><key>com.apple.security.automation.apple-events</key><true/><key>com.apple.security.device.audio-input</key><true/><key>com.apple.security.device.camera</key><true/></dict></plist>"#;
-        let stripped_xml =
-            remove_provisioned_entitlements_to_string(entitlements_xml).replace('\n', "");
-        let expected = r#"<?xml version="1.0" encoding="UTF-8"?><!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd"><plist version="1.0"><dict><key>com.apple.security.device.camera</key><true/><key>com.apple.security.device.audio-input</key><true/><key>com.apple.security.automation.apple-events</key><true/></dict></plist>"#;
-        assert_eq!(stripped_xml, expected);
+/// The entitlements to strip, resolved from [`EntitlementArgs`].
+struct StripRules {
+    base: Vec<String>,
+    extra: Vec<String>,
+    /// The [`DEBUG_ENTITLEMENTS`] to strip with `--strip-debug`, which are reported separately
+    /// from the provisioned ones.
+    debug: Vec<String>,
+    strip_prefixes: Vec<String>,
+    redactions: Vec<(String, String)>,
+    group_prefixes: Vec<String>,
+    /// Whether to also strip entitlements nested inside dictionary values.
+    deep: bool,
+    /// The entitlements allowed to be left after stripping, if restricted.
+    allowlist: Option<Vec<String>>,
+    /// The only entitlements to leave, from `--keep-only`, in which case every other entitlement
+    /// is stripped.
+    keep_only: Option<Vec<String>>,
+    /// Whether `base` is the single entitlement from `--only`, which is warned about if it isn't
+    /// present.
+    only: bool,
+    /// What to do before removing any [`AMBIGUOUS_ENTITLEMENTS`].
+    ambiguous: AmbiguousPolicy,
+    /// Whether to fail instead of stripping entitlements that are empty to begin with.
+    require_entitlements: bool,
+    /// Whether to remove duplicate strings from array entitlements after stripping.
+    dedupe_arrays: bool,
+    /// Whether to remove entitlements with empty values after stripping.
+    strip_empty: bool,
+    /// Whether to keep only the removed entitlements instead of the stripped ones.
+    only_provisioned: bool,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum AmbiguousPolicy {
+    Allow,
+    Warn,
+    Error,
+}
+
+impl StripRules {
+    /// Replaces the entitlements to strip with just `entitlement`, for `--only`.
+    fn only(self, entitlement: String) -> Self {
+        StripRules {
+            base: vec![entitlement],
+            only: true,
+            ..self
+        }
     }
 
-    #[test]
-    fn test_list_provisioned_entitlements() {
-        let entitlements_xml = br#"<?xml version="1.0" encoding="UTF-8"?><!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "https://www.apple.com/DTDs/PropertyList-1.0.dtd"><plist version="1.0"><dict><key>com.apple.application-identifier</key><string>AAAAAAAAAA.com.example.example</string><key>com.apple.developer.aps-environment</key><string>production</string><key>com.apple.developer.team-identifier</key><string>AAAAAAAAAA</string><key>com.apple.security.automation.apple-events</key><true/><key>com.apple.security.device.audio-input</key><true/><key>com.apple.security.device.camera</key><true/></dict></plist>"#;
-        let entitlements = xml_to_plist_value(entitlements_xml);
-        assert_eq!(
-            get_provisioned_entitlements(&entitlements).unwrap(),
-            [
-                "com.apple.application-identifier",
-                "com.apple.developer.aps-environment",
-                "com.apple.developer.team-identifier",
-            ]
+    /// Strips every entitlement except `keep_only` instead of the listed ones, for `--keep-only`.
+    fn keep_only(self, keep_only: Vec<String>) -> Self {
+        StripRules {
+            base: Vec::new(),
+            keep_only: Some(keep_only),
+            ..self
+        }
+    }
+
+    /// The entitlements not listed by `--keep-only`, if given.
+    fn unlisted_entitlements(&self, entitlements: &plist::Value) -> Result<Vec<String>> {
+        let Some(keep_only) = &self.keep_only else {
+            return Ok(Vec::new());
+        };
+        Ok(entitlements
+            .as_dictionary()
+            .ok_or_else(|| not_a_dictionary(entitlements))?
+            .keys()
+            .filter(|entitlement| !keep_only.contains(entitlement))
+            .cloned()
+            .collect())
+    }
+
+    /// Every listed entitlement to strip, including the debug ones.
+    fn strip_set(&self, keep: &[&str]) -> Vec<&str> {
+        let mut strip_set = self.provisioned_strip_set(keep);
+        for entitlement in &self.debug {
+            if !keep.contains(&entitlement.as_str()) && !strip_set.contains(&entitlement.as_str()) {
+                strip_set.push(entitlement);
+            }
+        }
+        strip_set
+    }
+
+    /// The listed entitlements to strip that are treated as provisioned, without the debug ones.
+    fn provisioned_strip_set(&self, keep: &[&str]) -> Vec<&str> {
+        let base: Vec<&str> = self.base.iter().map(String::as_str).collect();
+        let extra: Vec<&str> = self.extra.iter().map(String::as_str).collect();
+        strip_set(&base, &extra, keep)
+    }
+
+    /// Whether an entitlement is only stripped because of `--strip-debug`.
+    fn is_debug(&self, entitlement: &str) -> bool {
+        self.debug.iter().any(|debug| debug == entitlement)
+            && !self.provisioned_strip_set(&[]).contains(&entitlement)
+    }
+
+    /// The debug entitlements present that `--strip-debug` would strip.
+    fn debug_entitlements(&self, entitlements: &plist::Value) -> Result<Vec<String>> {
+        let debug: Vec<&str> = self
+            .debug
+            .iter()
+            .map(String::as_str)
+            .filter(|entitlement| self.is_debug(entitlement))
+            .collect();
+        let mut debug_entitlements: Vec<String> = get_present_entitlements(entitlements, &debug)
+            .context("Failed to get debug entitlements")?
+            .into_iter()
+            .map(String::from)
+            .collect();
+        if self.deep {
+            debug_entitlements.extend(
+                remove_nested_entitlements(&mut entitlements.clone(), &debug)
+                    .context("Failed to get nested debug entitlements")?,
+            );
+        }
+        Ok(debug_entitlements)
+    }
+
+    fn strip_prefixes(&self) -> Vec<&str> {
+        self.strip_prefixes.iter().map(String::as_str).collect()
+    }
+
+    fn warn_ineffective_keep(&self, keep: &[String]) {
+        let full_strip_set = self.strip_set(&[]);
+        let strip_prefixes = self.strip_prefixes();
+        for entitlement in keep {
+            if !full_strip_set.contains(&entitlement.as_str())
+                && !strip_prefixes
+                    .iter()
+                    .any(|prefix| entitlement.starts_with(prefix))
+            {
+                eprintln!(
+                    "Warning: {} is not a provisioned entitlement, --keep has no effect",
+                    entitlement
+                );
+            }
+        }
+    }
+
+    fn provisioned_entitlements(&self, entitlements: &plist::Value) -> Result<Vec<String>> {
+        let mut provisioned_entitlements: Vec<String> =
+            get_present_entitlements(entitlements, &self.provisioned_strip_set(&[]))
+                .context("Failed to get provisioned entitlements")?
+                .into_iter()
+                .map(String::from)
+                .collect();
+        let prefixed_entitlements = get_prefixed_entitlements(entitlements, &self.strip_prefixes())
+            .context("Failed to get prefixed entitlements")?;
+        for entitlement in prefixed_entitlements
+            .into_iter()
+            .chain(self.unlisted_entitlements(entitlements)?)
+        {
+            if !provisioned_entitlements.contains(&entitlement) {
+                provisioned_entitlements.push(entitlement);
+            }
+        }
+        if self.deep {
+            provisioned_entitlements.extend(
+                remove_nested_entitlements(
+                    &mut entitlements.clone(),
+                    &self.provisioned_strip_set(&[]),
+                )
+                .context("Failed to get nested provisioned entitlements")?,
+            );
+        }
+        Ok(provisioned_entitlements)
+    }
+
+    /// Strips the entitlements, returning the ones that were removed. Redacted entitlements are
+    /// kept. With --only-provisioned, the removed top-level entitlements are left instead.
+    fn strip(&self, entitlements: &mut plist::Value, keep: &[&str]) -> Result<Vec<String>> {
+        if self.require_entitlements
+            && entitlements
+                .as_dictionary()
+                .is_some_and(plist::Dictionary::is_empty)
+        {
+            bail!("The app has no entitlements, check it's the right build and that it's signed");
+        }
+        let original = self.only_provisioned.then(|| entitlements.clone());
+        if log::log_enabled!(log::Level::Trace) {
+            log::trace!(
+                "Entitlements before stripping:\n{}",
+                to_xml_string(entitlements)?
+            );
+        }
+
+        let redactions: Vec<(&str, &str)> = self
+            .redactions
+            .iter()
+            .map(|(entitlement, replacement)| (entitlement.as_str(), replacement.as_str()))
+            .collect();
+        let mut keep = keep.to_vec();
+        keep.extend(
+            redact_entitlements(entitlements, &redactions)
+                .context("Failed to redact entitlements")?,
+        );
+        if !self.group_prefixes.is_empty() {
+            let group_prefixes: Vec<&str> =
+                self.group_prefixes.iter().map(String::as_str).collect();
+            if filter_array_entitlement(entitlements, APPLICATION_GROUPS, &group_prefixes)
+                .context("Failed to filter application groups")?
+            {
+                keep.push(APPLICATION_GROUPS);
+            }
+        }
+        let keep = keep.as_slice();
+
+        let prefixed_entitlements = get_prefixed_entitlements(entitlements, &self.strip_prefixes())
+            .context("Failed to get prefixed entitlements")?;
+        let unlisted_entitlements = self.unlisted_entitlements(entitlements)?;
+        let mut to_remove = self.strip_set(keep);
+        to_remove.extend(
+            prefixed_entitlements
+                .iter()
+                .chain(&unlisted_entitlements)
+                .map(String::as_str)
+                .filter(|entitlement| !keep.contains(entitlement)),
         );
+        let mut removed: Vec<String> = get_present_entitlements(entitlements, &to_remove)
+            .context("Failed to get provisioned entitlements")?
+            .into_iter()
+            .map(String::from)
+            .collect();
+        self.check_ambiguous(&removed)?;
+        remove_entitlements(entitlements, &to_remove)
+            .context("Failed to remove provisioned entitlements")?;
+        if self.deep {
+            removed.extend(
+                remove_nested_entitlements(entitlements, &to_remove)
+                    .context("Failed to remove nested provisioned entitlements")?,
+            );
+        }
+        if self.only && removed.is_empty() {
+            eprintln!(
+                "Warning: {} is not present, nothing was stripped",
+                self.base.join(", ")
+            );
+        }
+
+        if self.dedupe_arrays {
+            for entitlement in dedupe_array_entitlements(entitlements)
+                .context("Failed to remove duplicate array entries")?
+            {
+                log::debug!("Removed duplicate entries from {}", entitlement);
+            }
+        }
+        if self.strip_empty {
+            for entitlement in remove_empty_entitlements(entitlements)
+                .context("Failed to remove empty entitlements")?
+            {
+                log::debug!("Removed {}, which was empty", entitlement);
+            }
+        }
+
+        if log::log_enabled!(log::Level::Trace) {
+            log::trace!(
+                "Entitlements after stripping:\n{}",
+                to_xml_string(entitlements)?
+            );
+        }
+        self.check_allowlist(entitlements)?;
+        if let Some(original) = original {
+            let removed: Vec<&str> = removed.iter().map(String::as_str).collect();
+            *entitlements = select_entitlements(&original, &removed)
+                .context("Failed to select provisioned entitlements")?;
+        }
+        Ok(removed)
     }
 
-    #[test]
-    fn test_provisioned_entitlements_sorted() {
-        assert!(PROVISIONED_ENTITLEMENTS.is_sorted());
+    /// Warns about, or fails on, removing any [`AMBIGUOUS_ENTITLEMENTS`].
+    fn check_ambiguous(&self, removed: &[String]) -> Result<()> {
+        let ambiguous: Vec<&str> = removed
+            .iter()
+            .map(String::as_str)
+            .filter(|entitlement| AMBIGUOUS_ENTITLEMENTS.contains(entitlement))
+            .collect();
+        if ambiguous.is_empty() {
+            return Ok(());
+        }
+        match self.ambiguous {
+            AmbiguousPolicy::Allow => {}
+            AmbiguousPolicy::Warn => eprintln!(
+                "Warning: removing {}, which some apps manage themselves, use --keep if it's needed",
+                ambiguous.join(", ")
+            ),
+            AmbiguousPolicy::Error => bail!(
+                "Not removing {}, which some apps manage themselves, use --keep to keep it",
+                ambiguous.join(", ")
+            ),
+        }
+        Ok(())
     }
 
-    #[test]
-    fn test_provisioned_entitlements_unique() {
-        let unique: HashSet<&&str> = PROVISIONED_ENTITLEMENTS.iter().collect();
-        assert_eq!(unique.len(), PROVISIONED_ENTITLEMENTS.len());
+    /// Fails if any of the stripped entitlements aren't in the allowlist.
+    fn check_allowlist(&self, stripped: &plist::Value) -> Result<()> {
+        let Some(allowlist) = &self.allowlist else {
+            return Ok(());
+        };
+        let unknown: Vec<&str> = stripped
+            .as_dictionary()
+            .ok_or_else(|| not_a_dictionary(stripped))?
+            .keys()
+            .filter(|entitlement| !allowlist.contains(entitlement))
+            .map(String::as_str)
+            .collect();
+        if !unknown.is_empty() {
+            bail!("Entitlements not in the allowlist: {}", unknown.join(", "));
+        }
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    /// Human-readable text
+    Text,
+    /// JSON
+    Json,
+}
+
+#[derive(Clone, Copy, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum ColorMode {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    fn write_global(self) {
+        let choice = match self {
+            ColorMode::Auto => anstream::ColorChoice::Auto,
+            ColorMode::Always => anstream::ColorChoice::Always,
+            ColorMode::Never => anstream::ColorChoice::Never,
+        };
+        choice.write_global();
+    }
+}
+
+/// The prefix of developer entitlements, most of which are provisioned.
+const DEVELOPER_PREFIX: &str = "com.apple.developer.";
+
+const HEADER_STYLE: anstyle::Style = anstyle::Style::new().bold();
+const PROVISIONED_STYLE: anstyle::Style = anstyle::AnsiColor::Red.on_default();
+
+/// How much informational output to print. Results, warnings and errors are always printed.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Verbosity {
+    Quiet,
+    Normal,
+}
+
+impl Verbosity {
+    fn info(self, message: impl std::fmt::Display) {
+        if self != Verbosity::Quiet {
+            println!("{}", message);
+        }
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+enum PlistFormat {
+    /// An XML plist
+    Xml,
+    /// A binary plist
+    Binary,
+    /// JSON, like `plutil -convert json`, with data as base64 strings and dates as ISO 8601
+    /// strings
+    PlistJson,
+}
+
+/// The DOCTYPE the plist crate writes, which is also what Apple's tools write.
+const PLIST_DOCTYPE: &str = r#"<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">"#;
+
+/// The XML declaration the plist crate writes at the start of XML plists.
+const XML_DECLARATION: &str = r#"<?xml version="1.0" encoding="UTF-8"?>"#;
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Doctype {
+    /// The DOCTYPE with an http:// DTD URL, as written by Apple's tools
+    Http,
+    /// The DOCTYPE with an https:// DTD URL
+    Https,
+    /// No DOCTYPE
+    None,
+}
+
+impl Doctype {
+    /// Replaces the DOCTYPE of an XML plist written by the plist crate.
+    fn apply(self, xml: Vec<u8>) -> Vec<u8> {
+        let replacement = match self {
+            Doctype::Http => return xml,
+            Doctype::Https => PLIST_DOCTYPE.replacen("http://", "https://", 1) + "\n",
+            Doctype::None => String::new(),
+        };
+        let doctype = format!("{}\n", PLIST_DOCTYPE);
+        match String::from_utf8(xml) {
+            Ok(xml) => xml.replacen(&doctype, &replacement, 1).into_bytes(),
+            Err(error) => error.into_bytes(),
+        }
+    }
+}
+
+#[derive(Args, Clone, Copy)]
+struct PlistOutputArgs {
+    /// The plist format to write the entitlements in, xml if it's not given
+    #[arg(long = "output-format", value_enum, env = "STRIPPER_OUTPUT_FORMAT")]
+    format: Option<PlistFormat>,
+
+    /// The DOCTYPE to write in XML plists
+    #[arg(long, value_enum, env = "STRIPPER_DOCTYPE", default_value_t = Doctype::Http)]
+    doctype: Doctype,
+
+    /// Don't write a DOCTYPE in XML plists, the same as --doctype none
+    #[arg(long)]
+    no_doctype: bool,
+
+    /// Don't write the <?xml ...?> declaration in XML plists, which with --no-doctype leaves just
+    /// the <plist> element
+    #[arg(long)]
+    no_xml_declaration: bool,
+
+    /// Indent XML plists by this many spaces, or tabs with --tabs, for each level. 0 writes the
+    /// entitlements dictionary on one line. JSON is indented by 2 spaces unless this is given
+    #[arg(long, value_name = "N")]
+    indent: Option<usize>,
+
+    /// Indent XML plists with tabs, which is the default, like Apple's tools
+    #[arg(long)]
+    tabs: bool,
+
+    /// Write straight to the output file instead of writing a temporary file and renaming it over
+    /// the output. This is always done for FIFOs and other special files
+    #[arg(long)]
+    no_atomic: bool,
+
+    /// Sort the keys of every dictionary before writing, so the output is byte-identical whatever
+    /// order the input entitlements were in
+    #[arg(long)]
+    sort_output_keys: bool,
+}
+
+impl PlistOutputArgs {
+    /// The format to write plists in.
+    fn format(self) -> PlistFormat {
+        self.format.unwrap_or(PlistFormat::Xml)
+    }
+
+    /// The options for writing to one of several output files, in the format its extension
+    /// implies unless --output-format is given.
+    fn for_path(self, path: &Path) -> Result<Self> {
+        if self.format.is_some() {
+            return Ok(self);
+        }
+        let format = match path.extension().and_then(std::ffi::OsStr::to_str) {
+            Some("xml") => PlistFormat::Xml,
+            Some("plist") => PlistFormat::Binary,
+            Some("json") => PlistFormat::PlistJson,
+            _ => bail!(
+                "Can't tell which format to write {} in, use a .xml, .plist or .json extension \
+                 or --output-format",
+                path.display()
+            ),
+        };
+        Ok(PlistOutputArgs {
+            format: Some(format),
+            ..self
+        })
+    }
+
+    /// Applies the DOCTYPE and XML declaration options to an XML plist written by the plist crate.
+    fn apply_xml_header(self, xml: Vec<u8>) -> Vec<u8> {
+        let doctype = if self.no_doctype {
+            Doctype::None
+        } else {
+            self.doctype
+        };
+        let xml = doctype.apply(xml);
+        if !self.no_xml_declaration {
+            return xml;
+        }
+        match xml.strip_prefix(XML_DECLARATION.as_bytes()) {
+            Some(rest) => rest.strip_prefix(b"\n").unwrap_or(rest).to_vec(),
+            None => xml,
+        }
+    }
+
+    fn xml_write_options(self) -> plist::XmlWriteOptions {
+        let indent_char = if self.tabs || self.indent.is_none() {
+            b'\t'
+        } else {
+            b' '
+        };
+        plist::XmlWriteOptions::default().indent(indent_char, self.indent.unwrap_or(1))
+    }
+
+    /// Writes entitlements as JSON, pretty-printed unless --indent is 0.
+    fn write_json<W: Write>(self, writer: &mut W, entitlements: &plist::Value) -> Result<()> {
+        let json = PlistJson(entitlements);
+        match self.indent.unwrap_or(2) {
+            0 => serde_json::to_writer(writer, &json),
+            indent => {
+                let indent = if self.tabs {
+                    vec![b'\t'; indent]
+                } else {
+                    vec![b' '; indent]
+                };
+                let formatter = serde_json::ser::PrettyFormatter::with_indent(&indent);
+                json.serialize(&mut serde_json::Serializer::with_formatter(
+                    writer, formatter,
+                ))
+            }
+        }
+        .context("Failed to write stripped entitlements as JSON")
+    }
+}
+
+/// Serializes a plist value as JSON for `--output-format plist-json`. Dictionaries keep their
+/// order, and the types JSON doesn't have are mapped like `plutil` does where it can: data
+/// becomes a base64 string, dates an ISO 8601 string and UIDs their number. Reals that are NaN or
+/// infinite can't be written.
+struct PlistJson<'a>(&'a plist::Value);
+
+impl Serialize for PlistJson<'_> {
+    fn serialize<S: serde::Serializer>(
+        &self,
+        serializer: S,
+    ) -> std::result::Result<S::Ok, S::Error> {
+        use base64::Engine;
+        use serde::ser::{Error, SerializeMap, SerializeSeq};
+
+        match self.0 {
+            plist::Value::Array(array) => {
+                let mut seq = serializer.serialize_seq(Some(array.len()))?;
+                for value in array {
+                    seq.serialize_element(&PlistJson(value))?;
+                }
+                seq.end()
+            }
+            plist::Value::Dictionary(dictionary) => {
+                let mut map = serializer.serialize_map(Some(dictionary.len()))?;
+                for (key, value) in dictionary {
+                    map.serialize_entry(key, &PlistJson(value))?;
+                }
+                map.end()
+            }
+            plist::Value::Boolean(boolean) => serializer.serialize_bool(*boolean),
+            plist::Value::Data(data) => {
+                serializer.serialize_str(&base64::engine::general_purpose::STANDARD.encode(data))
+            }
+            plist::Value::Date(date) => serializer.serialize_str(&date.to_xml_format()),
+            plist::Value::Real(real) if real.is_finite() => serializer.serialize_f64(*real),
+            plist::Value::Real(real) => Err(S::Error::custom(format!(
+                "{} can't be represented in JSON",
+                real
+            ))),
+            plist::Value::Integer(integer) => match integer.as_signed() {
+                Some(signed) => serializer.serialize_i64(signed),
+                None => serializer.serialize_u64(
+                    integer
+                        .as_unsigned()
+                        .ok_or_else(|| S::Error::custom("Integer out of range"))?,
+                ),
+            },
+            plist::Value::String(string) => serializer.serialize_str(string),
+            plist::Value::Uid(uid) => serializer.serialize_u64(uid.get()),
+            _ => Err(S::Error::custom("Unsupported plist value")),
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct VersionInfo<'a> {
+    version: &'a str,
+    codesign_path: &'a Path,
+}
+
+/// The JSON behind Apple's entitlements reference at
+/// <https://developer.apple.com/documentation/bundleresources/entitlements>.
+const APPLE_ENTITLEMENTS_URL: &str =
+    "https://developer.apple.com/tutorials/data/documentation/bundleresources/entitlements.json";
+
+/// The entitlements in Apple's entitlements reference when this version was released, in the
+/// same format as `--list-file`.
+const APPLE_ENTITLEMENTS_SNAPSHOT: &str = include_str!("apple_entitlements.txt");
+
+/// Downloads `url` with curl, which is used rather than an HTTP client as it's already installed
+/// wherever codesign is.
+fn fetch_url(url: &str) -> Result<Vec<u8>> {
+    log::debug!("Fetching {}", url);
+    let output = std::process::Command::new("curl")
+        .args(["--fail", "--silent", "--show-error", "--location", "--"])
+        .arg(url)
+        .output()
+        .context("Failed to execute curl")?;
+    if !output.status.success() {
+        bail!(
+            "curl failed with status {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim_end()
+        );
+    }
+    Ok(output.stdout)
+}
+
+/// Finds the entitlement keys in the JSON of Apple's documentation, which are any strings shaped
+/// like a reverse-DNS key starting with `com.apple.`, so the exact layout of the JSON doesn't
+/// matter. The keys are returned in sorted order.
+fn parse_apple_entitlements(json: &[u8]) -> Result<Vec<String>> {
+    fn collect(value: &serde_json::Value, keys: &mut BTreeSet<String>) {
+        match value {
+            serde_json::Value::String(string) if is_apple_entitlement_key(string) => {
+                keys.insert(string.clone());
+            }
+            serde_json::Value::Array(values) => {
+                for value in values {
+                    collect(value, keys);
+                }
+            }
+            serde_json::Value::Object(object) => {
+                for (key, value) in object {
+                    if is_apple_entitlement_key(key) {
+                        keys.insert(key.clone());
+                    }
+                    collect(value, keys);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    let json: serde_json::Value = serde_json::from_slice(json).context("Invalid JSON")?;
+    let mut keys = BTreeSet::new();
+    collect(&json, &mut keys);
+    if keys.is_empty() {
+        bail!("No entitlements found");
+    }
+    Ok(keys.into_iter().collect())
+}
+
+fn is_apple_entitlement_key(string: &str) -> bool {
+    string.strip_prefix("com.apple.").is_some_and(|rest| {
+        rest.split('.').count() >= 2
+            && rest.split('.').all(|part| {
+                !part.is_empty()
+                    && part
+                        .chars()
+                        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+            })
+    })
+}
+
+/// Returns the `reference` entitlements that look provisioned, as they're for a capability, but
+/// aren't in [`PROVISIONED_ENTITLEMENTS`], in sorted order.
+fn missing_provisioned_entitlements(reference: &[String]) -> Vec<&str> {
+    let mut missing: Vec<&str> = reference
+        .iter()
+        .map(String::as_str)
+        .filter(|entitlement| {
+            entitlement.starts_with(DEVELOPER_PREFIX)
+                && !PROVISIONED_ENTITLEMENTS.contains(entitlement)
+        })
+        .collect();
+    missing.sort_unstable();
+    missing.dedup();
+    missing
+}
+
+fn format_version(info: &VersionInfo, format: OutputFormat) -> Result<String> {
+    match format {
+        OutputFormat::Text => Ok(format!("{}\n", info.version)),
+        OutputFormat::Json => {
+            let mut output = serde_json::to_string(info).context("Failed to serialize version")?;
+            output.push('\n');
+            Ok(output)
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct DryRunResult {
+    app: Option<PathBuf>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bundle_identifier: Option<String>,
+    provisioned_entitlements: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    debug_entitlements: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    values: Option<plist::Dictionary>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    arch: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    signing_info: Option<SigningInfo>,
+}
+
+impl DryRunResult {
+    /// Whether any provisioned entitlements, or debug ones with `--strip-debug`, were found.
+    fn found_entitlements(&self) -> bool {
+        !self.provisioned_entitlements.is_empty() || !self.debug_entitlements.is_empty()
+    }
+}
+
+fn dry_run(
+    input: &Input,
+    codesign: &Codesign,
+    rules: &StripRules,
+    show_values: bool,
+) -> Result<Option<DryRunResult>> {
+    let Some(entitlements) = input.load_entitlements(codesign)? else {
+        return Ok(None);
+    };
+    dry_run_entitlements(input, &entitlements, rules, show_values).map(Some)
+}
+
+fn dry_run_entitlements(
+    input: &Input,
+    entitlements: &plist::Value,
+    rules: &StripRules,
+    show_values: bool,
+) -> Result<DryRunResult> {
+    let provisioned_entitlements = rules.provisioned_entitlements(entitlements)?;
+    let debug_entitlements = rules.debug_entitlements(entitlements)?;
+    if rules.allowlist.is_some() {
+        rules.strip(&mut entitlements.clone(), &[])?;
+    }
+    let app = input.app_path().map(Path::to_path_buf);
+    let bundle_identifier = dry_run_bundle_identifier(app.as_deref(), entitlements)?;
+    let values = if show_values {
+        let keys: Vec<&str> = provisioned_entitlements
+            .iter()
+            .map(String::as_str)
+            .collect();
+        let values = get_entitlement_values(entitlements, &keys)
+            .context("Failed to get provisioned entitlement values")?;
+        Some(
+            values
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    } else {
+        None
+    };
+    Ok(DryRunResult {
+        app,
+        bundle_identifier,
+        provisioned_entitlements,
+        debug_entitlements,
+        values,
+        arch: None,
+        signing_info: None,
+    })
+}
+
+/// Returns the bundle identifier to show for an app, from its application identifier entitlement
+/// or otherwise its `Info.plist`.
+fn dry_run_bundle_identifier(
+    app: Option<&Path>,
+    entitlements: &plist::Value,
+) -> Result<Option<String>> {
+    if let Some(bundle_identifier) =
+        get_bundle_identifier(entitlements).context("Failed to get bundle identifier")?
+    {
+        return Ok(Some(bundle_identifier));
+    }
+    let Some(app) = app.filter(|app| app.is_dir()) else {
+        return Ok(None);
+    };
+    match read_bundle_identifier(app) {
+        Ok(bundle_identifier) => Ok(bundle_identifier),
+        Err(error) => {
+            log::debug!("Failed to read bundle identifier: {}", error);
+            Ok(None)
+        }
+    }
+}
+
+fn format_dry_run(
+    result: &DryRunResult,
+    format: OutputFormat,
+    verbosity: Verbosity,
+) -> Result<String> {
+    match format {
+        OutputFormat::Text => {
+            let mut output = String::new();
+            if let Some(bundle_identifier) = &result.bundle_identifier
+                && (verbosity != Verbosity::Quiet || result.found_entitlements())
+            {
+                output.push_str(&format!(
+                    "{HEADER_STYLE}Bundle identifier:{HEADER_STYLE:#} {}\n",
+                    bundle_identifier
+                ));
+            }
+            if result.provisioned_entitlements.is_empty() {
+                if verbosity != Verbosity::Quiet {
+                    output.push_str("No provisioned entitlements found\n");
+                }
+            } else {
+                output.push_str(&format!(
+                    "{HEADER_STYLE}Provisioned entitlements:{HEADER_STYLE:#}\n"
+                ));
+                for entitlement in &result.provisioned_entitlements {
+                    output.push_str(&format!(
+                        "- {PROVISIONED_STYLE}{}{PROVISIONED_STYLE:#}",
+                        entitlement
+                    ));
+                    if let Some(value) = result
+                        .values
+                        .as_ref()
+                        .and_then(|values| values.get(entitlement))
+                    {
+                        let value = serde_json::to_string(value)
+                            .context("Failed to serialize entitlement value")?;
+                        output.push_str(&format!(": {}", value));
+                    }
+                    output.push('\n');
+                }
+            }
+            if !result.debug_entitlements.is_empty() {
+                output.push_str(&format!(
+                    "{HEADER_STYLE}Debug entitlements:{HEADER_STYLE:#}\n"
+                ));
+                for entitlement in &result.debug_entitlements {
+                    output.push_str(&format!("- {}\n", entitlement));
+                }
+            }
+            if let Some(signing_info) = &result.signing_info {
+                output.push_str(&format_signing_info(signing_info));
+            }
+            Ok(output)
+        }
+        OutputFormat::Json => {
+            let mut output =
+                serde_json::to_string(result).context("Failed to serialize dry run result")?;
+            output.push('\n');
+            Ok(output)
+        }
+    }
+}
+
+fn format_signing_info(signing_info: &SigningInfo) -> String {
+    let mut output = format!("{HEADER_STYLE}Signing info:{HEADER_STYLE:#}\n");
+    let not_set = "not set".to_string();
+    output.push_str(&format!(
+        "- Identifier: {}\n",
+        signing_info.identifier.as_ref().unwrap_or(&not_set)
+    ));
+    output.push_str(&format!(
+        "- TeamIdentifier: {}\n",
+        signing_info.team_identifier.as_ref().unwrap_or(&not_set)
+    ));
+    for authority in &signing_info.authorities {
+        output.push_str(&format!("- Authority: {}\n", authority));
+    }
+    output
+}
+
+fn format_dry_run_bundles(
+    results: &[DryRunResult],
+    format: OutputFormat,
+    verbosity: Verbosity,
+) -> Result<String> {
+    match format {
+        OutputFormat::Text => {
+            let mut groups = Vec::new();
+            for result in results {
+                if verbosity == Verbosity::Quiet && !result.found_entitlements() {
+                    continue;
+                }
+                let header = match &result.arch {
+                    Some(arch) => arch.clone(),
+                    None => result
+                        .app
+                        .as_deref()
+                        .unwrap_or(Path::new("-"))
+                        .display()
+                        .to_string(),
+                };
+                groups.push(format!(
+                    "{HEADER_STYLE}{}:{HEADER_STYLE:#}\n{}",
+                    header,
+                    format_dry_run(result, format, verbosity)?
+                ));
+            }
+            Ok(groups.join("\n"))
+        }
+        OutputFormat::Json => {
+            let mut output =
+                serde_json::to_string(results).context("Failed to serialize dry run results")?;
+            output.push('\n');
+            Ok(output)
+        }
+    }
+}
+
+fn print_dry_run(result: &DryRunResult, format: OutputFormat, verbosity: Verbosity) -> Result<()> {
+    anstream::print!("{}", format_dry_run(result, format, verbosity)?);
+    Ok(())
+}
+
+fn format_entitlement_list(entitlements: &[&str], format: OutputFormat) -> Result<String> {
+    match format {
+        OutputFormat::Text => {
+            let mut output = String::new();
+            for entitlement in entitlements {
+                output.push_str(entitlement);
+                output.push('\n');
+            }
+            Ok(output)
+        }
+        OutputFormat::Json => {
+            let mut output =
+                serde_json::to_string(entitlements).context("Failed to serialize entitlements")?;
+            output.push('\n');
+            Ok(output)
+        }
+    }
+}
+
+/// What an entitlement grants, as printed by the explain command.
+#[derive(Serialize)]
+struct Explanation<'a> {
+    entitlement: &'a str,
+    description: Option<&'static str>,
+    provisioned: bool,
+    debug: bool,
+}
+
+impl<'a> Explanation<'a> {
+    fn new(entitlement: &'a str) -> Self {
+        Explanation {
+            entitlement,
+            description: describe_entitlement(entitlement),
+            provisioned: PROVISIONED_ENTITLEMENTS.contains(&entitlement),
+            debug: DEBUG_ENTITLEMENTS.contains(&entitlement),
+        }
+    }
+}
+
+fn format_explanations(entitlements: &[String], format: OutputFormat) -> Result<String> {
+    let explanations: Vec<Explanation> = entitlements
+        .iter()
+        .map(|entitlement| Explanation::new(entitlement))
+        .collect();
+    match format {
+        OutputFormat::Text => {
+            let mut output = String::new();
+            for explanation in &explanations {
+                output.push_str(explanation.entitlement);
+                output.push('\n');
+                output.push_str("  ");
+                output.push_str(
+                    explanation
+                        .description
+                        .unwrap_or("No description available."),
+                );
+                output.push('\n');
+                if explanation.provisioned {
+                    output.push_str(
+                        "  Provisioned: only a provisioning profile can grant it, so it's \
+                         stripped to re-sign the app without one.\n",
+                    );
+                } else if explanation.debug {
+                    output.push_str(
+                        "  Debug: distribution builds usually don't want it, so it's stripped \
+                         with --strip-debug.\n",
+                    );
+                }
+            }
+            Ok(output)
+        }
+        OutputFormat::Json => {
+            let mut output =
+                serde_json::to_string(&explanations).context("Failed to serialize explanations")?;
+            output.push('\n');
+            Ok(output)
+        }
+    }
+}
+
+/// An entitlement that's missing from one of two apps or has different values in each.
+#[derive(Serialize)]
+struct EntitlementDifference {
+    entitlement: String,
+    provisioned: bool,
+    first: Option<plist::Value>,
+    second: Option<plist::Value>,
+}
+
+fn compare_entitlements(
+    first: &plist::Value,
+    second: &plist::Value,
+) -> Result<Vec<EntitlementDifference>> {
+    let value = |entitlements: &plist::Value, entitlement: &str| -> Result<Option<plist::Value>> {
+        Ok(entitlements
+            .as_dictionary()
+            .ok_or_else(|| not_a_dictionary(entitlements))?
+            .get(entitlement)
+            .cloned())
+    };
+    get_differing_entitlements(&[first, second])?
+        .into_iter()
+        .map(|entitlement| {
+            Ok(EntitlementDifference {
+                provisioned: PROVISIONED_ENTITLEMENTS.contains(&entitlement.as_str()),
+                first: value(first, &entitlement)?,
+                second: value(second, &entitlement)?,
+                entitlement,
+            })
+        })
+        .collect()
+}
+
+fn format_comparison(
+    differences: &[EntitlementDifference],
+    format: OutputFormat,
+    verbosity: Verbosity,
+) -> Result<String> {
+    match format {
+        OutputFormat::Text => {
+            if differences.is_empty() {
+                if verbosity == Verbosity::Quiet {
+                    return Ok(String::new());
+                }
+                return Ok("No differences found\n".to_string());
+            }
+            let format_value = |value: &Option<plist::Value>| -> Result<String> {
+                match value {
+                    Some(value) => serde_json::to_string(value)
+                        .context("Failed to serialize entitlement value"),
+                    None => Ok("missing".to_string()),
+                }
+            };
+            let mut output = format!("{HEADER_STYLE}Differing entitlements:{HEADER_STYLE:#}\n");
+            for difference in differences {
+                if difference.provisioned {
+                    output.push_str(&format!(
+                        "- {PROVISIONED_STYLE}{} (provisioned){PROVISIONED_STYLE:#}",
+                        difference.entitlement
+                    ));
+                } else {
+                    output.push_str(&format!("- {}", difference.entitlement));
+                }
+                output.push_str(&format!(
+                    ": {} -> {}\n",
+                    format_value(&difference.first)?,
+                    format_value(&difference.second)?
+                ));
+            }
+            Ok(output)
+        }
+        OutputFormat::Json => {
+            let mut output =
+                serde_json::to_string(differences).context("Failed to serialize differences")?;
+            output.push('\n');
+            Ok(output)
+        }
+    }
+}
+
+/// Parses entitlements from a plist, or from codesign's text format if the input isn't a plist.
+fn parse_entitlements(bytes: &[u8]) -> Result<plist::Value> {
+    if bytes.iter().all(u8::is_ascii_whitespace) {
+        bail!("Entitlements input is empty");
+    }
+    if !bytes.trim_ascii_start().starts_with(b"<") && !bytes.starts_with(BINARY_PLIST_MAGIC) {
+        let text =
+            std::str::from_utf8(bytes).context("Entitlements input is not a plist or text")?;
+        return parse_entitlements_text(text).context("Failed to parse codesign entitlements text");
+    }
+    let entitlements = plist::from_bytes(bytes).context("Failed to parse entitlements plist")?;
+    Ok(entitlements)
+}
+
+fn read_entitlements_file(path: &Path) -> Result<plist::Value> {
+    let bytes = fs::read(path).context("Failed to read entitlements file")?;
+    parse_entitlements(&bytes)
+}
+
+fn read_stdin() -> Result<Vec<u8>> {
+    let mut bytes = Vec::new();
+    std::io::stdin()
+        .read_to_end(&mut bytes)
+        .context("Failed to read stdin")?;
+    Ok(bytes)
+}
+
+/// Parses the entitlements of an input, checking them for suspicious keys with --strict-parse and
+/// for an unexpected plist version, which fails with strip --strict.
+fn parse_input_entitlements(
+    bytes: &[u8],
+    format: InputFormat,
+    source: &str,
+    codesign: &Codesign,
+) -> Result<plist::Value> {
+    if codesign.strict_parses() {
+        warn_suspicious_keys(bytes, source);
+    }
+    check_plist_version(bytes, source, codesign.fails_on_plist_version())?;
+    match format {
+        InputFormat::Auto => parse_entitlements(bytes),
+        InputFormat::Xml => {
+            plist::from_reader_xml(bytes).context("Failed to parse entitlements as an XML plist")
+        }
+        InputFormat::Binary => {
+            if !bytes.starts_with(BINARY_PLIST_MAGIC) {
+                bail!("Entitlements are not a binary plist");
+            }
+            plist::from_bytes(bytes).context("Failed to parse entitlements as a binary plist")
+        }
+    }
+}
+
+/// The bytes every binary plist starts with.
+const BINARY_PLIST_MAGIC: &[u8] = b"bplist";
+
+#[derive(Serialize)]
+#[serde(rename_all = "lowercase")]
+enum AuditStatus {
+    Ok,
+    Error,
+}
+
+#[derive(Serialize)]
+struct AuditRecord {
+    app: PathBuf,
+    removed: Vec<String>,
+    output: Option<PathBuf>,
+    status: AuditStatus,
+    error: Option<String>,
+}
+
+impl AuditRecord {
+    /// Records the result of stripping an app, with its paths relative to `relative_to`.
+    fn new(
+        app: &Path,
+        output: Option<&Path>,
+        result: &Result<&Vec<String>, &anyhow::Error>,
+        relative_to: Option<&Path>,
+    ) -> Self {
+        let app = relative_path(app, relative_to).to_path_buf();
+        let output = output.map(|output| relative_path(output, relative_to).to_path_buf());
+        match result {
+            Ok(removed) => AuditRecord {
+                app,
+                removed: removed.to_vec(),
+                output,
+                status: AuditStatus::Ok,
+                error: None,
+            },
+            Err(error) => AuditRecord {
+                app,
+                removed: Vec::new(),
+                output,
+                status: AuditStatus::Error,
+                error: Some(format!("{:#}", error)),
+            },
+        }
+    }
+}
+
+/// A JSON Lines log of the result of stripping each app in a batch, flushed after every app so
+/// it stays valid if the process is interrupted.
+struct AuditLog {
+    writer: LineWriter<fs::File>,
+    /// The directory to log paths relative to, from `--relative-to`.
+    relative_to: Option<PathBuf>,
+}
+
+impl AuditLog {
+    fn open(path: &Path, relative_to: Option<PathBuf>) -> Result<Self> {
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .context("Failed to open JSON Lines audit log")?;
+        Ok(Self {
+            writer: LineWriter::new(file),
+            relative_to,
+        })
+    }
+
+    fn record(
+        &mut self,
+        app: &Path,
+        output: Option<&Path>,
+        result: &Result<&Vec<String>, &anyhow::Error>,
+    ) -> Result<()> {
+        let record = AuditRecord::new(app, output, result, self.relative_to.as_deref());
+        let mut line =
+            serde_json::to_string(&record).context("Failed to serialize audit record")?;
+        line.push('\n');
+        self.writer
+            .write_all(line.as_bytes())
+            .and_then(|()| self.writer.flush())
+            .context("Failed to write JSON Lines audit log")
+    }
+}
+
+/// The `--summary-json` report of a whole batch, written once every app has been stripped.
+#[derive(Serialize)]
+struct BatchSummary {
+    total: usize,
+    failed: usize,
+    /// How many apps each entitlement was removed from.
+    removed: BTreeMap<String, usize>,
+    apps: Vec<AuditRecord>,
+    #[serde(skip)]
+    relative_to: Option<PathBuf>,
+}
+
+impl BatchSummary {
+    fn new(relative_to: Option<PathBuf>) -> Self {
+        BatchSummary {
+            total: 0,
+            failed: 0,
+            removed: BTreeMap::new(),
+            apps: Vec::new(),
+            relative_to,
+        }
+    }
+
+    fn record(
+        &mut self,
+        app: &Path,
+        output: Option<&Path>,
+        result: &Result<&Vec<String>, &anyhow::Error>,
+    ) {
+        let record = AuditRecord::new(app, output, result, self.relative_to.as_deref());
+        self.total += 1;
+        if let AuditStatus::Error = record.status {
+            self.failed += 1;
+        }
+        for entitlement in &record.removed {
+            *self.removed.entry(entitlement.clone()).or_default() += 1;
+        }
+        self.apps.push(record);
+    }
+
+    fn write(&self, path: &Path) -> Result<()> {
+        let mut summary =
+            serde_json::to_string_pretty(self).context("Failed to serialize summary")?;
+        summary.push('\n');
+        fs::write(path, summary).context("Failed to write JSON summary")
+    }
+}
+
+/// Returns `path` relative to `base`, or as given if there's no base or it isn't inside it.
+fn relative_path<'a>(path: &'a Path, base: Option<&Path>) -> &'a Path {
+    base.and_then(|base| path.strip_prefix(base).ok())
+        .unwrap_or(path)
+}
+
+/// Warns about remaining developer entitlements that aren't in the provisioned list, since Apple
+/// adds new provisioned entitlements over time.
+fn warn_unknown_entitlements(entitlements: &plist::Value, keep: &[&str]) -> Result<()> {
+    let unknown: Vec<String> = get_prefixed_entitlements(entitlements, &[DEVELOPER_PREFIX])
+        .context("Failed to get developer entitlements")?
+        .into_iter()
+        .filter(|entitlement| !keep.contains(&entitlement.as_str()))
+        .collect();
+    if !unknown.is_empty() {
+        eprintln!(
+            "Warning: {} not stripped but might be provisioned: {}",
+            if unknown.len() == 1 {
+                "this entitlement was"
+            } else {
+                "these entitlements were"
+            },
+            unknown.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Lists what [`strip_entitlements`] would remove, which is the [`dry_run`] listing without kept and
+/// redacted entitlements.
+fn strip_dry_run(
+    input: &Input,
+    codesign: &Codesign,
+    rules: &StripRules,
+    keep: &[&str],
+) -> Result<Option<DryRunResult>> {
+    let Some(mut entitlements) = input.load_entitlements(codesign)? else {
+        return Ok(None);
+    };
+    let app = input.app_path().map(Path::to_path_buf);
+    let bundle_identifier = dry_run_bundle_identifier(app.as_deref(), &entitlements)?;
+    let (debug_entitlements, provisioned_entitlements) = rules
+        .strip(&mut entitlements, keep)?
+        .into_iter()
+        .partition(|entitlement| rules.is_debug(entitlement));
+    Ok(Some(DryRunResult {
+        app,
+        bundle_identifier,
+        provisioned_entitlements,
+        debug_entitlements,
+        values: None,
+        arch: None,
+        signing_info: None,
+    }))
+}
+
+/// The error for entitlements that aren't a dictionary, naming what they are instead.
+fn not_a_dictionary(entitlements: &plist::Value) -> StripError {
+    StripError::NotADictionary {
+        name: "Entitlements root".to_string(),
+        found: value_type_name(entitlements),
+    }
+}
+
+/// Loads and strips the entitlements, returning them, the entitlements that were removed and how
+/// many there were, or `None` if codesign isn't executed.
+fn strip_entitlements(
+    input: &Input,
+    codesign: &Codesign,
+    rules: &StripRules,
+    keep: &[&str],
+    warn_unknown: bool,
+) -> Result<Option<(plist::Value, Vec<String>, StripStats)>> {
+    let Some(entitlements) = input.load_entitlements(codesign)? else {
+        return Ok(None);
+    };
+    strip_loaded_entitlements(entitlements, rules, keep, warn_unknown).map(Some)
+}
+
+fn strip_loaded_entitlements(
+    original: plist::Value,
+    rules: &StripRules,
+    keep: &[&str],
+    warn_unknown: bool,
+) -> Result<(plist::Value, Vec<String>, StripStats)> {
+    let mut entitlements = original.clone();
+    let removed = rules.strip(&mut entitlements, keep)?;
+    if warn_unknown {
+        warn_unknown_entitlements(&entitlements, keep)?;
+    }
+    let stats = StripStats::new(&original, &entitlements, &removed);
+    Ok((entitlements, removed, stats))
+}
+
+/// A progress bar for stripping each of `bundle_paths` in turn, which is hidden with --quiet or
+/// when stderr isn't a terminal.
+fn bundle_progress(bundle_paths: &[PathBuf], verbosity: Verbosity) -> ProgressBar {
+    if verbosity == Verbosity::Quiet {
+        return ProgressBar::hidden();
+    }
+    ProgressBar::new(bundle_paths.len() as u64)
+        .with_style(
+            ProgressStyle::with_template("{bar:30} {pos}/{len} {wide_msg}")
+                .expect("progress template is valid"),
+        )
+        // Don't leave a partial bar behind when returning an error
+        .with_finish(ProgressFinish::AndClear)
+}
+
+/// Calls `f` on each item, on up to `threads` threads at once, returning the results in the
+/// order of `items`.
+fn map_parallel<T: Sync, R: Send>(
+    items: &[T],
+    threads: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    use std::sync::{
+        Mutex, PoisonError,
+        atomic::{AtomicUsize, Ordering},
+    };
+
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(items.iter().map(|_| None).collect::<Vec<Option<R>>>());
+    std::thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, items.len().max(1)) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(index) else {
+                        break;
+                    };
+                    let result = f(item);
+                    results.lock().unwrap_or_else(PoisonError::into_inner)[index] = Some(result);
+                }
+            });
+        }
+    });
+    results
+        .into_inner()
+        .unwrap_or_else(PoisonError::into_inner)
+        .into_iter()
+        .map(|result| result.expect("every item is mapped"))
+        .collect()
+}
+
+fn bundle_name(bundle_path: &Path) -> String {
+    bundle_path
+        .file_name()
+        .unwrap_or(bundle_path.as_os_str())
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn print_strip_dry_run_bundles(
+    bundle_paths: &[PathBuf],
+    codesign: &Codesign,
+    rules: &StripRules,
+    keep: &[&str],
+    verbosity: Verbosity,
+    relative_to: Option<&Path>,
+) -> Result<()> {
+    let mut results = Vec::new();
+    for bundle_path in bundle_paths {
+        let shown_path = relative_path(bundle_path, relative_to);
+        let Some(mut result) =
+            strip_dry_run(&Input::App(bundle_path.clone()), codesign, rules, keep).with_context(
+                || format!("Failed to get entitlements from {}", shown_path.display()),
+            )?
+        else {
+            continue;
+        };
+        result.app = Some(shown_path.to_path_buf());
+        results.push(result);
+    }
+    // Only the codesign commands were wanted, and they've been printed
+    if !codesign.executes() {
+        return Ok(());
+    }
+    anstream::print!(
+        "{}",
+        format_dry_run_bundles(&results, OutputFormat::Text, verbosity)?
+    );
+    Ok(())
+}
+
+/// How many entitlements a strip removed, for `--stats`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct StripStats {
+    present: usize,
+    remaining: usize,
+    nested_removed: usize,
+}
+
+impl StripStats {
+    /// Counts the top-level entitlements before and after stripping, including any removed for
+    /// being empty, and the nested entitlements `removed` with --deep.
+    fn new(original: &plist::Value, stripped: &plist::Value, removed: &[String]) -> Self {
+        let original = original.as_dictionary();
+        let nested_removed = removed
+            .iter()
+            .filter(|entitlement| {
+                original.is_none_or(|original| !original.contains_key(entitlement))
+            })
+            .count();
+        StripStats {
+            present: original.map_or(0, plist::Dictionary::len),
+            remaining: stripped.as_dictionary().map_or(0, plist::Dictionary::len),
+            nested_removed,
+        }
+    }
+
+    fn add(&mut self, other: StripStats) {
+        self.present += other.present;
+        self.remaining += other.remaining;
+        self.nested_removed += other.nested_removed;
+    }
+}
+
+impl std::fmt::Display for StripStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Removed {} of {} entitlements ({} remaining)",
+            self.present.saturating_sub(self.remaining),
+            self.present,
+            self.remaining
+        )?;
+        if self.nested_removed > 0 {
+            write!(
+                f,
+                "\nRemoved {} nested entitlement{}",
+                self.nested_removed,
+                if self.nested_removed == 1 { "" } else { "s" }
+            )?;
+        }
+        Ok(())
+    }
+}
+
+fn write_entitlements(
+    entitlements: &plist::Value,
+    output_path: &Path,
+    plist_output: PlistOutputArgs,
+) -> Result<()> {
+    if output_path == Path::new("-") {
+        let mut buf_writer = BufWriter::new(std::io::stdout().lock());
+        write_plist(&mut buf_writer, entitlements, plist_output)
+    } else if plist_output.no_atomic || is_special_file(output_path) {
+        // Renaming over a FIFO would replace it rather than write to whatever is reading it
+        let writer = fs::File::create(output_path).context("Failed to open output file")?;
+        write_plist(&mut BufWriter::new(writer), entitlements, plist_output)
+    } else {
+        let (temp_path, temp_file) = create_temp_output_file(output_path)?;
+        let result =
+            write_entitlements_file(entitlements, temp_file, plist_output).and_then(|()| {
+                fs::rename(&temp_path, output_path).context("Failed to replace output file")
+            });
+        if result.is_err() {
+            let _ = fs::remove_file(&temp_path);
+        }
+        result
+    }
+}
+
+/// Returns whether a path exists and isn't a regular file, such as a FIFO.
+fn is_special_file(path: &Path) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| !metadata.is_file())
+}
+
+/// Prints entitlements for `--print`, as XML even if they're written as a binary plist.
+fn print_entitlements(
+    entitlements: &plist::Value,
+    plist_output: PlistOutputArgs,
+    to_stdout: bool,
+) -> Result<()> {
+    let plist_output = PlistOutputArgs {
+        format: Some(PlistFormat::Xml),
+        ..plist_output
+    };
+    if to_stdout {
+        write_plist(&mut std::io::stdout().lock(), entitlements, plist_output)
+    } else {
+        let mut stderr = std::io::stderr().lock();
+        write_plist(&mut stderr, entitlements, plist_output)?;
+        // The plist crate doesn't end the XML with a newline
+        writeln!(stderr).context("Failed to write stripped entitlements")
+    }
+}
+
+/// Re-signs an app with its stripped entitlements, for `--in-place`.
+fn resign_app(
+    input: &Input,
+    entitlements: &plist::Value,
+    codesign: &Codesign,
+    identity: &str,
+) -> Result<()> {
+    let Some(app_path) = input.app_path() else {
+        bail!("--in-place requires an app path");
+    };
+    if is_ipa(app_path) || is_pkg(app_path) {
+        bail!("--in-place doesn't support .ipa or .pkg files");
+    }
+    let dir = create_private_temp_dir()?;
+    let entitlements_path = dir.join("stripped.entitlements");
+    let result = write_signing_entitlements(entitlements, &entitlements_path).and_then(|()| {
+        codesign
+            .sign(app_path, identity, &entitlements_path)
+            .map(drop)
+            .context("Failed to re-sign app")
+    });
+    let _ = fs::remove_dir_all(&dir);
+    result
+}
+
+/// Creates a new directory in the temp dir that only the current user can access, so files in it
+/// can't be replaced by other users.
+fn create_private_temp_dir() -> Result<PathBuf> {
+    let mut builder = fs::DirBuilder::new();
+    #[cfg(unix)]
+    std::os::unix::fs::DirBuilderExt::mode(&mut builder, 0o700);
+    // Makes the name hard to guess, so it's rarely taken already
+    let nonce = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.subsec_nanos());
+    for attempt in 0..TEMP_FILE_ATTEMPTS {
+        let dir = std::env::temp_dir().join(format!(
+            "provisioned-entitlement-stripper-{}-{nonce:08x}-{attempt}",
+            std::process::id()
+        ));
+        match builder.create(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(error) => return Err(error).context("Failed to create temporary directory"),
+        }
+    }
+    bail!("Failed to create temporary directory: every name is already taken")
+}
+
+/// Writes the entitlements to re-sign an app with to a new XML file at `path`, failing instead of
+/// writing through anything that's already there.
+fn write_signing_entitlements(entitlements: &plist::Value, path: &Path) -> Result<()> {
+    let file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(path)
+        .context("Failed to create entitlements file to sign with")?;
+    let plist_output = PlistOutputArgs {
+        format: Some(PlistFormat::Xml),
+        doctype: Doctype::Http,
+        indent: None,
+        tabs: false,
+        no_atomic: false,
+        sort_output_keys: false,
+        no_doctype: false,
+        no_xml_declaration: false,
+    };
+    write_entitlements_file(entitlements, file, plist_output)
+}
+
+/// Checks an app's signature with codesign, for --verify and `verify`, returning `None` if codesign
+/// isn't executed.
+fn verify_signature(codesign: &Codesign, app_path: &Path) -> Result<Option<()>> {
+    codesign
+        .verify(app_path)
+        .with_context(|| format!("Failed to verify the signature of {}", app_path.display()))
+}
+
+/// The pkgutil binary used to expand .pkg inputs, from `$PKGUTIL` if it's set.
+fn pkgutil_path() -> PathBuf {
+    std::env::var_os("PKGUTIL").map_or_else(|| PathBuf::from(DEFAULT_PKGUTIL_PATH), PathBuf::from)
+}
+
+/// Strips an app's entitlements to `output_path` for `watch`, bypassing the entitlements cache
+/// since rebuilding an app doesn't always change the bundle's modification time.
+fn watch_strip(
+    app_path: &Path,
+    output_path: &Path,
+    rules: &StripRules,
+    plist_output: PlistOutputArgs,
+    codesign: &Codesign,
+) -> Result<Option<Vec<String>>> {
+    let entitlements = if is_ipa(app_path) {
+        extract_ipa(app_path)
+            .and_then(|extracted| codesign.get_entitlements(extracted.app_path()))
+            .context("Failed to get entitlements from ipa")?
+    } else if is_pkg(app_path) {
+        expand_pkg(&pkgutil_path(), app_path)
+            .and_then(|expanded| codesign.get_entitlements(expanded.app_path()))
+            .context("Failed to get entitlements from pkg")?
+    } else {
+        codesign
+            .get_entitlements(app_path)
+            .context("Failed to get entitlements from app")?
+    };
+    let Some(mut entitlements) = entitlements else {
+        return Ok(None);
+    };
+    let removed = rules.strip(&mut entitlements, &[])?;
+    write_entitlements(&entitlements, output_path, plist_output)?;
+    Ok(Some(removed))
+}
+
+/// Formats the time of day in UTC as `HH:MM:SS`, for the lines printed by `watch`.
+fn format_timestamp(time: std::time::SystemTime) -> String {
+    let seconds = time
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    format!(
+        "{:02}:{:02}:{:02}",
+        seconds / 3600 % 24,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Strips `app_path` whenever it changes until Ctrl-C is pressed, waiting for `debounce` without
+/// any changes first so a rebuild is only stripped once. Failed strips are reported without
+/// stopping, as the app is often mid-build.
+fn watch(
+    app_path: &Path,
+    output_path: &Path,
+    rules: &StripRules,
+    plist_output: PlistOutputArgs,
+    codesign: &Codesign,
+    debounce: std::time::Duration,
+) -> Result<()> {
+    use notify::Watcher;
+    use std::sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::{self, RecvTimeoutError},
+    };
+
+    let interrupted = Arc::new(AtomicBool::new(false));
+    ctrlc::set_handler({
+        let interrupted = Arc::clone(&interrupted);
+        move || interrupted.store(true, Ordering::SeqCst)
+    })
+    .context("Failed to set Ctrl-C handler")?;
+
+    let (sender, receiver) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+        if event.is_ok_and(|event| !event.kind.is_access()) {
+            let _ = sender.send(());
+        }
+    })
+    .context("Failed to create file watcher")?;
+    watcher
+        .watch(app_path, notify::RecursiveMode::Recursive)
+        .with_context(|| format!("Failed to watch {}", app_path.display()))?;
+
+    let strip = || {
+        let timestamp = format_timestamp(std::time::SystemTime::now());
+        match watch_strip(app_path, output_path, rules, plist_output, codesign) {
+            Ok(None) => {}
+            Ok(Some(removed)) => println!(
+                "[{timestamp}] Stripped {} to {}, removing {} entitlement{}",
+                app_path.display(),
+                output_path.display(),
+                removed.len(),
+                if removed.len() == 1 { "" } else { "s" }
+            ),
+            Err(error) => eprintln!(
+                "[{timestamp}] Failed to strip {}: {error:#}",
+                app_path.display()
+            ),
+        }
+    };
+
+    strip();
+    eprintln!(
+        "Watching {} for changes, press Ctrl-C to stop",
+        app_path.display()
+    );
+    // How often to check for Ctrl-C while waiting for changes
+    let poll_interval = std::time::Duration::from_millis(100);
+    let mut changed = false;
+    while !interrupted.load(Ordering::SeqCst) {
+        match receiver.recv_timeout(if changed { debounce } else { poll_interval }) {
+            Ok(()) => changed = true,
+            Err(RecvTimeoutError::Timeout) if changed => {
+                changed = false;
+                strip();
+            }
+            Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => bail!("File watcher stopped unexpectedly"),
+        }
+    }
+    Ok(())
+}
+
+/// How many temporary file names to try before giving up, if something else keeps creating them.
+const TEMP_FILE_ATTEMPTS: u32 = 100;
+
+/// Creates a new temporary file next to `output_path`, so it can be renamed over it atomically.
+///
+/// The file is never one that already existed, such as a symlink someone else placed at its path,
+/// so the next name is tried instead if it does.
+fn create_temp_output_file(output_path: &Path) -> Result<(PathBuf, fs::File)> {
+    let file_name = output_path
+        .file_name()
+        .context("Output path has no file name")?;
+    for attempt in 0..TEMP_FILE_ATTEMPTS {
+        let mut temp_file_name = std::ffi::OsString::from(".");
+        temp_file_name.push(file_name);
+        temp_file_name.push(format!(".tmp-{}", std::process::id()));
+        if attempt > 0 {
+            temp_file_name.push(format!("-{attempt}"));
+        }
+        let temp_path = output_path.with_file_name(temp_file_name);
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&temp_path)
+        {
+            Ok(file) => return Ok((temp_path, file)),
+            Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => {}
+            Err(error) => return Err(error).context("Failed to create output file"),
+        }
+    }
+    bail!("Failed to create output file: every temporary file name is already taken")
+}
+
+fn write_entitlements_file(
+    entitlements: &plist::Value,
+    file: fs::File,
+    plist_output: PlistOutputArgs,
+) -> Result<()> {
+    let mut buf_writer = BufWriter::new(file);
+    write_plist(&mut buf_writer, entitlements, plist_output)?;
+    buf_writer
+        .into_inner()
+        .context("Failed to write stripped entitlements")?
+        .sync_all()
+        .context("Failed to write stripped entitlements")
+}
+
+fn write_plist<W: Write>(
+    writer: &mut W,
+    entitlements: &plist::Value,
+    plist_output: PlistOutputArgs,
+) -> Result<()> {
+    let mut sorted;
+    let entitlements = if plist_output.sort_output_keys {
+        sorted = entitlements.clone();
+        sort_keys(&mut sorted);
+        &sorted
+    } else {
+        entitlements
+    };
+    match plist_output.format() {
+        PlistFormat::Xml => {
+            let mut xml = Vec::new();
+            plist::to_writer_xml_with_options(
+                &mut xml,
+                entitlements,
+                &plist_output.xml_write_options(),
+            )
+            .context("Failed to write stripped entitlements")?;
+            writer
+                .write_all(&plist_output.apply_xml_header(xml))
+                .context("Failed to write stripped entitlements")?;
+        }
+        PlistFormat::Binary => plist::to_writer_binary(&mut *writer, entitlements)
+            .context("Failed to write stripped entitlements")?,
+        PlistFormat::PlistJson => plist_output.write_json(writer, entitlements)?,
+    }
+    writer
+        .flush()
+        .context("Failed to write stripped entitlements")?;
+    Ok(())
+}
+
+fn batch_output_path(
+    app_path: &Path,
+    entitlements: &plist::Value,
+    output_dir: &Path,
+    name_template: &NameTemplate,
+    format: PlistFormat,
+) -> Result<PathBuf> {
+    let file_name = name_template.render(app_path, entitlements, format)?;
+    if Path::new(&file_name).file_name() != Some(file_name.as_os_str()) {
+        bail!(
+            "Name template produced {:?} for {}, which is not a file name",
+            file_name,
+            app_path.display()
+        );
+    }
+    Ok(output_dir.join(file_name))
+}
+
+const NAME_TEMPLATE_TOKENS: &str = "{app}, {bundle_id}, {team} and {ext}";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum NameTemplatePart {
+    Literal(String),
+    App,
+    BundleId,
+    Team,
+    Ext,
+}
+
+/// A `--name-template` for batch output file names, such as `{app}.entitlements.{ext}`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct NameTemplate {
+    parts: Vec<NameTemplatePart>,
+}
+
+impl NameTemplate {
+    fn parse(template: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find(['{', '}']) {
+            if rest[start..].starts_with('}') {
+                bail!("Unmatched }} in name template");
+            }
+            if start > 0 {
+                parts.push(NameTemplatePart::Literal(rest[..start].to_string()));
+            }
+            let end = rest[start..]
+                .find('}')
+                .context("Unmatched { in name template")?
+                + start;
+            parts.push(match &rest[start + 1..end] {
+                "app" => NameTemplatePart::App,
+                "bundle_id" => NameTemplatePart::BundleId,
+                "team" => NameTemplatePart::Team,
+                "ext" => NameTemplatePart::Ext,
+                token => bail!(
+                    "Unknown token {{{}}} in name template, expected {}",
+                    token,
+                    NAME_TEMPLATE_TOKENS
+                ),
+            });
+            rest = &rest[end + 1..];
+        }
+        if !rest.is_empty() {
+            parts.push(NameTemplatePart::Literal(rest.to_string()));
+        }
+        Ok(NameTemplate { parts })
+    }
+
+    /// Renders the file name for an app, reading `{bundle_id}` and `{team}` from its unstripped
+    /// entitlements.
+    fn render(
+        &self,
+        app_path: &Path,
+        entitlements: &plist::Value,
+        format: PlistFormat,
+    ) -> Result<std::ffi::OsString> {
+        // Built from OS strings so app names that aren't valid UTF-8 are kept as they are
+        let mut file_name = std::ffi::OsString::new();
+        for part in &self.parts {
+            match part {
+                NameTemplatePart::Literal(literal) => file_name.push(literal),
+                NameTemplatePart::App => file_name.push(
+                    app_path
+                        .file_stem()
+                        .with_context(|| format!("{} has no file name", app_path.display()))?,
+                ),
+                NameTemplatePart::BundleId => {
+                    let bundle_id =
+                        get_bundle_identifier(entitlements)?.context(NO_APPLICATION_IDENTIFIER)?;
+                    file_name.push(bundle_id);
+                }
+                NameTemplatePart::Team => {
+                    let team = match get_string_entitlement(entitlements, TEAM_IDENTIFIER)? {
+                        Some(team) => team,
+                        None => get_application_identifier(entitlements)?
+                            .context(NO_APPLICATION_IDENTIFIER)?
+                            .0
+                            .context("The application identifier has no team ID prefix")?,
+                    };
+                    file_name.push(team);
+                }
+                NameTemplatePart::Ext => file_name.push(match format {
+                    PlistFormat::Xml => "xml",
+                    PlistFormat::Binary => "plist",
+                    PlistFormat::PlistJson => "json",
+                }),
+            }
+        }
+        Ok(file_name)
+    }
+}
+
+/// The entitlement holding an app's team ID.
+const TEAM_IDENTIFIER: &str = "com.apple.developer.team-identifier";
+
+const NO_APPLICATION_IDENTIFIER: &str =
+    "Entitlements have no application identifier to read the bundle ID or team from";
+
+fn get_string_entitlement<'a>(
+    entitlements: &'a plist::Value,
+    entitlement: &str,
+) -> Result<Option<&'a str>> {
+    entitlements
+        .as_dictionary()
+        .ok_or_else(|| not_a_dictionary(entitlements))?
+        .get(entitlement)
+        .map(|value| {
+            value
+                .as_string()
+                .with_context(|| format!("{} is not a string", entitlement))
+        })
+        .transpose()
+}
+
+fn to_xml_string(entitlements: &plist::Value) -> Result<String> {
+    let mut writer = Vec::new();
+    plist::to_writer_xml(&mut writer, entitlements).context("Failed to serialize entitlements")?;
+    String::from_utf8(writer).context("Serialized entitlements are not valid UTF-8")
+}
+
+/// The comment [`annotate_xml`] puts above each entitlement that would be stripped.
+const ANNOTATION: &str = "<!-- Provisioned, will be stripped -->";
+
+/// Inserts an [`ANNOTATION`] comment above each top-level key of an XML plist that is one of
+/// `entitlements`, indented to match the key. Keys of nested dictionaries aren't annotated.
+fn annotate_xml(xml: &str, entitlements: &[String]) -> Result<String> {
+    use quick_xml::events::Event;
+
+    let mut reader = quick_xml::Reader::from_str(xml);
+    let mut insert_at = Vec::new();
+    let mut depth = 0;
+    // Where the top-level key being read starts, and its text so far
+    let mut key: Option<(usize, String)> = None;
+    loop {
+        let position = reader.buffer_position() as usize;
+        match reader
+            .read_event()
+            .context("Failed to read entitlements XML")?
+        {
+            Event::Start(start) if start.name().as_ref() == b"dict" => depth += 1,
+            Event::End(end) if end.name().as_ref() == b"dict" => depth -= 1,
+            Event::Start(start) if start.name().as_ref() == b"key" && depth == 1 => {
+                key = Some((position, String::new()));
+            }
+            Event::Text(text) if key.is_some() => {
+                let text = text
+                    .xml_content()
+                    .context("Failed to read entitlements XML")?;
+                key.as_mut().unwrap().1.push_str(&text);
+            }
+            Event::GeneralRef(reference) if key.is_some() => {
+                let character = reference
+                    .resolve_char_ref()
+                    .ok()
+                    .flatten()
+                    .map(String::from)
+                    .or_else(|| {
+                        let name = reference.decode().ok()?;
+                        quick_xml::escape::resolve_predefined_entity(&name).map(String::from)
+                    })
+                    .context("Failed to read entitlements XML")?;
+                key.as_mut().unwrap().1.push_str(&character);
+            }
+            Event::End(end) if end.name().as_ref() == b"key" => {
+                if let Some((start, key)) = key.take()
+                    && entitlements.contains(&key)
+                {
+                    insert_at.push(start);
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    let mut annotated = String::with_capacity(xml.len());
+    let mut written = 0;
+    for position in insert_at {
+        let line_start = xml[..position].rfind('\n').map_or(0, |index| index + 1);
+        annotated.push_str(&xml[written..position]);
+        annotated.push_str(ANNOTATION);
+        annotated.push('\n');
+        annotated.push_str(&xml[line_start..position]);
+        written = position;
+    }
+    annotated.push_str(&xml[written..]);
+    Ok(annotated)
+}
+
+fn diff_lines(original: &str, stripped: &str) -> String {
+    diff_lines_labeled(("original", original), ("stripped", stripped))
+}
+
+fn diff_lines_labeled(
+    (original_label, original): (&str, &str),
+    (stripped_label, stripped): (&str, &str),
+) -> String {
+    let original: Vec<&str> = original.lines().collect();
+    let stripped: Vec<&str> = stripped.lines().collect();
+
+    // lengths[i][j] is the length of the longest common subsequence of original[i..] and
+    // stripped[j..]
+    let mut lengths = vec![vec![0; stripped.len() + 1]; original.len() + 1];
+    for i in (0..original.len()).rev() {
+        for j in (0..stripped.len()).rev() {
+            lengths[i][j] = if original[i] == stripped[j] {
+                lengths[i + 1][j + 1] + 1
+            } else {
+                lengths[i + 1][j].max(lengths[i][j + 1])
+            };
+        }
+    }
+
+    let mut output = format!("--- {}\n+++ {}\n", original_label, stripped_label);
+    let (mut i, mut j) = (0, 0);
+    while i < original.len() || j < stripped.len() {
+        if i < original.len() && j < stripped.len() && original[i] == stripped[j] {
+            output.push_str(&format!(" {}\n", original[i]));
+            i += 1;
+            j += 1;
+        } else if j == stripped.len()
+            || (i < original.len() && lengths[i + 1][j] >= lengths[i][j + 1])
+        {
+            output.push_str(&format!("-{}\n", original[i]));
+            i += 1;
+        } else {
+            output.push_str(&format!("+{}\n", stripped[j]));
+            j += 1;
+        }
+    }
+    output
+}
+
+/// The config file read when --config isn't given, if it exists in the current directory.
+const DEFAULT_CONFIG_PATH: &str = ".stripper.toml";
+
+/// Defaults for flags, read from the --config file.
+///
+/// Flags given on the command line and their environment variables take precedence over the
+/// config, which takes precedence over the built-in defaults. A list given on the command line,
+/// such as --extra, replaces the config's list rather than adding to it.
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct Config {
+    codesign_path: Option<PathBuf>,
+    #[serde(default)]
+    extra: Vec<String>,
+    #[serde(default)]
+    keep: Vec<String>,
+    output_format: Option<PlistFormat>,
+    color: Option<ColorMode>,
+}
+
+impl Config {
+    /// Reads the config at `path`, or at [`DEFAULT_CONFIG_PATH`] if it exists when `path` isn't
+    /// given.
+    fn load(path: Option<&Path>) -> Result<Option<Self>> {
+        let path = match path {
+            Some(path) => path,
+            None if Path::new(DEFAULT_CONFIG_PATH).is_file() => Path::new(DEFAULT_CONFIG_PATH),
+            None => return Ok(None),
+        };
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read config {}", path.display()))?;
+        toml::from_str(&contents)
+            .with_context(|| format!("Failed to parse config {}", path.display()))
+            .map(Some)
+    }
+
+    /// Sets the config's values as the defaults of the arguments they're for, in every subcommand
+    /// that has them.
+    fn apply(&self, mut command: clap::Command) -> clap::Command {
+        if let Some(codesign_path) = &self.codesign_path {
+            let codesign_path = codesign_path.clone().into_os_string();
+            command = command.mut_arg("codesign_path", |arg| arg.default_value(codesign_path));
+        }
+        if let Some(color) = self.color {
+            command = command.mut_arg("color", |arg| arg.default_value(value_name(color)));
+        }
+        let subcommands: Vec<String> = command
+            .get_subcommands()
+            .map(|subcommand| subcommand.get_name().to_string())
+            .collect();
+        for name in subcommands {
+            command = command.mut_subcommand(name, |mut subcommand| {
+                let output_format = self.output_format.map(|format| vec![value_name(format)]);
+                for (long, values) in [
+                    ("extra", Some(&self.extra)),
+                    ("keep", Some(&self.keep)),
+                    ("output-format", output_format.as_ref()),
+                ] {
+                    let Some(values) = values.filter(|values| !values.is_empty()) else {
+                        continue;
+                    };
+                    let Some(id) = subcommand
+                        .get_arguments()
+                        .find(|arg| arg.get_long() == Some(long))
+                        .map(|arg| arg.get_id().clone())
+                    else {
+                        continue;
+                    };
+                    subcommand = subcommand.mut_arg(id, |arg| arg.default_values(values.clone()));
+                }
+                subcommand
+            });
+        }
+        command
+    }
+}
+
+/// The name a value is given as on the command line.
+fn value_name(value: impl ValueEnum) -> String {
+    value
+        .to_possible_value()
+        .expect("no values are skipped")
+        .get_name()
+        .to_string()
+}
+
+/// Finds the --config argument before the arguments are parsed, as the config sets their
+/// defaults.
+fn find_config_arg(args: impl IntoIterator<Item = std::ffi::OsString>) -> Option<PathBuf> {
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if arg == "--" {
+            break;
+        }
+        if arg == "--config" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.to_str().and_then(|arg| arg.strip_prefix("--config=")) {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+fn main() -> Result<ExitCode> {
+    let config = Config::load(find_config_arg(std::env::args_os().skip(1)).as_deref())?;
+    let command = match &config {
+        Some(config) => config.apply(Cli::command()),
+        None => Cli::command(),
+    };
+    let matches = command.get_matches();
+    // --format can't conflict with --null only when it's json, and json from STRIPPER_FORMAT is
+    // ignored rather than an error
+    if let Some(("dry-run", dry_run)) = matches.subcommand()
+        && dry_run.get_flag("null")
+        && dry_run.value_source("format") == Some(clap::parser::ValueSource::CommandLine)
+        && matches!(
+            dry_run.get_one::<OutputFormat>("format"),
+            Some(OutputFormat::Json)
+        )
+    {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "the argument '--null' cannot be used with '--format json'",
+            )
+            .exit();
+    }
+    let cli = Cli::from_arg_matches(&matches).unwrap_or_else(|error| error.exit());
+    // clap doesn't propagate global arguments that subcommand arguments conflict with
+    if let Commands::DryRun {
+        all_archs: true, ..
+    } = cli.command
+        && cli.arch.is_some()
+    {
+        Cli::command()
+            .error(
+                clap::error::ErrorKind::ArgumentConflict,
+                "the argument '--all-archs' cannot be used with '--arch <ARCH>'",
+            )
+            .exit();
+    }
+    cli.color.write_global();
+    env_logger::Builder::new()
+        .filter_level(match cli.verbose {
+            0 => log::LevelFilter::Warn,
+            1 => log::LevelFilter::Debug,
+            _ => log::LevelFilter::Trace,
+        })
+        .init();
+    if config.is_some() {
+        log::debug!(
+            "Using defaults from {}",
+            cli.config
+                .as_deref()
+                .unwrap_or(Path::new(DEFAULT_CONFIG_PATH))
+                .display()
+        );
+    }
+
+    let error_format = cli.error_format;
+    match run(cli) {
+        Err(error) if is_not_signed(&error) => {
+            print_error(&error, error_format)?;
+            Ok(ExitCode::from(NOT_SIGNED_EXIT_CODE))
+        }
+        Err(error) if is_codesign_not_found(&error) => {
+            print_error(&error, error_format)?;
+            if let OutputFormat::Text = error_format {
+                eprintln!(
+                    "codesign not found; use --from-file or --from-stdin on this platform, or \
+                     --codesign-path if it's installed elsewhere"
+                );
+            }
+            Ok(ExitCode::from(CODESIGN_NOT_FOUND_EXIT_CODE))
+        }
+        Err(error) if is_codesign_failed(&error) => {
+            print_error(&error, error_format)?;
+            Ok(ExitCode::from(CODESIGN_FAILED_EXIT_CODE))
+        }
+        Err(error) => {
+            print_error(&error, error_format)?;
+            Ok(ExitCode::FAILURE)
+        }
+        result => result,
+    }
+}
+
+#[derive(Serialize)]
+struct ErrorReport {
+    error: String,
+    context: Vec<String>,
+}
+
+impl ErrorReport {
+    fn new(error: &anyhow::Error) -> Self {
+        let mut context: Vec<String> = error.chain().map(ToString::to_string).collect();
+        let error = context.pop().unwrap_or_default();
+        ErrorReport { error, context }
+    }
+}
+
+/// Prints an error to stderr in the `--error-format`, with text errors printed the same way as
+/// returning them from `main` would.
+fn print_error(error: &anyhow::Error, format: OutputFormat) -> Result<()> {
+    match format {
+        OutputFormat::Text => eprintln!("Error: {:?}", error),
+        OutputFormat::Json => eprintln!(
+            "{}",
+            serde_json::to_string(&ErrorReport::new(error)).context("Failed to serialize error")?
+        ),
+    }
+    Ok(())
+}
+
+/// The exit status when codesign reports the app isn't signed, which is usually the wrong build
+/// being given rather than a failure of the tool.
+const NOT_SIGNED_EXIT_CODE: u8 = 3;
+
+/// The exit status when codesign failed or timed out, other than for an unsigned app. 2 is
+/// already taken by usage errors.
+const CODESIGN_FAILED_EXIT_CODE: u8 = 4;
+
+/// The exit status when codesign doesn't exist, the same as a shell uses for a command that isn't
+/// found.
+const CODESIGN_NOT_FOUND_EXIT_CODE: u8 = 127;
+
+/// Whether an error was caused by a [`StripError`] matching `predicate`.
+fn is_strip_error(error: &anyhow::Error, predicate: impl Fn(&StripError) -> bool) -> bool {
+    error
+        .chain()
+        .filter_map(|cause| cause.downcast_ref::<StripError>())
+        .any(predicate)
+}
+
+fn is_not_signed(error: &anyhow::Error) -> bool {
+    is_strip_error(error, |error| matches!(error, StripError::NotSigned))
+}
+
+fn is_codesign_not_found(error: &anyhow::Error) -> bool {
+    is_strip_error(error, |error| {
+        matches!(error, StripError::CodesignNotFound { .. })
+    })
+}
+
+fn is_codesign_failed(error: &anyhow::Error) -> bool {
+    is_strip_error(error, |error| {
+        matches!(
+            error,
+            StripError::CodesignFailed { .. } | StripError::CodesignTimedOut(_)
+        )
+    })
+}
+
+fn run(cli: Cli) -> Result<ExitCode> {
+    let verbosity = if cli.quiet {
+        Verbosity::Quiet
+    } else {
+        Verbosity::Normal
+    };
+    let codesign = Codesign::new(cli.codesign_path)
+        .timeout(cli.timeout.map(std::time::Duration::from_secs))
+        .arch(cli.arch)
+        .print_command(cli.print_command)
+        .execute(!cli.no_exec)
+        .strict_parse(cli.strict_parse)
+        .args(cli.codesign_arg)?;
+
+    match cli.command {
+        Commands::Strip {
+            input,
+            entitlement_args,
+            output_paths,
+            output_dir,
+            merge_output,
+            recursive,
+            name_template,
+            plist_output,
+            keep,
+            only,
+            keep_only,
+            dry_run,
+            json_lines,
+            summary_json,
+            threads,
+            relative_to,
+            warn_unknown,
+            stats,
+            print,
+            backup,
+            force,
+            warn_ambiguous,
+            strict,
+            fail_if_no_entitlements,
+            dedupe_arrays,
+            strip_empty,
+            only_provisioned,
+            in_place,
+            sign,
+            verify,
+        } => {
+            input.check_target_types()?;
+            let codesign = codesign.fail_on_plist_version(strict);
+            let mut rules = entitlement_args.rules()?;
+            if let Some(only) = only {
+                rules = rules.only(only);
+            }
+            if !keep_only.is_empty() {
+                rules = rules.keep_only(resolve_keep_only(&keep_only)?);
+            }
+            rules.ambiguous = if strict {
+                AmbiguousPolicy::Error
+            } else if warn_ambiguous {
+                AmbiguousPolicy::Warn
+            } else {
+                AmbiguousPolicy::Allow
+            };
+            rules.require_entitlements = fail_if_no_entitlements;
+            rules.dedupe_arrays = dedupe_arrays;
+            rules.strip_empty = strip_empty;
+            rules.only_provisioned = only_provisioned;
+            rules.warn_ineffective_keep(&keep);
+            let mut total_stats = StripStats::default();
+            let keep: Vec<&str> = keep.iter().map(String::as_str).collect();
+
+            if let Some(output_dir) = output_dir {
+                let mut bundle_paths = Vec::new();
+                let mut nested_bundle_errors = Vec::new();
+                let mut failed = 0;
+                for app_path in &input.app_paths {
+                    bundle_paths.push(app_path.clone());
+                    if recursive {
+                        match find_nested_bundles(app_path).map_err(anyhow::Error::from) {
+                            Ok(nested_bundle_paths) => bundle_paths.extend(nested_bundle_paths),
+                            Err(error) => {
+                                eprintln!(
+                                    "Failed to find nested bundles in {}: {:#}",
+                                    relative_path(app_path, relative_to.as_deref()).display(),
+                                    error
+                                );
+                                nested_bundle_errors.push((app_path, error));
+                                failed += 1;
+                            }
+                        }
+                    }
+                }
+
+                if dry_run {
+                    print_strip_dry_run_bundles(
+                        &bundle_paths,
+                        &codesign,
+                        &rules,
+                        &keep,
+                        verbosity,
+                        relative_to.as_deref(),
+                    )?;
+                    return Ok(ExitCode::SUCCESS);
+                }
+
+                let mut audit_log = json_lines
+                    .as_deref()
+                    .map(|path| AuditLog::open(path, relative_to.clone()))
+                    .transpose()?;
+                let mut summary = summary_json
+                    .as_ref()
+                    .map(|_| BatchSummary::new(relative_to.clone()));
+                for (app_path, error) in &nested_bundle_errors {
+                    if let Some(audit_log) = &mut audit_log {
+                        audit_log.record(app_path, None, &Err(error))?;
+                    }
+                    if let Some(summary) = &mut summary {
+                        summary.record(app_path, None, &Err(error));
+                    }
+                }
+
+                let threads = threads
+                    .or_else(|| std::thread::available_parallelism().ok())
+                    .map_or(1, std::num::NonZeroUsize::get);
+                let progress = bundle_progress(&bundle_paths, verbosity);
+                // Running codesign is the slow part, so only that and stripping happen in
+                // parallel, and writing is left until afterwards so it happens in order
+                let stripped_bundles = map_parallel(&bundle_paths, threads, |bundle_path| {
+                    progress.set_message(bundle_name(bundle_path));
+                    let mut bundle_output_path = None;
+                    let result = Input::App(bundle_path.clone())
+                        .load_entitlements(&codesign)
+                        .and_then(|entitlements| {
+                            let Some(entitlements) = entitlements else {
+                                return Ok(None);
+                            };
+                            bundle_output_path = Some(batch_output_path(
+                                bundle_path,
+                                &entitlements,
+                                &output_dir,
+                                &name_template,
+                                plist_output.format(),
+                            )?);
+                            strip_loaded_entitlements(entitlements, &rules, &keep, warn_unknown)
+                                .map(Some)
+                        });
+                    progress.inc(1);
+                    (bundle_output_path, result)
+                });
+                progress.finish_and_clear();
+
+                let mut written_paths = HashSet::new();
+                for (bundle_path, (bundle_output_path, result)) in
+                    bundle_paths.iter().zip(stripped_bundles)
+                {
+                    // Only the codesign command was wanted, and it's been printed
+                    let Some(result) = result.transpose() else {
+                        continue;
+                    };
+                    let result = result.and_then(|(stripped, removed, stats)| {
+                        let output_path = bundle_output_path
+                            .as_ref()
+                            .expect("output path is set before stripping");
+                        if !written_paths.insert(output_path.clone()) {
+                            bail!(
+                                "{} was already written for another bundle",
+                                relative_path(output_path, relative_to.as_deref()).display()
+                            );
+                        }
+                        write_entitlements(&stripped, output_path, plist_output)?;
+                        Ok((removed, stats))
+                    });
+                    let removed = result.as_ref().map(|(removed, _)| removed);
+                    if let Some(audit_log) = &mut audit_log {
+                        audit_log.record(bundle_path, bundle_output_path.as_deref(), &removed)?;
+                    }
+                    if let Some(summary) = &mut summary {
+                        summary.record(bundle_path, bundle_output_path.as_deref(), &removed);
+                    }
+                    match result {
+                        Ok((_, bundle_stats)) => total_stats.add(bundle_stats),
+                        Err(error) => {
+                            eprintln!(
+                                "Failed to strip {}: {:#}",
+                                relative_path(bundle_path, relative_to.as_deref()).display(),
+                                error
+                            );
+                            failed += 1;
+                        }
+                    }
+                }
+
+                if !codesign.executes() {
+                    return Ok(ExitCode::SUCCESS);
+                }
+                if let (Some(summary), Some(summary_json)) = (&summary, &summary_json) {
+                    summary.write(summary_json)?;
+                }
+                if stats {
+                    eprintln!("{}", total_stats);
+                }
+
+                // Apps whose nested bundles couldn't be found count as a single failed bundle
+                let kind = if recursive { "bundles" } else { "apps" };
+                let total = bundle_paths.len() + nested_bundle_errors.len();
+                verbosity.info(format!("Stripped {} of {} {}", total - failed, total, kind));
+                if failed > 0 {
+                    bail!("Failed to strip {} of {} {}", failed, total, kind);
+                }
+            } else if let Some(merge_output) = merge_output {
+                let mut bundle_paths = Vec::new();
+                for app_path in &input.app_paths {
+                    bundle_paths.push(app_path.clone());
+                    if recursive {
+                        bundle_paths.extend(find_nested_bundles(app_path).with_context(|| {
+                            format!(
+                                "Failed to find nested bundles in {}",
+                                relative_path(app_path, relative_to.as_deref()).display()
+                            )
+                        })?);
+                    }
+                }
+
+                if dry_run {
+                    print_strip_dry_run_bundles(
+                        &bundle_paths,
+                        &codesign,
+                        &rules,
+                        &keep,
+                        verbosity,
+                        relative_to.as_deref(),
+                    )?;
+                    return Ok(ExitCode::SUCCESS);
+                }
+
+                let mut merged = plist::Dictionary::new();
+                let mut sources: HashMap<String, &Path> = HashMap::new();
+                let progress = bundle_progress(&bundle_paths, verbosity);
+                for bundle_path in &bundle_paths {
+                    progress.set_message(bundle_name(bundle_path));
+                    let Some((entitlements, _, bundle_stats)) = strip_entitlements(
+                        &Input::App(bundle_path.clone()),
+                        &codesign,
+                        &rules,
+                        &keep,
+                        warn_unknown,
+                    )
+                    .with_context(|| {
+                        format!(
+                            "Failed to strip {}",
+                            relative_path(bundle_path, relative_to.as_deref()).display()
+                        )
+                    })?
+                    else {
+                        progress.finish_and_clear();
+                        return Ok(ExitCode::SUCCESS);
+                    };
+                    total_stats.add(bundle_stats);
+                    let entitlements = match entitlements {
+                        plist::Value::Dictionary(entitlements) => entitlements,
+                        entitlements => bail!(not_a_dictionary(&entitlements)),
+                    };
+                    for (key, value) in entitlements {
+                        match merged.get(&key) {
+                            Some(merged_value) if *merged_value != value => bail!(
+                                "{} has different values in {} and {}",
+                                key,
+                                relative_path(sources[&key], relative_to.as_deref()).display(),
+                                relative_path(bundle_path, relative_to.as_deref()).display()
+                            ),
+                            Some(_) => {}
+                            None => {
+                                sources.insert(key.clone(), bundle_path.as_path());
+                                merged.insert(key, value);
+                            }
+                        }
+                    }
+                    progress.inc(1);
+                }
+                progress.finish_and_clear();
+                let merged = plist::Value::Dictionary(merged);
+                write_entitlements(&merged, &merge_output, plist_output)?;
+                if print {
+                    print_entitlements(&merged, plist_output, false)?;
+                }
+                if stats {
+                    eprintln!("{}", total_stats);
+                }
+            } else if dry_run {
+                let Some(result) = strip_dry_run(&input.single_input()?, &codesign, &rules, &keep)?
+                else {
+                    return Ok(ExitCode::SUCCESS);
+                };
+                print_dry_run(&result, OutputFormat::Text, verbosity)?;
+            } else {
+                let input = input.single_input()?;
+                // A single output keeps the --output-format default, whatever its extension
+                let outputs = match output_paths.as_slice() {
+                    [output_path] => vec![(output_path, plist_output)],
+                    output_paths => output_paths
+                        .iter()
+                        .map(|output_path| Ok((output_path, plist_output.for_path(output_path)?)))
+                        .collect::<Result<Vec<_>>>()?,
+                };
+                let stripped = match &backup {
+                    Some(backup) => {
+                        if !force && backup.exists() {
+                            bail!(
+                                "{} already exists, use --force to overwrite it",
+                                backup.display()
+                            );
+                        }
+                        let Some(entitlements) = input.load_entitlements(&codesign)? else {
+                            return Ok(ExitCode::SUCCESS);
+                        };
+                        write_entitlements(&entitlements, backup, plist_output)
+                            .context("Failed to write backup")?;
+                        Some(strip_loaded_entitlements(
+                            entitlements,
+                            &rules,
+                            &keep,
+                            warn_unknown,
+                        )?)
+                    }
+                    None => strip_entitlements(&input, &codesign, &rules, &keep, warn_unknown)?,
+                };
+                let Some((stripped, _, strip_stats)) = stripped else {
+                    return Ok(ExitCode::SUCCESS);
+                };
+                for (output_path, plist_output) in &outputs {
+                    write_entitlements(&stripped, output_path, *plist_output)
+                        .with_context(|| format!("Failed to write {}", output_path.display()))?;
+                }
+                if print {
+                    print_entitlements(&stripped, plist_output, output_paths.is_empty())?;
+                }
+                if stats {
+                    eprintln!("{}", strip_stats);
+                }
+                if in_place {
+                    let identity = sign.as_deref().context("--in-place requires --sign")?;
+                    resign_app(&input, &stripped, &codesign, identity)?;
+                    if verify {
+                        let app_path = input.app_path().context("--verify requires an app path")?;
+                        if verify_signature(&codesign, app_path)?.is_some()
+                            && verbosity != Verbosity::Quiet
+                        {
+                            eprintln!("{} has a valid signature", app_path.display());
+                        }
+                    }
+                }
+            }
+        }
+        Commands::DryRun {
+            input,
+            entitlement_args,
+            format,
+            recursive,
+            exit_code,
+            show_values,
+            all_archs,
+            show_signing_info,
+            count_only,
+            annotate,
+            null,
+        } => {
+            let rules = entitlement_args.rules()?;
+            let input = input.input()?;
+            if annotate {
+                let Some(entitlements) = input.load_entitlements(&codesign)? else {
+                    return Ok(ExitCode::SUCCESS);
+                };
+                let provisioned_entitlements = rules.provisioned_entitlements(&entitlements)?;
+                print!(
+                    "{}",
+                    annotate_xml(&to_xml_string(&entitlements)?, &provisioned_entitlements)?
+                );
+                if exit_code && !provisioned_entitlements.is_empty() {
+                    return Ok(ExitCode::FAILURE);
+                }
+                return Ok(ExitCode::SUCCESS);
+            }
+            let mut results = if all_archs {
+                let Input::App(app_path) = &input else {
+                    bail!("--all-archs requires an app path");
+                };
+                if is_ipa(app_path) || is_pkg(app_path) {
+                    bail!("--all-archs doesn't support .ipa or .pkg files");
+                }
+                let mut results = Vec::new();
+                let mut slices = Vec::new();
+                let Some(archs) = codesign
+                    .get_architectures(app_path)
+                    .context("Failed to get architectures of app")?
+                else {
+                    return Ok(ExitCode::SUCCESS);
+                };
+                for arch in archs {
+                    let Some(entitlements) = input
+                        .load_entitlements(&codesign.clone().arch(Some(arch.clone())))
+                        .with_context(|| format!("Failed to get entitlements of {}", arch))?
+                    else {
+                        return Ok(ExitCode::SUCCESS);
+                    };
+                    let mut result =
+                        dry_run_entitlements(&input, &entitlements, &rules, show_values)?;
+                    result.arch = Some(arch);
+                    results.push(result);
+                    slices.push(entitlements);
+                }
+                let differing = get_differing_entitlements(&slices.iter().collect::<Vec<_>>())?;
+                if !differing.is_empty() {
+                    eprintln!(
+                        "Warning: entitlements differ between architectures: {}",
+                        differing.join(", ")
+                    );
+                }
+                results
+            } else if recursive {
+                let Input::App(app_path) = &input else {
+                    bail!("--recursive requires an app path");
+                };
+                let Some(result) = dry_run(&input, &codesign, &rules, show_values)? else {
+                    return Ok(ExitCode::SUCCESS);
+                };
+                let mut results = vec![result];
+                for bundle_path in find_nested_bundles(app_path)? {
+                    let Some(result) =
+                        dry_run(&Input::App(bundle_path), &codesign, &rules, show_values)
+                            .context("Failed to get entitlements from nested bundle")?
+                    else {
+                        return Ok(ExitCode::SUCCESS);
+                    };
+                    results.push(result);
+                }
+                results
+            } else {
+                let Some(result) = dry_run(&input, &codesign, &rules, show_values)? else {
+                    return Ok(ExitCode::SUCCESS);
+                };
+                vec![result]
+            };
+
+            if show_signing_info {
+                for result in &mut results {
+                    let app_path = result
+                        .app
+                        .clone()
+                        .context("--show-signing-info requires an app path")?;
+                    let Some(signing_info) = Input::App(app_path)
+                        .signing_info(&codesign)
+                        .context("Failed to get signing info")?
+                    else {
+                        return Ok(ExitCode::SUCCESS);
+                    };
+                    result.signing_info = Some(signing_info);
+                }
+            }
+
+            if count_only {
+                let count: usize = results
+                    .iter()
+                    .map(|result| result.provisioned_entitlements.len())
+                    .sum();
+                println!("{}", count);
+            } else if null {
+                let mut stdout = std::io::stdout().lock();
+                for entitlement in &results[0].provisioned_entitlements {
+                    stdout
+                        .write_all(entitlement.as_bytes())
+                        .and_then(|()| stdout.write_all(b"\0"))
+                        .context("Failed to write provisioned entitlements")?;
+                }
+                stdout
+                    .flush()
+                    .context("Failed to write provisioned entitlements")?;
+            } else if all_archs || recursive {
+                anstream::print!("{}", format_dry_run_bundles(&results, format, verbosity)?);
+            } else {
+                print_dry_run(&results[0], format, verbosity)?;
+            }
+
+            let found = results.iter().any(DryRunResult::found_entitlements);
+            if exit_code && found {
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Commands::Diff {
+            input,
+            entitlement_args,
+        } => {
+            let rules = entitlement_args.rules()?;
+            let Some(original) = input.load_entitlements(&codesign)? else {
+                return Ok(ExitCode::SUCCESS);
+            };
+            let mut stripped = original.clone();
+            rules.strip(&mut stripped, &[])?;
+
+            print!(
+                "{}",
+                diff_lines(&to_xml_string(&original)?, &to_xml_string(&stripped)?)
+            );
+        }
+        Commands::Check {
+            input,
+            entitlement_args,
+            expected,
+        } => {
+            let rules = entitlement_args.rules()?;
+            let Some(mut stripped) = input.load_entitlements(&codesign)? else {
+                return Ok(ExitCode::SUCCESS);
+            };
+            rules.strip(&mut stripped, &[])?;
+            let expected_entitlements = read_entitlements_file(&expected)
+                .with_context(|| format!("Failed to read {}", expected.display()))?;
+
+            if stripped != expected_entitlements {
+                eprintln!("{} is not up to date", expected.display());
+                print!(
+                    "{}",
+                    diff_lines_labeled(
+                        (
+                            &expected.display().to_string(),
+                            &to_xml_string(&expected_entitlements)?
+                        ),
+                        ("stripped", &to_xml_string(&stripped)?),
+                    )
+                );
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Commands::Merge {
+            input,
+            entitlement_args,
+            base,
+            output_path,
+            plist_output,
+        } => {
+            let rules = entitlement_args.rules()?;
+            let Some(mut entitlements) = input.load_entitlements(&codesign)? else {
+                return Ok(ExitCode::SUCCESS);
+            };
+            rules.strip(&mut entitlements, &[])?;
+            let base = read_entitlements_file(&base).context("Failed to get base entitlements")?;
+            merge_entitlements(&mut entitlements, &base)?;
+            write_entitlements(&entitlements, &output_path, plist_output)?;
+        }
+        Commands::Compare {
+            first,
+            second,
+            format,
+        } => {
+            let first_entitlements = Input::App(first.clone())
+                .load_entitlements(&codesign)
+                .with_context(|| format!("Failed to get entitlements from {}", first.display()))?;
+            let second_entitlements = Input::App(second.clone())
+                .load_entitlements(&codesign)
+                .with_context(|| format!("Failed to get entitlements from {}", second.display()))?;
+            let (Some(first_entitlements), Some(second_entitlements)) =
+                (first_entitlements, second_entitlements)
+            else {
+                return Ok(ExitCode::SUCCESS);
+            };
+            let differences = compare_entitlements(&first_entitlements, &second_entitlements)?;
+            anstream::print!("{}", format_comparison(&differences, format, verbosity)?);
+        }
+        Commands::Extract {
+            input,
+            output_path,
+            plist_output,
+        } => {
+            let Some(entitlements) = input.load_entitlements(&codesign)? else {
+                return Ok(ExitCode::SUCCESS);
+            };
+            write_entitlements(&entitlements, &output_path, plist_output)?;
+        }
+        Commands::Watch {
+            app_path,
+            output_path,
+            entitlement_args,
+            plist_output,
+            debounce,
+        } => {
+            let rules = entitlement_args.rules()?;
+            watch(
+                &app_path,
+                &output_path,
+                &rules,
+                plist_output,
+                &codesign,
+                std::time::Duration::from_millis(debounce),
+            )?;
+        }
+        Commands::Validate { path, format } => {
+            let entitlements = read_entitlements_file(&path)
+                .with_context(|| format!("Failed to read {}", path.display()))?;
+            let provisioned_entitlements = get_provisioned_entitlements(&entitlements, &[])?;
+            print!(
+                "{}",
+                format_entitlement_list(&provisioned_entitlements, format)?
+            );
+            if !provisioned_entitlements.is_empty() {
+                eprintln!("{} contains provisioned entitlements", path.display());
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Commands::Verify { app_path } => {
+            if verify_signature(&codesign, &app_path)?.is_some() && verbosity != Verbosity::Quiet {
+                println!("{} has a valid signature", app_path.display());
+            }
+        }
+        Commands::Profile {
+            profile_path,
+            format,
+        } => {
+            let entitlements = get_profile_entitlements(&profile_path)
+                .context("Failed to get entitlements from provisioning profile")?;
+            let entitlements: Vec<&str> = entitlements
+                .as_dictionary()
+                .ok_or_else(|| not_a_dictionary(&entitlements))?
+                .keys()
+                .map(String::as_str)
+                .collect();
+            print!("{}", format_entitlement_list(&entitlements, format)?);
+        }
+        Commands::KnownEntitlements { since, format } => {
+            let entitlements = match since {
+                Some(since) => provisioned_entitlements_since(since),
+                None => PROVISIONED_ENTITLEMENTS.to_vec(),
+            };
+            print!("{}", format_entitlement_list(&entitlements, format)?);
+        }
+        Commands::Explain {
+            entitlements,
+            format,
+        } => {
+            print!("{}", format_explanations(&entitlements, format)?);
+        }
+        Commands::AuditList {
+            online,
+            url,
+            format,
+        } => {
+            let reference = if online {
+                parse_apple_entitlements(&fetch_url(&url)?).with_context(|| {
+                    format!("Failed to read entitlements reference from {}", url)
+                })?
+            } else {
+                parse_entitlement_list(APPLE_ENTITLEMENTS_SNAPSHOT)?
+            };
+            let missing = missing_provisioned_entitlements(&reference);
+            print!("{}", format_entitlement_list(&missing, format)?);
+            if !missing.is_empty() {
+                let source = if online {
+                    url.as_str()
+                } else {
+                    "the bundled snapshot"
+                };
+                eprintln!(
+                    "Some entitlements in {} look provisioned but aren't considered provisioned",
+                    source
+                );
+                return Ok(ExitCode::FAILURE);
+            }
+        }
+        Commands::Version { format } => {
+            let info = VersionInfo {
+                version: env!("CARGO_PKG_VERSION"),
+                codesign_path: codesign.path(),
+            };
+            print!("{}", format_version(&info, format)?);
+        }
+        Commands::Completions { shell, output } => match output {
+            Some(output_dir) => {
+                let path = shell
+                    .generate_to(&mut Cli::command(), &output_dir)
+                    .context("Failed to write completions")?;
+                verbosity.info(format!("Wrote {}", path.display()));
+            }
+            None => shell.generate(&mut Cli::command(), &mut std::io::stdout()),
+        },
+        Commands::Man { output } => match output {
+            Some(output_dir) => {
+                clap_mangen::generate_to(Cli::command(), &output_dir)
+                    .context("Failed to write man pages")?;
+                verbosity.info(format!("Wrote man pages to {}", output_dir.display()));
+            }
+            None => clap_mangen::Man::new(Cli::command())
+                .render(&mut std::io::stdout())
+                .context("Failed to write man page")?,
+        },
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use provisioned_entitlement_stripper::get_provisioned_entitlements;
+
+    fn plain(styled: String) -> String {
+        anstream::adapter::strip_str(&styled).to_string()
+    }
+
+    #[test]
+    fn test_read_entitlements_file() {
+        let entitlements_xml = br#"<?xml version="1.0" encoding="UTF-8"?><!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "https://www.apple.com/DTDs/PropertyList-1.0.dtd"><plist version="1.0"><dict><key>com.apple.developer.team-identifier</key><string>AAAAAAAAAA</string><key>com.apple.security.device.camera</key><true/></dict></plist>"#;
+        let path = std::env::temp_dir().join(format!(
+            "provisioned-entitlement-stripper-test-{}.xml",
+            std::process::id()
+        ));
+        fs::write(&path, entitlements_xml).unwrap();
+        let entitlements = read_entitlements_file(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            provisioned_entitlement_stripper::get_provisioned_entitlements(
+                &entitlements.unwrap(),
+                &[]
+            )
+            .unwrap(),
+            ["com.apple.developer.team-identifier"]
+        );
+    }
+
+    #[test]
+    fn test_read_entitlements_file_missing() {
+        let path = Path::new("/nonexistent/entitlements.xml");
+        assert!(read_entitlements_file(path).is_err());
+    }
+
+    #[test]
+    fn test_parse_entitlements_empty() {
+        let error = parse_entitlements(b"").unwrap_err();
+        assert_eq!(error.to_string(), "Entitlements input is empty");
+        let error = parse_entitlements(b" \n").unwrap_err();
+        assert_eq!(error.to_string(), "Entitlements input is empty");
+    }
+
+    #[test]
+    fn test_parse_entitlements_invalid() {
+        let error = parse_entitlements(b"<plist").unwrap_err();
+        assert_eq!(error.to_string(), "Failed to parse entitlements plist");
+        let error = parse_entitlements(b"not a plist").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Failed to parse codesign entitlements text"
+        );
+    }
+
+    #[test]
+    fn test_from_stdin_conflicts() {
+        assert!(
+            Cli::try_parse_from([
+                "provisioned-entitlement-stripper",
+                "dry-run",
+                "App.app",
+                "--from-stdin",
+            ])
+            .is_err()
+        );
+        assert!(
+            Cli::try_parse_from([
+                "provisioned-entitlement-stripper",
+                "dry-run",
+                "--from-file",
+                "entitlements.xml",
+                "--from-stdin",
+            ])
+            .is_err()
+        );
+        assert!(
+            Cli::try_parse_from([
+                "provisioned-entitlement-stripper",
+                "strip",
+                "--from-stdin",
+                "-o",
+                "out.xml",
+            ])
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_from_file_conflicts_with_app_path() {
+        assert!(
+            Cli::try_parse_from([
+                "provisioned-entitlement-stripper",
+                "dry-run",
+                "App.app",
+                "--from-file",
+                "entitlements.xml",
+            ])
+            .is_err()
+        );
+        assert!(
+            Cli::try_parse_from([
+                "provisioned-entitlement-stripper",
+                "dry-run",
+                "--from-file",
+                "entitlements.xml",
+            ])
+            .is_ok()
+        );
+        assert!(Cli::try_parse_from(["provisioned-entitlement-stripper", "dry-run"]).is_err());
+    }
+
+    fn plist_output(format: PlistFormat) -> PlistOutputArgs {
+        PlistOutputArgs {
+            format: Some(format),
+            doctype: Doctype::Http,
+            indent: None,
+            tabs: false,
+            no_atomic: false,
+            sort_output_keys: false,
+            no_doctype: false,
+            no_xml_declaration: false,
+        }
+    }
+
+    #[test]
+    fn test_doctype() {
+        let entitlements = plist::Value::Dictionary(plist::Dictionary::new());
+        let mut xml = Vec::new();
+        plist::to_writer_xml(&mut xml, &entitlements).unwrap();
+        let doctype = |doctype: Doctype| String::from_utf8(doctype.apply(xml.clone())).unwrap();
+
+        assert_eq!(
+            doctype(Doctype::Http),
+            String::from_utf8(xml.clone()).unwrap()
+        );
+        assert!(doctype(Doctype::Http).contains(PLIST_DOCTYPE));
+        assert!(doctype(Doctype::Https).contains(
+            r#"<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "https://www.apple.com/DTDs/PropertyList-1.0.dtd">"#
+        ));
+        assert_eq!(
+            doctype(Doctype::None),
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<plist version=\"1.0\">\n<dict/>\n</plist>"
+        );
+    }
+
+    #[test]
+    fn test_write_entitlements_binary_round_trip() {
+        let entitlements_xml = br#"<?xml version="1.0" encoding="UTF-8"?><!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "https://www.apple.com/DTDs/PropertyList-1.0.dtd"><plist version="1.0"><dict><key>com.apple.developer.team-identifier</key><string>AAAAAAAAAA</string><key>com.apple.security.device.camera</key><true/></dict></plist>"#;
+        let mut entitlements = parse_entitlements(entitlements_xml).unwrap();
+        remove_entitlements(
+            &mut entitlements,
+            &strip_set(&PROVISIONED_ENTITLEMENTS, &[], &[]),
+        )
+        .unwrap();
+
+        let path = std::env::temp_dir().join(format!(
+            "provisioned-entitlement-stripper-test-binary-{}.plist",
+            std::process::id()
+        ));
+        write_entitlements(&entitlements, &path, plist_output(PlistFormat::Binary)).unwrap();
+        let bytes = fs::read(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert!(bytes.starts_with(b"bplist00"));
+        let read_back = parse_entitlements(&bytes).unwrap();
+        assert!(
+            get_provisioned_entitlements(&read_back, &[])
+                .unwrap()
+                .is_empty()
+        );
+        assert_eq!(read_back, entitlements);
+    }
+
+    #[test]
+    fn test_write_entitlements_atomic() {
+        let dir = std::env::temp_dir().join(format!(
+            "provisioned-entitlement-stripper-atomic-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let output_path = dir.join("out.xml");
+        fs::write(&output_path, b"old").unwrap();
+
+        let entitlements = plist::Value::Dictionary(plist::Dictionary::new());
+        write_entitlements(&entitlements, &output_path, plist_output(PlistFormat::Xml)).unwrap();
+        let contents = fs::read(&output_path).unwrap();
+        let files = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(parse_entitlements(&contents).unwrap(), entitlements);
+        assert_eq!(files, 1);
+    }
+
+    #[test]
+    fn test_write_signing_entitlements_existing_file() {
+        let dir = create_private_temp_dir().unwrap();
+        let path = dir.join("stripped.entitlements");
+        let entitlements = plist::Value::Dictionary(plist::Dictionary::new());
+        write_signing_entitlements(&entitlements, &path).unwrap();
+        let written = fs::read(&path).unwrap();
+
+        fs::write(&path, b"existing").unwrap();
+        let error = write_signing_entitlements(&entitlements, &path).unwrap_err();
+        let existing = fs::read(&path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(parse_entitlements(&written).unwrap(), entitlements);
+        assert_eq!(
+            error.to_string(),
+            "Failed to create entitlements file to sign with"
+        );
+        assert_eq!(existing, b"existing");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_signing_entitlements_symlink() {
+        let dir = create_private_temp_dir().unwrap();
+        let path = dir.join("stripped.entitlements");
+        let target_path = dir.join("target");
+        fs::write(&target_path, b"target").unwrap();
+        std::os::unix::fs::symlink(&target_path, &path).unwrap();
+
+        let entitlements = plist::Value::Dictionary(plist::Dictionary::new());
+        let result = write_signing_entitlements(&entitlements, &path);
+        let target = fs::read(&target_path).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(result.is_err());
+        assert_eq!(target, b"target");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_create_private_temp_dir() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let first = create_private_temp_dir().unwrap();
+        let second = create_private_temp_dir().unwrap();
+        let mode = fs::metadata(&first).unwrap().permissions().mode();
+        fs::remove_dir(&first).unwrap();
+        fs::remove_dir(&second).unwrap();
+        assert_ne!(first, second);
+        assert_eq!(mode & 0o777, 0o700);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_entitlements_existing_temp_file() {
+        let dir = std::env::temp_dir().join(format!(
+            "provisioned-entitlement-stripper-existing-temp-{}",
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let output_path = dir.join("out.xml");
+        let target_path = dir.join("target");
+        fs::write(&target_path, b"target").unwrap();
+        let temp_path = dir.join(format!(".out.xml.tmp-{}", std::process::id()));
+        std::os::unix::fs::symlink(&target_path, &temp_path).unwrap();
+
+        let entitlements = plist::Value::Dictionary(plist::Dictionary::new());
+        write_entitlements(&entitlements, &output_path, plist_output(PlistFormat::Xml)).unwrap();
+        let contents = fs::read(&output_path).unwrap();
+        let target = fs::read(&target_path).unwrap();
+        let temp_is_symlink = fs::symlink_metadata(&temp_path)
+            .unwrap()
+            .file_type()
+            .is_symlink();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(parse_entitlements(&contents).unwrap(), entitlements);
+        assert_eq!(target, b"target");
+        assert!(temp_is_symlink);
+    }
+
+    #[test]
+    fn test_format_known_entitlements() {
+        let text = format_entitlement_list(&PROVISIONED_ENTITLEMENTS, OutputFormat::Text).unwrap();
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines, *PROVISIONED_ENTITLEMENTS);
+
+        let json = format_entitlement_list(&PROVISIONED_ENTITLEMENTS, OutputFormat::Json).unwrap();
+        let entitlements: Vec<String> = serde_json::from_str(&json).unwrap();
+        assert_eq!(entitlements, *PROVISIONED_ENTITLEMENTS);
+    }
+
+    #[test]
+    fn test_strip_stats() {
+        let mut stripped = plist::Dictionary::new();
+        stripped.insert("com.apple.security.app-sandbox".into(), true.into());
+        let mut original = stripped.clone();
+        original.insert(
+            "com.apple.developer.team-identifier".into(),
+            "AAAAAAAAAA".into(),
+        );
+        original.insert("com.example.empty".into(), plist::Dictionary::new().into());
+        let removed = [
+            "com.apple.developer.team-identifier".to_string(),
+            "com.example.settings/application-identifier".to_string(),
+        ];
+        let mut stats = StripStats::new(
+            &plist::Value::Dictionary(original),
+            &plist::Value::Dictionary(stripped),
+            &removed,
+        );
+        assert_eq!(
+            stats,
+            StripStats {
+                present: 3,
+                remaining: 1,
+                nested_removed: 1,
+            }
+        );
+        assert_eq!(
+            stats.to_string(),
+            "Removed 2 of 3 entitlements (1 remaining)\nRemoved 1 nested entitlement"
+        );
+        stats.add(StripStats {
+            present: 4,
+            remaining: 3,
+            nested_removed: 0,
+        });
+        assert_eq!(
+            stats.to_string(),
+            "Removed 3 of 7 entitlements (4 remaining)\nRemoved 1 nested entitlement"
+        );
+    }
+
+    fn batch_output_path_for(template: &str, format: PlistFormat) -> Result<PathBuf> {
+        let entitlements =
+            read_entitlements_file(Path::new("tests/fixtures/provisioned.xml")).unwrap();
+        batch_output_path(
+            Path::new("/Applications/Example.app"),
+            &entitlements,
+            Path::new("out"),
+            &NameTemplate::parse(template).unwrap(),
+            format,
+        )
+    }
+
+    #[test]
+    fn test_batch_output_path() {
+        assert_eq!(
+            batch_output_path_for("{app}.entitlements.{ext}", PlistFormat::Xml).unwrap(),
+            Path::new("out/Example.entitlements.xml")
+        );
+        assert_eq!(
+            batch_output_path_for("{app}.entitlements.{ext}", PlistFormat::Binary).unwrap(),
+            Path::new("out/Example.entitlements.plist")
+        );
+        assert_eq!(
+            batch_output_path_for("{bundle_id}.entitlements", PlistFormat::Xml).unwrap(),
+            Path::new("out/com.example.example.entitlements")
+        );
+        assert_eq!(
+            batch_output_path_for("{team}-{app}.{ext}", PlistFormat::Xml).unwrap(),
+            Path::new("out/AAAAAAAAAA-Example.xml")
+        );
+        assert!(batch_output_path_for("../{app}", PlistFormat::Xml).is_err());
+    }
+
+    #[test]
+    fn test_batch_output_path_unicode() {
+        let entitlements =
+            read_entitlements_file(Path::new("tests/fixtures/provisioned.xml")).unwrap();
+        let template = NameTemplate::parse("{app} ({team}).{ext}").unwrap();
+        assert_eq!(
+            batch_output_path(
+                Path::new("/Users/me/My Apps/Café.app"),
+                &entitlements,
+                Path::new("/Users/me/Entitlements Für Apps"),
+                &template,
+                PlistFormat::Xml,
+            )
+            .unwrap(),
+            Path::new("/Users/me/Entitlements Für Apps/Café (AAAAAAAAAA).xml")
+        );
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::ffi::OsStrExt;
+
+            let app_path = Path::new(std::ffi::OsStr::from_bytes(b"/Apps/Caf\xe9.app"));
+            assert_eq!(
+                batch_output_path(
+                    app_path,
+                    &entitlements,
+                    Path::new("out"),
+                    &template,
+                    PlistFormat::Xml
+                )
+                .unwrap()
+                .as_os_str()
+                .as_bytes(),
+                b"out/Caf\xe9 (AAAAAAAAAA).xml"
+            );
+        }
+    }
+
+    #[test]
+    fn test_name_template_invalid() {
+        assert_eq!(
+            NameTemplate::parse("{app}.{name}").unwrap_err().to_string(),
+            "Unknown token {name} in name template, expected {app}, {bundle_id}, {team} and {ext}"
+        );
+        assert_eq!(
+            NameTemplate::parse("{app").unwrap_err().to_string(),
+            "Unmatched { in name template"
+        );
+        assert_eq!(
+            NameTemplate::parse("app}").unwrap_err().to_string(),
+            "Unmatched } in name template"
+        );
+    }
+
+    #[test]
+    fn test_name_template_bundle_id_matches_dry_run() {
+        let mut dictionary = plist::Dictionary::new();
+        dictionary.insert(
+            "com.apple.application-identifier".to_string(),
+            plist::Value::String("com.example.example".to_string()),
+        );
+        let entitlements = plist::Value::Dictionary(dictionary);
+        let template = NameTemplate::parse("{bundle_id}").unwrap();
+        let render = |template: &NameTemplate| {
+            template.render(Path::new("Example.app"), &entitlements, PlistFormat::Xml)
+        };
+        assert_eq!(render(&template).unwrap(), "com.example.example");
+        assert_eq!(
+            dry_run_bundle_identifier(None, &entitlements)
+                .unwrap()
+                .as_deref(),
+            Some("com.example.example")
+        );
+        assert_eq!(
+            render(&NameTemplate::parse("{team}").unwrap())
+                .unwrap_err()
+                .to_string(),
+            "The application identifier has no team ID prefix"
+        );
+    }
+
+    #[test]
+    fn test_name_template_missing_bundle_id() {
+        let error = NameTemplate::parse("{bundle_id}")
+            .unwrap()
+            .render(
+                Path::new("Example.app"),
+                &plist::Value::Dictionary(plist::Dictionary::new()),
+                PlistFormat::Xml,
+            )
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Entitlements have no application identifier to read the bundle ID or team from"
+        );
+    }
+
+    #[test]
+    fn test_batch_output_dir_conflicts() {
+        assert!(
+            Cli::try_parse_from([
+                "provisioned-entitlement-stripper",
+                "strip",
+                "A.app",
+                "B.app",
+                "--output-dir",
+                "out",
+                "-o",
+                "out.xml",
+            ])
+            .is_err()
+        );
+        assert!(
+            Cli::try_parse_from([
+                "provisioned-entitlement-stripper",
+                "strip",
+                "A.app",
+                "B.app",
+                "--output-dir",
+                "out",
+            ])
+            .is_ok()
+        );
+        assert!(
+            Cli::try_parse_from(["provisioned-entitlement-stripper", "strip", "A.app"]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_audit_log() {
+        let path = std::env::temp_dir().join(format!(
+            "provisioned-entitlement-stripper-audit-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = fs::remove_file(&path);
+        let mut audit_log = AuditLog::open(&path, None).unwrap();
+        let removed = vec!["com.apple.developer.team-identifier".to_string()];
+        audit_log
+            .record(
+                Path::new("A.app"),
+                Some(Path::new("out/A.entitlements.xml")),
+                &Ok(&removed),
+            )
+            .unwrap();
+        let error = anyhow::anyhow!("codesign failed").context("Failed to get entitlements");
+        audit_log
+            .record(Path::new("B.app"), None, &Err(&error))
+            .unwrap();
+        let contents = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(
+            contents,
+            "{\"app\":\"A.app\",\"removed\":[\"com.apple.developer.team-identifier\"],\"output\":\"out/A.entitlements.xml\",\"status\":\"ok\",\"error\":null}\n\
+             {\"app\":\"B.app\",\"removed\":[],\"output\":null,\"status\":\"error\",\"error\":\"Failed to get entitlements: codesign failed\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_batch_summary() {
+        let mut summary = BatchSummary::new(Some(PathBuf::from("/apps")));
+        let removed = vec![
+            "com.apple.application-identifier".to_string(),
+            "com.apple.developer.team-identifier".to_string(),
+        ];
+        summary.record(
+            Path::new("/apps/A.app"),
+            Some(Path::new("/apps/out/A.entitlements.xml")),
+            &Ok(&removed),
+        );
+        let removed = vec!["com.apple.developer.team-identifier".to_string()];
+        summary.record(Path::new("/apps/B.app"), None, &Ok(&removed));
+        let error = anyhow::anyhow!("codesign failed");
+        summary.record(Path::new("/other/C.app"), None, &Err(&error));
+
+        assert_eq!(
+            serde_json::to_value(&summary).unwrap(),
+            serde_json::json!({
+                "total": 3,
+                "failed": 1,
+                "removed": {
+                    "com.apple.application-identifier": 1,
+                    "com.apple.developer.team-identifier": 2,
+                },
+                "apps": [
+                    {
+                        "app": "A.app",
+                        "removed": [
+                            "com.apple.application-identifier",
+                            "com.apple.developer.team-identifier",
+                        ],
+                        "output": "out/A.entitlements.xml",
+                        "status": "ok",
+                        "error": null,
+                    },
+                    {
+                        "app": "B.app",
+                        "removed": ["com.apple.developer.team-identifier"],
+                        "output": null,
+                        "status": "ok",
+                        "error": null,
+                    },
+                    {
+                        "app": "/other/C.app",
+                        "removed": [],
+                        "output": null,
+                        "status": "error",
+                        "error": "codesign failed",
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn test_format_version() {
+        let info = VersionInfo {
+            version: "1.2.3",
+            codesign_path: Path::new("/usr/bin/codesign"),
+        };
+        assert_eq!(
+            format_version(&info, OutputFormat::Text).unwrap(),
+            "1.2.3\n"
+        );
+        assert_eq!(
+            format_version(&info, OutputFormat::Json).unwrap(),
+            "{\"version\":\"1.2.3\",\"codesign_path\":\"/usr/bin/codesign\"}\n"
+        );
+    }
+
+    #[test]
+    fn test_diff_lines() {
+        let original = "<dict>\n<key>a</key>\n<true/>\n<key>b</key>\n<false/>\n</dict>\n";
+        let stripped = "<dict>\n<key>b</key>\n<false/>\n</dict>\n";
+        assert_eq!(
+            diff_lines(original, stripped),
+            "--- original\n+++ stripped\n <dict>\n-<key>a</key>\n-<true/>\n <key>b</key>\n <false/>\n </dict>\n"
+        );
+    }
+
+    #[test]
+    fn test_diff_lines_added() {
+        assert_eq!(
+            diff_lines("a\nb\n", "a\nc\n"),
+            "--- original\n+++ stripped\n a\n-b\n+c\n"
+        );
+    }
+
+    #[test]
+    fn test_diff_lines_unchanged() {
+        assert_eq!(
+            diff_lines("a\nb\n", "a\nb\n"),
+            "--- original\n+++ stripped\n a\n b\n"
+        );
+    }
+
+    #[test]
+    fn test_format_dry_run_text() {
+        let result = DryRunResult {
+            app: Some(PathBuf::from("App.app")),
+            bundle_identifier: None,
+            provisioned_entitlements: vec![
+                "com.apple.application-identifier".to_string(),
+                "com.apple.developer.team-identifier".to_string(),
+            ],
+            debug_entitlements: Vec::new(),
+            values: None,
+            arch: None,
+            signing_info: None,
+        };
+        assert_eq!(
+            plain(format_dry_run(&result, OutputFormat::Text, Verbosity::Normal).unwrap()),
+            "Provisioned entitlements:\n- com.apple.application-identifier\n- com.apple.developer.team-identifier\n"
+        );
+    }
+
+    #[test]
+    fn test_format_dry_run_text_styled() {
+        let result = DryRunResult {
+            app: Some(PathBuf::from("App.app")),
+            bundle_identifier: None,
+            provisioned_entitlements: vec!["com.apple.developer.team-identifier".to_string()],
+            debug_entitlements: Vec::new(),
+            values: None,
+            arch: None,
+            signing_info: None,
+        };
+        assert_eq!(
+            format_dry_run(&result, OutputFormat::Text, Verbosity::Normal).unwrap(),
+            "\x1b[1mProvisioned entitlements:\x1b[0m\n- \x1b[31mcom.apple.developer.team-identifier\x1b[0m\n"
+        );
+    }
+
+    #[test]
+    fn test_format_dry_run_text_values() {
+        let mut values = plist::Dictionary::new();
+        values.insert(
+            "com.apple.developer.team-identifier".to_string(),
+            plist::Value::String("AAAAAAAAAA".to_string()),
+        );
+        values.insert(
+            "com.apple.security.application-groups".to_string(),
+            plist::Value::Array(vec![
+                plist::Value::String("AAAAAAAAAA.com.example.a".to_string()),
+                plist::Value::String("AAAAAAAAAA.com.example.b".to_string()),
+            ]),
+        );
+        let result = DryRunResult {
+            app: Some(PathBuf::from("App.app")),
+            bundle_identifier: None,
+            provisioned_entitlements: vec![
+                "com.apple.developer.team-identifier".to_string(),
+                "com.apple.security.application-groups".to_string(),
+            ],
+            debug_entitlements: Vec::new(),
+            values: Some(values),
+            arch: None,
+            signing_info: None,
+        };
+        assert_eq!(
+            plain(format_dry_run(&result, OutputFormat::Text, Verbosity::Normal).unwrap()),
+            "Provisioned entitlements:\n- com.apple.developer.team-identifier: \"AAAAAAAAAA\"\n- com.apple.security.application-groups: [\"AAAAAAAAAA.com.example.a\",\"AAAAAAAAAA.com.example.b\"]\n"
+        );
+        assert_eq!(
+            format_dry_run(&result, OutputFormat::Json, Verbosity::Normal).unwrap(),
+            "{\"app\":\"App.app\",\"provisioned_entitlements\":[\"com.apple.developer.team-identifier\",\"com.apple.security.application-groups\"],\"values\":{\"com.apple.developer.team-identifier\":\"AAAAAAAAAA\",\"com.apple.security.application-groups\":[\"AAAAAAAAAA.com.example.a\",\"AAAAAAAAAA.com.example.b\"]}}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_dry_run_text_signing_info() {
+        let result = DryRunResult {
+            app: Some(PathBuf::from("App.app")),
+            bundle_identifier: None,
+            provisioned_entitlements: vec!["com.apple.developer.team-identifier".to_string()],
+            debug_entitlements: Vec::new(),
+            values: None,
+            arch: None,
+            signing_info: Some(SigningInfo {
+                identifier: Some("com.example.example".to_string()),
+                team_identifier: None,
+                authorities: vec!["Apple Root CA".to_string()],
+            }),
+        };
+        assert_eq!(
+            plain(format_dry_run(&result, OutputFormat::Text, Verbosity::Normal).unwrap()),
+            "Provisioned entitlements:\n- com.apple.developer.team-identifier\nSigning info:\n- Identifier: com.example.example\n- TeamIdentifier: not set\n- Authority: Apple Root CA\n"
+        );
+    }
+
+    #[test]
+    fn test_format_dry_run_text_empty() {
+        let result = DryRunResult {
+            app: Some(PathBuf::from("App.app")),
+            bundle_identifier: None,
+            provisioned_entitlements: vec![],
+            debug_entitlements: Vec::new(),
+            values: None,
+            arch: None,
+            signing_info: None,
+        };
+        assert_eq!(
+            plain(format_dry_run(&result, OutputFormat::Text, Verbosity::Normal).unwrap()),
+            "No provisioned entitlements found\n"
+        );
+    }
+
+    #[test]
+    fn test_format_dry_run_text_empty_quiet() {
+        let result = DryRunResult {
+            app: Some(PathBuf::from("App.app")),
+            bundle_identifier: None,
+            provisioned_entitlements: vec![],
+            debug_entitlements: Vec::new(),
+            values: None,
+            arch: None,
+            signing_info: None,
+        };
+        assert_eq!(
+            plain(format_dry_run(&result, OutputFormat::Text, Verbosity::Quiet).unwrap()),
+            ""
+        );
+        assert_eq!(
+            format_dry_run(&result, OutputFormat::Json, Verbosity::Quiet).unwrap(),
+            "{\"app\":\"App.app\",\"provisioned_entitlements\":[]}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_dry_run_json() {
+        let result = DryRunResult {
+            app: Some(PathBuf::from("App.app")),
+            bundle_identifier: None,
+            provisioned_entitlements: vec!["com.apple.developer.team-identifier".to_string()],
+            debug_entitlements: Vec::new(),
+            values: None,
+            arch: None,
+            signing_info: None,
+        };
+        assert_eq!(
+            format_dry_run(&result, OutputFormat::Json, Verbosity::Normal).unwrap(),
+            "{\"app\":\"App.app\",\"provisioned_entitlements\":[\"com.apple.developer.team-identifier\"]}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_dry_run_json_empty() {
+        let result = DryRunResult {
+            app: Some(PathBuf::from("App.app")),
+            bundle_identifier: None,
+            provisioned_entitlements: vec![],
+            debug_entitlements: Vec::new(),
+            values: None,
+            arch: None,
+            signing_info: None,
+        };
+        assert_eq!(
+            format_dry_run(&result, OutputFormat::Json, Verbosity::Normal).unwrap(),
+            "{\"app\":\"App.app\",\"provisioned_entitlements\":[]}\n"
+        );
+    }
+
+    #[test]
+    fn test_format_dry_run_bundles_text() {
+        let results = [
+            DryRunResult {
+                app: Some(PathBuf::from("App.app")),
+                bundle_identifier: None,
+                provisioned_entitlements: vec!["com.apple.developer.team-identifier".to_string()],
+                debug_entitlements: Vec::new(),
+                values: None,
+                arch: None,
+                signing_info: None,
+            },
+            DryRunResult {
+                app: Some(PathBuf::from("App.app/Contents/PlugIns/Widget.appex")),
+                bundle_identifier: None,
+                provisioned_entitlements: vec![],
+                debug_entitlements: Vec::new(),
+                values: None,
+                arch: None,
+                signing_info: None,
+            },
+        ];
+        assert_eq!(
+            plain(format_dry_run_bundles(&results, OutputFormat::Text, Verbosity::Normal).unwrap()),
+            "App.app:\nProvisioned entitlements:\n- com.apple.developer.team-identifier\n\nApp.app/Contents/PlugIns/Widget.appex:\nNo provisioned entitlements found\n"
+        );
+    }
+
+    #[test]
+    fn test_format_dry_run_archs_text() {
+        let results = [
+            DryRunResult {
+                app: Some(PathBuf::from("App.app")),
+                bundle_identifier: None,
+                provisioned_entitlements: vec!["com.apple.developer.team-identifier".to_string()],
+                debug_entitlements: Vec::new(),
+                values: None,
+                arch: Some("x86_64".to_string()),
+                signing_info: None,
+            },
+            DryRunResult {
+                app: Some(PathBuf::from("App.app")),
+                bundle_identifier: None,
+                provisioned_entitlements: vec![],
+                debug_entitlements: Vec::new(),
+                values: None,
+                arch: Some("arm64".to_string()),
+                signing_info: None,
+            },
+        ];
+        assert_eq!(
+            plain(format_dry_run_bundles(&results, OutputFormat::Text, Verbosity::Normal).unwrap()),
+            "x86_64:\nProvisioned entitlements:\n- com.apple.developer.team-identifier\n\narm64:\nNo provisioned entitlements found\n"
+        );
+    }
+
+    #[test]
+    fn test_dry_run_all_archs_conflicts() {
+        assert!(
+            Cli::try_parse_from([
+                "provisioned-entitlement-stripper",
+                "dry-run",
+                "--all-archs",
+                "--from-file",
+                "a.xml"
+            ])
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_format_dry_run_bundles_json() {
+        let results = [DryRunResult {
+            app: Some(PathBuf::from("App.app")),
+            bundle_identifier: None,
+            provisioned_entitlements: vec![],
+            debug_entitlements: Vec::new(),
+            values: None,
+            arch: None,
+            signing_info: None,
+        }];
+        assert_eq!(
+            format_dry_run_bundles(&results, OutputFormat::Json, Verbosity::Normal).unwrap(),
+            "[{\"app\":\"App.app\",\"provisioned_entitlements\":[]}]\n"
+        );
+    }
+
+    #[test]
+    fn test_format_timestamp() {
+        let time = std::time::UNIX_EPOCH + std::time::Duration::from_secs(86400 * 3 + 3661);
+        assert_eq!(format_timestamp(time), "01:01:01");
+        assert_eq!(format_timestamp(std::time::UNIX_EPOCH), "00:00:00");
+    }
+
+    #[test]
+    fn test_annotate_xml() {
+        let xml = "<plist version=\"1.0\">\n<dict>\n\t<key>a&amp;b</key>\n\t<dict>\n\t\t<key>a&amp;b</key>\n\t\t<true/>\n\t</dict>\n\t<key>c</key>\n\t<true/>\n</dict>\n</plist>";
+        assert_eq!(
+            annotate_xml(xml, &["a&b".to_string()]).unwrap(),
+            "<plist version=\"1.0\">\n<dict>\n\t<!-- Provisioned, will be stripped -->\n\t<key>a&amp;b</key>\n\t<dict>\n\t\t<key>a&amp;b</key>\n\t\t<true/>\n\t</dict>\n\t<key>c</key>\n\t<true/>\n</dict>\n</plist>"
+        );
+    }
+
+    #[test]
+    fn test_map_parallel() {
+        let items: Vec<usize> = (0..20).collect();
+        for threads in [1, 3, 64] {
+            assert_eq!(
+                map_parallel(&items, threads, |item| item * 2),
+                (0..40).step_by(2).collect::<Vec<_>>()
+            );
+        }
+        assert!(map_parallel(&[] as &[usize], 4, |item| *item).is_empty());
+    }
+
+    #[test]
+    fn test_parse_apple_entitlements() {
+        let json = br#"{
+            "identifier": {"url": "doc://com.apple.documentation/documentation/BundleResources/Entitlements"},
+            "references": {
+                "doc://com.apple.documentation/documentation/BundleResources/Entitlements/com.apple.developer.healthkit": {
+                    "title": "HealthKit Entitlement",
+                    "name": "com.apple.developer.healthkit",
+                    "url": "/documentation/bundleresources/entitlements/com.apple.developer.healthkit"
+                },
+                "com.apple.developer.team-identifier": {
+                    "fragments": [{"kind": "identifier", "text": "com.apple.developer.team-identifier"}]
+                },
+                "keychain-access-groups": {"title": "Keychain Access Groups Entitlement"}
+            }
+        }"#;
+        assert_eq!(
+            parse_apple_entitlements(json).unwrap(),
+            [
+                "com.apple.developer.healthkit",
+                "com.apple.developer.team-identifier"
+            ]
+        );
+        assert!(parse_apple_entitlements(b"{}").is_err());
+        assert!(parse_apple_entitlements(b"<html>").is_err());
+    }
+
+    #[test]
+    fn test_missing_provisioned_entitlements() {
+        let reference = [
+            "com.apple.developer.team-identifier".to_string(),
+            "com.apple.developer.healthkit".to_string(),
+            "com.apple.security.app-sandbox".to_string(),
+        ];
+        assert_eq!(
+            missing_provisioned_entitlements(&reference),
+            ["com.apple.developer.healthkit"]
+        );
+    }
+
+    #[test]
+    fn test_apple_entitlements_snapshot() {
+        let snapshot = parse_entitlement_list(APPLE_ENTITLEMENTS_SNAPSHOT).unwrap();
+        assert!(
+            snapshot
+                .iter()
+                .all(|key| key.contains('.') || key.contains('-'))
+        );
+        let unique: HashSet<&String> = snapshot.iter().collect();
+        assert_eq!(unique.len(), snapshot.len());
+    }
+
+    fn plist_json(value: &plist::Value, indent: Option<usize>) -> String {
+        let mut output = Vec::new();
+        PlistOutputArgs {
+            indent,
+            ..plist_output(PlistFormat::PlistJson)
+        }
+        .write_json(&mut output, value)
+        .unwrap();
+        String::from_utf8(output).unwrap()
+    }
+
+    #[test]
+    fn test_plist_json() {
+        let mut dictionary = plist::Dictionary::new();
+        dictionary.insert("z".to_string(), plist::Value::Boolean(true));
+        dictionary.insert(
+            "a".to_string(),
+            plist::Value::Array(vec![plist::Value::String("TEAM.group".to_string())]),
+        );
+        dictionary.insert("int".to_string(), plist::Value::Integer((-1).into()));
+        dictionary.insert("uint".to_string(), plist::Value::Integer(u64::MAX.into()));
+        dictionary.insert("real".to_string(), plist::Value::Real(1.5));
+        dictionary.insert("data".to_string(), plist::Value::Data(b"hi!".to_vec()));
+        dictionary.insert(
+            "date".to_string(),
+            plist::Value::Date(plist::Date::from_xml_format("2024-01-02T03:04:05Z").unwrap()),
+        );
+        dictionary.insert("empty".to_string(), plist::Dictionary::new().into());
+        let value = plist::Value::Dictionary(dictionary);
+        assert_eq!(
+            plist_json(&value, None),
+            "{\n  \"z\": true,\n  \"a\": [\n    \"TEAM.group\"\n  ],\n  \"int\": -1,\n  \
+             \"uint\": 18446744073709551615,\n  \"real\": 1.5,\n  \"data\": \"aGkh\",\n  \
+             \"date\": \"2024-01-02T03:04:05Z\",\n  \"empty\": {}\n}"
+        );
+        assert_eq!(
+            plist_json(&value, Some(0)),
+            "{\"z\":true,\"a\":[\"TEAM.group\"],\"int\":-1,\"uint\":18446744073709551615,\
+             \"real\":1.5,\"data\":\"aGkh\",\"date\":\"2024-01-02T03:04:05Z\",\"empty\":{}}"
+        );
+    }
+
+    #[test]
+    fn test_plist_json_round_trip() {
+        let value = plist::Value::from_reader_xml(
+            b"<plist version=\"1.0\"><dict><key>com.apple.developer.icloud-services</key>\
+              <array><string>CloudKit</string></array><key>com.apple.security.app-sandbox</key>\
+              <true/><key>com.apple.developer.team-identifier</key><string>TEAM</string>\
+              </dict></plist>"
+                .as_slice(),
+        )
+        .unwrap();
+        let json: serde_json::Value = serde_json::from_str(&plist_json(&value, None)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "com.apple.developer.icloud-services": ["CloudKit"],
+                "com.apple.security.app-sandbox": true,
+                "com.apple.developer.team-identifier": "TEAM",
+            })
+        );
+    }
+
+    #[test]
+    fn test_plist_json_non_finite() {
+        let mut output = Vec::new();
+        let error = plist_output(PlistFormat::PlistJson)
+            .write_json(&mut output, &plist::Value::Real(f64::NAN))
+            .unwrap_err();
+        assert!(format!("{:#}", error).contains("NaN can't be represented in JSON"));
+    }
+
+    #[test]
+    fn test_config_parse() {
+        let config: Config = toml::from_str(
+            r#"
+codesign-path = "/opt/bin/codesign"
+extra = ["com.example.a", "com.example.b"]
+keep = ["com.apple.security.application-groups"]
+output-format = "plist-json"
+color = "never"
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            config.codesign_path.as_deref(),
+            Some(Path::new("/opt/bin/codesign"))
+        );
+        assert_eq!(config.extra, ["com.example.a", "com.example.b"]);
+        assert_eq!(config.keep, ["com.apple.security.application-groups"]);
+        assert!(matches!(config.output_format, Some(PlistFormat::PlistJson)));
+        assert!(matches!(config.color, Some(ColorMode::Never)));
+
+        let empty: Config = toml::from_str("").unwrap();
+        assert!(empty.codesign_path.is_none() && empty.extra.is_empty());
+        assert!(toml::from_str::<Config>("codesign_path = \"codesign\"").is_err());
+        assert!(toml::from_str::<Config>("color = \"sometimes\"").is_err());
+    }
+
+    #[test]
+    fn test_config_apply() {
+        let config = Config {
+            extra: vec!["com.example.a".to_string()],
+            output_format: Some(PlistFormat::Binary),
+            color: Some(ColorMode::Never),
+            ..Config::default()
+        };
+        let parse = |args: &[&str]| {
+            let matches = config
+                .apply(Cli::command())
+                .try_get_matches_from(args)
+                .unwrap();
+            Cli::from_arg_matches(&matches).unwrap()
+        };
+
+        let cli = parse(&[
+            "provisioned-entitlement-stripper",
+            "strip",
+            "A.app",
+            "-o",
+            "-",
+        ]);
+        assert!(matches!(cli.color, ColorMode::Never));
+        let Commands::Strip {
+            entitlement_args,
+            plist_output,
+            ..
+        } = cli.command
+        else {
+            panic!("expected strip");
+        };
+        assert_eq!(entitlement_args.extra, ["com.example.a"]);
+        assert!(matches!(plist_output.format, Some(PlistFormat::Binary)));
+
+        let cli = parse(&[
+            "provisioned-entitlement-stripper",
+            "strip",
+            "A.app",
+            "-o",
+            "-",
+            "--extra",
+            "com.example.b",
+            "--output-format",
+            "xml",
+            "--color",
+            "always",
+        ]);
+        assert!(matches!(cli.color, ColorMode::Always));
+        let Commands::Strip {
+            entitlement_args,
+            plist_output,
+            ..
+        } = cli.command
+        else {
+            panic!("expected strip");
+        };
+        assert_eq!(entitlement_args.extra, ["com.example.b"]);
+        assert!(matches!(plist_output.format, Some(PlistFormat::Xml)));
+    }
+
+    #[test]
+    fn test_find_config_arg() {
+        let find = |args: &[&str]| find_config_arg(args.iter().map(std::ffi::OsString::from));
+        assert_eq!(
+            find(&["--config", "a.toml", "dry-run"]),
+            Some(PathBuf::from("a.toml"))
+        );
+        assert_eq!(
+            find(&["dry-run", "--config=b.toml"]),
+            Some(PathBuf::from("b.toml"))
+        );
+        assert_eq!(find(&["dry-run", "A.app"]), None);
+        assert_eq!(find(&["dry-run", "--", "--config"]), None);
     }
 }
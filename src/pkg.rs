@@ -0,0 +1,222 @@
+use std::{
+    ffi::OsStr,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use crate::error::{Result, StripError};
+
+/// The pkgutil binary used when no other path is given.
+pub const DEFAULT_PKGUTIL_PATH: &str = "/usr/sbin/pkgutil";
+
+/// Returns whether a path looks like a macOS installer package.
+pub fn is_pkg(path: &Path) -> bool {
+    path.extension()
+        .and_then(OsStr::to_str)
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("pkg"))
+}
+
+/// The payload of a `.pkg` expanded into a temporary directory, which is removed when this is
+/// dropped.
+#[derive(Debug)]
+pub struct ExpandedPkg {
+    temp_dir: PathBuf,
+    app_path: PathBuf,
+}
+
+impl ExpandedPkg {
+    /// The path of the primary app in the expanded payload.
+    pub fn app_path(&self) -> &Path {
+        &self.app_path
+    }
+}
+
+impl Drop for ExpandedPkg {
+    fn drop(&mut self) {
+        if let Err(error) = fs::remove_dir_all(&self.temp_dir) {
+            log::warn!(
+                "Failed to remove temporary directory {}: {}",
+                self.temp_dir.display(),
+                error
+            );
+        }
+    }
+}
+
+/// Expands a `.pkg` with `pkgutil --expand-full` to a temporary directory and locates the primary
+/// app in its payload, which is the least deeply nested one.
+///
+/// Fails if there's no app, or several apps are equally shallow, as there's no telling which is
+/// the primary one.
+pub fn expand_pkg(pkgutil_path: &Path, pkg_path: &Path) -> Result<ExpandedPkg> {
+    static EXPANDED: AtomicUsize = AtomicUsize::new(0);
+
+    let temp_dir = std::env::temp_dir().join(format!(
+        "provisioned-entitlement-stripper-pkg-{}-{}",
+        std::process::id(),
+        EXPANDED.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::create_dir_all(&temp_dir)
+        .map_err(|error| StripError::io("Failed to create temporary directory", error))?;
+    // Construct this before expanding so the directory is cleaned up if anything fails
+    let mut expanded = ExpandedPkg {
+        temp_dir,
+        app_path: PathBuf::new(),
+    };
+    // pkgutil refuses to expand into a directory that already exists
+    let payload_dir = expanded.temp_dir.join("expanded");
+    log::debug!(
+        "Expanding {} to {}",
+        pkg_path.display(),
+        payload_dir.display()
+    );
+    let output = Command::new(pkgutil_path)
+        .arg("--expand-full")
+        .arg(pkg_path)
+        .arg(&payload_dir)
+        .output()
+        .map_err(|error| {
+            if error.kind() == std::io::ErrorKind::NotFound {
+                StripError::PkgutilNotFound {
+                    path: pkgutil_path.to_path_buf(),
+                }
+            } else {
+                StripError::io(
+                    format!("Failed to execute pkgutil at {}", pkgutil_path.display()),
+                    error,
+                )
+            }
+        })?;
+    if !output.status.success() {
+        return Err(StripError::InvalidPkg(format!(
+            "pkgutil failed with status {}, stderr: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr)
+        )));
+    }
+    expanded.app_path = find_primary_app(&payload_dir)?;
+    Ok(expanded)
+}
+
+fn find_primary_app(payload_dir: &Path) -> Result<PathBuf> {
+    let mut apps = Vec::new();
+    find_apps_in(payload_dir, 0, &mut apps)?;
+    let Some(min_depth) = apps.iter().map(|(depth, _)| *depth).min() else {
+        return Err(StripError::InvalidPkg(
+            "pkg has no app in its payload".to_string(),
+        ));
+    };
+    let mut primary: Vec<PathBuf> = apps
+        .into_iter()
+        .filter(|(depth, _)| *depth == min_depth)
+        .map(|(_, path)| path)
+        .collect();
+    if primary.len() > 1 {
+        primary.sort();
+        return Err(StripError::InvalidPkg(format!(
+            "pkg has {} apps at the same level of its payload: {}",
+            primary.len(),
+            primary
+                .iter()
+                .map(|path| path
+                    .strip_prefix(payload_dir)
+                    .unwrap_or(path)
+                    .display()
+                    .to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )));
+    }
+    Ok(primary.remove(0))
+}
+
+/// Collects the apps in `dir` with how deeply they're nested, without looking inside the apps
+/// themselves or following symlinks.
+fn find_apps_in(dir: &Path, depth: usize, apps: &mut Vec<(usize, PathBuf)>) -> Result<()> {
+    let read_dir_error =
+        |error| StripError::io(format!("Failed to read directory {}", dir.display()), error);
+    for entry in fs::read_dir(dir).map_err(read_dir_error)? {
+        let entry = entry.map_err(read_dir_error)?;
+        let file_type = entry.file_type().map_err(|error| {
+            StripError::io(
+                format!("Failed to get file type of {}", entry.path().display()),
+                error,
+            )
+        })?;
+        if !file_type.is_dir() {
+            continue;
+        }
+        let path = entry.path();
+        if path.extension() == Some(OsStr::new("app")) {
+            apps.push((depth, path));
+        } else {
+            find_apps_in(&path, depth + 1, apps)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!(
+            "provisioned-entitlement-stripper-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_is_pkg() {
+        assert!(is_pkg(Path::new("Example.pkg")));
+        assert!(is_pkg(Path::new("Example.PKG")));
+        assert!(!is_pkg(Path::new("Example.app")));
+    }
+
+    #[test]
+    fn test_find_primary_app() {
+        let dir = test_dir("pkg-primary");
+        for path in [
+            "Example.pkg/Payload/Applications/Example.app/Contents/PlugIns/Helper.app",
+            "Example.pkg/Payload/Library/Support/Other.app",
+            "Scripts",
+        ] {
+            fs::create_dir_all(dir.join(path)).unwrap();
+        }
+
+        let app = find_primary_app(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            app.unwrap(),
+            dir.join("Example.pkg/Payload/Applications/Example.app")
+        );
+    }
+
+    #[test]
+    fn test_find_primary_app_ambiguous() {
+        let dir = test_dir("pkg-ambiguous");
+        fs::create_dir_all(dir.join("Payload/B.app")).unwrap();
+        fs::create_dir_all(dir.join("Payload/A.app")).unwrap();
+
+        let error = find_primary_app(&dir).unwrap_err();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(
+            error.to_string(),
+            "pkg has 2 apps at the same level of its payload: Payload/A.app, Payload/B.app"
+        );
+    }
+
+    #[test]
+    fn test_expand_pkg_missing_pkgutil() {
+        let error =
+            expand_pkg(Path::new("/nonexistent/pkgutil"), Path::new("Example.pkg")).unwrap_err();
+        assert!(matches!(error, StripError::PkgutilNotFound { .. }));
+    }
+}
@@ -0,0 +1,124 @@
+use std::{fs, path::Path};
+
+use crate::error::{Result, StripError};
+
+const PLIST_START: &[u8] = b"<?xml";
+const PLIST_END: &[u8] = b"</plist>";
+
+/// Gets the entitlements granted by a provisioning profile, such as an app's
+/// `embedded.mobileprovision`.
+pub fn get_profile_entitlements(profile_path: &Path) -> Result<plist::Value> {
+    let bytes = fs::read(profile_path)
+        .map_err(|error| StripError::io("Failed to read provisioning profile", error))?;
+    parse_profile_entitlements(&bytes)
+}
+
+/// Parses the `Entitlements` dictionary out of the contents of a provisioning profile.
+pub fn parse_profile_entitlements(bytes: &[u8]) -> Result<plist::Value> {
+    let profile: plist::Value =
+        plist::from_bytes(extract_profile_plist(bytes)?).map_err(|error| {
+            StripError::parse_with("Failed to parse provisioning profile plist", error)
+        })?;
+    let mut profile = match profile {
+        plist::Value::Dictionary(profile) => profile,
+        profile => {
+            return Err(StripError::not_a_dictionary(
+                "Provisioning profile root",
+                &profile,
+            ));
+        }
+    };
+    let entitlements = profile
+        .remove("Entitlements")
+        .ok_or_else(|| StripError::parse("Provisioning profile has no Entitlements"))?;
+    if entitlements.as_dictionary().is_none() {
+        return Err(StripError::not_a_dictionary(
+            "Provisioning profile Entitlements",
+            &entitlements,
+        ));
+    }
+    Ok(entitlements)
+}
+
+/// Returns the XML plist embedded in a provisioning profile.
+///
+/// Profiles are a PKCS#7 signed-data message with the plist stored unencrypted as its content, so
+/// the plist can be found without verifying the signature or decoding the rest of the message.
+fn extract_profile_plist(bytes: &[u8]) -> Result<&[u8]> {
+    let no_plist = || StripError::parse("No plist found in provisioning profile");
+    let start = find(bytes, PLIST_START).ok_or_else(no_plist)?;
+    let end = find(&bytes[start..], PLIST_END).ok_or_else(no_plist)? + start + PLIST_END.len();
+    Ok(&bytes[start..end])
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROFILE_PLIST: &[u8] = br#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>AppIDName</key>
+	<string>Example</string>
+	<key>Entitlements</key>
+	<dict>
+		<key>application-identifier</key>
+		<string>AAAAAAAAAA.com.example.example</string>
+		<key>com.apple.developer.team-identifier</key>
+		<string>AAAAAAAAAA</string>
+	</dict>
+</dict>
+</plist>"#;
+
+    fn wrap(plist: &[u8]) -> Vec<u8> {
+        [
+            b"\x30\x80\x06\x09\x2a\x86\x48\x86\xf7\x0d\x01\x07\x02",
+            plist,
+            b"\x00\x00\x31\x00",
+        ]
+        .concat()
+    }
+
+    #[test]
+    fn test_parse_profile_entitlements() {
+        let entitlements = parse_profile_entitlements(&wrap(PROFILE_PLIST)).unwrap();
+        let keys: Vec<&str> = entitlements
+            .as_dictionary()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        assert_eq!(
+            keys,
+            [
+                "application-identifier",
+                "com.apple.developer.team-identifier"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_profile_entitlements_no_plist() {
+        let error = parse_profile_entitlements(b"\x30\x80\x00\x00").unwrap_err();
+        assert_eq!(error.to_string(), "No plist found in provisioning profile");
+    }
+
+    #[test]
+    fn test_parse_profile_entitlements_missing() {
+        let error = parse_profile_entitlements(&wrap(
+            br#"<?xml version="1.0" encoding="UTF-8"?><plist version="1.0"><dict></dict></plist>"#,
+        ))
+        .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Provisioning profile has no Entitlements"
+        );
+    }
+}
@@ -0,0 +1,1642 @@
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+fn run(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_provisioned-entitlement-stripper"))
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .output()
+        .unwrap()
+}
+
+fn run_with_stdin(args: &[&str], stdin: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_provisioned-entitlement-stripper"))
+        .args(args)
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn test_dry_run_exit_code_provisioned() {
+    let output = run(&[
+        "dry-run",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "--exit-code",
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Bundle identifier: com.example.example\nProvisioned entitlements:\n- com.apple.application-identifier\n- com.apple.developer.team-identifier\n"
+    );
+}
+
+#[test]
+fn test_dry_run_from_stdin() {
+    let provisioned = std::fs::read("tests/fixtures/provisioned.xml").unwrap();
+    let output = run_with_stdin(
+        &["dry-run", "--from-stdin", "--format", "json", "--exit-code"],
+        &provisioned,
+    );
+    assert_eq!(output.status.code(), Some(1));
+    let from_file = run(&[
+        "dry-run",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "--format",
+        "json",
+    ]);
+    assert_eq!(output.stdout, from_file.stdout);
+    assert!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .contains(r#""com.apple.developer.team-identifier""#)
+    );
+
+    let output = run_with_stdin(&["dry-run", "--from-stdin"], b"not a plist");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("Failed to get entitlements from stdin")
+    );
+}
+
+#[test]
+fn test_dry_run_exit_code_unprovisioned() {
+    let output = run(&[
+        "dry-run",
+        "--from-file",
+        "tests/fixtures/unprovisioned.xml",
+        "--exit-code",
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_dry_run_without_exit_code() {
+    let output = run(&["dry-run", "--from-file", "tests/fixtures/provisioned.xml"]);
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn test_dry_run_count_only() {
+    let output = run(&[
+        "dry-run",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "--count-only",
+        "--exit-code",
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "2\n");
+
+    let output = run(&[
+        "dry-run",
+        "--from-file",
+        "tests/fixtures/unprovisioned.xml",
+        "--count-only",
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "0\n");
+}
+
+#[test]
+fn test_dry_run_annotate() {
+    let output = run(&[
+        "dry-run",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "--annotate",
+        "--exit-code",
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<!-- Provisioned, will be stripped -->
+	<key>com.apple.application-identifier</key>
+	<string>AAAAAAAAAA.com.example.example</string>
+	<!-- Provisioned, will be stripped -->
+	<key>com.apple.developer.team-identifier</key>
+	<string>AAAAAAAAAA</string>
+	<key>com.apple.security.device.camera</key>
+	<true/>
+</dict>
+</plist>"#
+    );
+}
+
+#[test]
+fn test_strip_to_stdout() {
+    let output = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "-o",
+        "-",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>com.apple.security.device.camera</key>
+	<true/>
+</dict>
+</plist>"#
+    );
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_strip_codesign_text() {
+    let output = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/provisioned.txt",
+        "-o",
+        "-",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>com.apple.security.device.camera</key>
+	<true/>
+</dict>
+</plist>"#
+    );
+}
+
+#[test]
+fn test_strip_stats() {
+    let output = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "--stats",
+        "-o",
+        "-",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Removed 2 of 3 entitlements (1 remaining)\n"
+    );
+}
+
+#[test]
+fn test_strip_stats_deep() {
+    let output = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/nested.xml",
+        "--deep",
+        "--stats",
+        "-o",
+        "-",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Removed 1 of 2 entitlements (1 remaining)\nRemoved 1 nested entitlement\n"
+    );
+}
+
+#[test]
+fn test_strip_stats_strip_empty() {
+    let output = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/empty-values.xml",
+        "--strip-empty",
+        "--stats",
+        "-o",
+        "-",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Removed 4 of 5 entitlements (1 remaining)\n"
+    );
+}
+
+#[test]
+fn test_strip_doctype() {
+    let output = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "--doctype",
+        "https",
+        "-o",
+        "-",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.contains(
+        r#"<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "https://www.apple.com/DTDs/PropertyList-1.0.dtd">"#
+    ));
+
+    let output = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "--doctype",
+        "none",
+        "-o",
+        "-",
+    ]);
+    assert!(output.status.success());
+    assert!(
+        !String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("<!DOCTYPE")
+    );
+}
+
+#[test]
+fn test_dry_run_null() {
+    let output = run(&[
+        "dry-run",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "-0",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        output.stdout,
+        b"com.apple.application-identifier\0com.apple.developer.team-identifier\0"
+    );
+
+    let none = run(&[
+        "dry-run",
+        "--from-file",
+        "tests/fixtures/unprovisioned.xml",
+        "--null",
+    ]);
+    assert!(none.status.success());
+    assert!(none.stdout.is_empty());
+
+    let json = run(&[
+        "dry-run",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "--null",
+        "--format",
+        "json",
+    ]);
+    assert_eq!(json.status.code(), Some(2));
+    assert!(
+        String::from_utf8(json.stderr)
+            .unwrap()
+            .contains("'--null' cannot be used with '--format json'")
+    );
+}
+
+#[test]
+fn test_dry_run_quiet() {
+    let output = run(&[
+        "dry-run",
+        "--from-file",
+        "tests/fixtures/unprovisioned.xml",
+        "--quiet",
+    ]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_dry_run_list_file() {
+    let output = run(&[
+        "dry-run",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "--list-file",
+        "tests/fixtures/list.txt",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Bundle identifier: com.example.example\nProvisioned entitlements:\n- com.apple.security.device.camera\n"
+    );
+}
+
+#[test]
+fn test_dry_run_color() {
+    let args = ["dry-run", "--from-file", "tests/fixtures/provisioned.xml"];
+    let plain = run(&args);
+    assert!(!plain.stdout.contains(&b'\x1b'));
+
+    let colored = run(&[&args[..], &["--color", "always"]].concat());
+    assert!(colored.stdout.contains(&b'\x1b'));
+}
+
+#[test]
+fn test_strip_dry_run() {
+    let output_path = std::env::temp_dir().join(format!(
+        "provisioned-entitlement-stripper-strip-dry-run-{}.xml",
+        std::process::id()
+    ));
+    let output = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "-o",
+        output_path.to_str().unwrap(),
+        "--keep",
+        "com.apple.developer.team-identifier",
+        "--dry-run",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Bundle identifier: com.example.example\nProvisioned entitlements:\n- com.apple.application-identifier\n"
+    );
+    assert!(!output_path.exists());
+}
+
+#[test]
+fn test_check_up_to_date() {
+    let output = run(&[
+        "check",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "--expected",
+        "tests/fixtures/unprovisioned.xml",
+    ]);
+    assert_eq!(output.status.code(), Some(0));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_check_stale() {
+    let output = run(&[
+        "check",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "--expected",
+        "tests/fixtures/provisioned.xml",
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.starts_with("--- tests/fixtures/provisioned.xml\n+++ stripped\n"));
+    assert!(stdout.contains("-\t<key>com.apple.developer.team-identifier</key>\n"));
+}
+
+#[test]
+fn test_strip_warn_unknown() {
+    let args = [
+        "strip",
+        "--from-file",
+        "tests/fixtures/unknown.xml",
+        "-o",
+        "-",
+    ];
+    let quiet = run(&args);
+    assert!(quiet.status.success());
+    assert!(quiet.stderr.is_empty());
+
+    let warned = run(&[&args[..], &["--warn-unknown"]].concat());
+    assert!(warned.status.success());
+    assert_eq!(
+        String::from_utf8(warned.stderr).unwrap(),
+        "Warning: this entitlement was not stripped but might be provisioned: com.apple.developer.example-service\n"
+    );
+}
+
+#[test]
+fn test_merge() {
+    let output = run(&[
+        "merge",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "--base",
+        "tests/fixtures/base.xml",
+        "-o",
+        "-",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>com.apple.security.device.camera</key>
+	<false/>
+	<key>com.apple.security.network.client</key>
+	<true/>
+</dict>
+</plist>"#
+    );
+}
+
+#[test]
+fn test_strip_redact() {
+    let output = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "-o",
+        "-",
+        "--redact",
+        "com.apple.developer.team-identifier=XXXXXXXXXX",
+        "--redact",
+        "com.apple.application-identifier=XXXXXXXXXX.com.example.example",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>com.apple.application-identifier</key>
+	<string>XXXXXXXXXX.com.example.example</string>
+	<key>com.apple.developer.team-identifier</key>
+	<string>XXXXXXXXXX</string>
+	<key>com.apple.security.device.camera</key>
+	<true/>
+</dict>
+</plist>"#
+    );
+}
+
+#[test]
+fn test_strip_redact_invalid() {
+    let output = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "-o",
+        "-",
+        "--redact",
+        "com.apple.developer.team-identifier",
+    ]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_strip_fail_on_unknown_key() {
+    let allowed = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "-o",
+        "-",
+        "--fail-on-unknown-key",
+        "tests/fixtures/allowlist.txt",
+    ]);
+    assert!(allowed.status.success());
+
+    let unknown = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/unknown.xml",
+        "-o",
+        "-",
+        "--fail-on-unknown-key",
+        "tests/fixtures/allowlist.txt",
+    ]);
+    assert_eq!(unknown.status.code(), Some(1));
+    assert!(unknown.stdout.is_empty());
+    assert!(
+        String::from_utf8(unknown.stderr)
+            .unwrap()
+            .contains("Entitlements not in the allowlist: com.apple.developer.example-service")
+    );
+}
+
+#[test]
+fn test_dry_run_fail_on_unknown_key() {
+    let output = run(&[
+        "dry-run",
+        "--from-file",
+        "tests/fixtures/unknown.xml",
+        "--list-file",
+        "tests/fixtures/list.txt",
+        "--fail-on-unknown-key",
+        "tests/fixtures/allowlist.txt",
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("com.apple.developer.example-service, com.apple.developer.team-identifier")
+    );
+}
+
+#[test]
+fn test_strip_group_prefix() {
+    let args = [
+        "strip",
+        "--from-file",
+        "tests/fixtures/groups.xml",
+        "-o",
+        "-",
+    ];
+    let partial = run(&[&args[..], &["--group-prefix", "AAAAAAAAAA."]].concat());
+    assert!(partial.status.success());
+    assert_eq!(
+        String::from_utf8(partial.stdout).unwrap(),
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>com.apple.security.application-groups</key>
+	<array>
+		<string>group.com.example.custom</string>
+	</array>
+</dict>
+</plist>"#
+    );
+
+    let full = run(&[
+        &args[..],
+        &["--group-prefix", "AAAAAAAAAA.", "--group-prefix", "group."],
+    ]
+    .concat());
+    assert!(full.status.success());
+    assert!(
+        !String::from_utf8(full.stdout)
+            .unwrap()
+            .contains("application-groups")
+    );
+}
+
+#[test]
+fn test_completions_output() {
+    let dir = std::env::temp_dir().join(format!(
+        "provisioned-entitlement-stripper-completions-{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let output = run(&["completions", "zsh", "--output", dir.to_str().unwrap()]);
+    let written = dir.join("_provisioned-entitlement-stripper").is_file();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(output.status.success());
+    assert!(written);
+}
+
+#[test]
+fn test_man() {
+    let output = run(&["man"]);
+    assert!(output.status.success());
+    let page = String::from_utf8(output.stdout).unwrap();
+    assert!(page.starts_with(".ie \\n(.g .ds Aq \\(aq"));
+    assert!(page.contains("dry\\-run"));
+}
+
+#[test]
+fn test_man_output() {
+    let dir = std::env::temp_dir().join(format!(
+        "provisioned-entitlement-stripper-man-{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let output = run(&["man", "--output", dir.to_str().unwrap()]);
+    let main_page = dir.join("provisioned-entitlement-stripper.1").is_file();
+    let strip_page = dir
+        .join("provisioned-entitlement-stripper-strip.1")
+        .is_file();
+    std::fs::remove_dir_all(&dir).unwrap();
+    assert!(output.status.success());
+    assert!(main_page);
+    assert!(strip_page);
+}
+
+#[test]
+fn test_validate() {
+    let output = run(&["validate", "tests/fixtures/provisioned.xml"]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "com.apple.application-identifier\ncom.apple.developer.team-identifier\n"
+    );
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "tests/fixtures/provisioned.xml contains provisioned entitlements\n"
+    );
+
+    let output = run(&["validate", "tests/fixtures/unprovisioned.xml"]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_strict_parse() {
+    let output = run(&[
+        "--strict-parse",
+        "dry-run",
+        "--from-file",
+        "tests/fixtures/duplicates.xml",
+    ]);
+    assert!(output.status.success());
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains(r#"have a duplicate key "com.apple.developer.team-identifier""#)
+    );
+
+    let output = run(&["dry-run", "--from-file", "tests/fixtures/duplicates.xml"]);
+    assert!(output.status.success());
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_plist_version() {
+    let output = run(&["dry-run", "--from-file", "tests/fixtures/plist-version.xml"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "No provisioned entitlements found\n"
+    );
+    assert!(String::from_utf8(output.stderr).unwrap().contains(
+        r#"Entitlements from tests/fixtures/plist-version.xml are plist version "2.0", expected 1.0"#
+    ));
+
+    let args = [
+        "strip",
+        "--from-file",
+        "tests/fixtures/plist-version.xml",
+        "-o",
+        "-",
+    ];
+    let output = run(&args);
+    assert!(output.status.success());
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains(r#"are plist version "2.0", expected 1.0"#)
+    );
+
+    let output = run(&[&args[..], &["--strict"]].concat());
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains(r#"are plist version "2.0", expected 1.0"#)
+    );
+}
+
+#[test]
+fn test_strip_only() {
+    let output = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "--only",
+        "com.apple.developer.team-identifier",
+        "-o",
+        "-",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>com.apple.application-identifier</key>
+	<string>AAAAAAAAAA.com.example.example</string>
+	<key>com.apple.security.device.camera</key>
+	<true/>
+</dict>
+</plist>"#
+    );
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_strip_only_missing() {
+    let output = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/unprovisioned.xml",
+        "--only",
+        "com.apple.developer.team-identifier",
+        "-o",
+        "-",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Warning: com.apple.developer.team-identifier is not present, nothing was stripped\n"
+    );
+}
+
+#[test]
+fn test_strip_print() {
+    let output_path = std::env::temp_dir().join(format!(
+        "provisioned-entitlement-stripper-print-{}.plist",
+        std::process::id()
+    ));
+    let output = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "--print",
+        "--output-format",
+        "binary",
+        "-o",
+        output_path.to_str().unwrap(),
+    ]);
+    let written = std::fs::read(&output_path).unwrap();
+    std::fs::remove_file(&output_path).unwrap();
+    assert!(output.status.success());
+    assert!(written.starts_with(b"bplist"));
+    assert!(output.stdout.is_empty());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>com.apple.security.device.camera</key>
+	<true/>
+</dict>
+</plist>
+"#
+    );
+}
+
+#[test]
+fn test_strip_print_without_output() {
+    let output = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "--print",
+    ]);
+    assert!(output.status.success());
+    assert!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("<key>com.apple.security.device.camera</key>")
+    );
+    assert!(output.stderr.is_empty());
+}
+
+#[test]
+fn test_input_format() {
+    let output = run(&[
+        "dry-run",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "--input-format",
+        "xml",
+    ]);
+    assert!(output.status.success());
+
+    let output = run(&[
+        "dry-run",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "--input-format",
+        "binary",
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("Entitlements are not a binary plist")
+    );
+
+    let output = run(&[
+        "dry-run",
+        "--from-file",
+        "tests/fixtures/provisioned.txt",
+        "--input-format",
+        "xml",
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("Failed to parse entitlements as an XML plist")
+    );
+}
+
+#[test]
+fn test_strip_backup() {
+    let backup_path = std::env::temp_dir().join(format!(
+        "provisioned-entitlement-stripper-backup-{}.xml",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&backup_path);
+    let args = [
+        "strip",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "--backup",
+        backup_path.to_str().unwrap(),
+        "-o",
+        "-",
+    ];
+    let output = run(&args);
+    let backup = std::fs::read_to_string(&backup_path).unwrap();
+    let existing = run(&args);
+    let forced = run(&[&args[..], &["--force"]].concat());
+    std::fs::remove_file(&backup_path).unwrap();
+
+    assert!(output.status.success());
+    assert_eq!(
+        backup,
+        std::fs::read_to_string("tests/fixtures/provisioned.xml")
+            .unwrap()
+            .trim_end()
+    );
+    assert_eq!(existing.status.code(), Some(1));
+    assert!(
+        String::from_utf8(existing.stderr)
+            .unwrap()
+            .contains("already exists, use --force to overwrite it")
+    );
+    assert!(forced.status.success());
+}
+
+#[test]
+fn test_strip_indent() {
+    let output = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "--indent",
+        "2",
+        "-o",
+        "-",
+    ]);
+    assert!(output.status.success());
+    assert!(
+        String::from_utf8(output.stdout)
+            .unwrap()
+            .contains("\n  <key>com.apple.security.device.camera</key>\n  <true/>\n")
+    );
+
+    let output = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "--indent",
+        "0",
+        "--doctype",
+        "none",
+        "-o",
+        "-",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict><key>com.apple.security.device.camera</key><true/></dict>
+</plist>"#
+    );
+}
+
+#[test]
+fn test_codesign_not_found() {
+    let output = run(&[
+        "--codesign-path",
+        "/nonexistent/codesign",
+        "dry-run",
+        "Example.app",
+    ]);
+    assert_eq!(output.status.code(), Some(127));
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("codesign not found at /nonexistent/codesign"));
+    assert!(
+        stderr.contains("codesign not found; use --from-file or --from-stdin on this platform")
+    );
+
+    let output = run(&[
+        "--codesign-path",
+        "/nonexistent/codesign",
+        "--error-format",
+        "json",
+        "strip",
+        "Example.app",
+        "-o",
+        "-",
+    ]);
+    assert_eq!(output.status.code(), Some(127));
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_strip_warn_ambiguous() {
+    let args = [
+        "strip",
+        "--from-file",
+        "tests/fixtures/groups.xml",
+        "-o",
+        "-",
+    ];
+    let output = run(&[&args[..], &["--warn-ambiguous"]].concat());
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stderr).unwrap(),
+        "Warning: removing com.apple.security.application-groups, which some apps manage \
+         themselves, use --keep if it's needed\n"
+    );
+
+    let output = run(&[&args[..], &["--strict"]].concat());
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(String::from_utf8(output.stderr).unwrap().contains(
+        "Not removing com.apple.security.application-groups, which some apps manage themselves"
+    ));
+
+    let output = run(&[
+        &args[..],
+        &[
+            "--strict",
+            "--keep",
+            "com.apple.security.application-groups",
+        ],
+    ]
+    .concat());
+    assert!(output.status.success());
+}
+
+#[cfg(unix)]
+#[test]
+fn test_strip_to_fifo() {
+    use std::os::unix::fs::FileTypeExt;
+
+    let fifo_path = std::env::temp_dir().join(format!(
+        "provisioned-entitlement-stripper-fifo-{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_file(&fifo_path);
+    assert!(
+        Command::new("mkfifo")
+            .arg(&fifo_path)
+            .status()
+            .unwrap()
+            .success()
+    );
+    let reader = {
+        let fifo_path = fifo_path.clone();
+        std::thread::spawn(move || std::fs::read_to_string(fifo_path).unwrap())
+    };
+    let output = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "-o",
+        fifo_path.to_str().unwrap(),
+    ]);
+    let written = reader.join().unwrap();
+    let still_fifo = std::fs::symlink_metadata(&fifo_path)
+        .unwrap()
+        .file_type()
+        .is_fifo();
+    std::fs::remove_file(&fifo_path).unwrap();
+    assert!(output.status.success());
+    assert!(written.contains("<key>com.apple.security.device.camera</key>"));
+    assert!(still_fifo);
+}
+
+#[test]
+fn test_dry_run_deep() {
+    let output = run(&["dry-run", "--from-file", "tests/fixtures/nested.xml"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Provisioned entitlements:\n- com.apple.developer.team-identifier\n"
+    );
+
+    let output = run(&[
+        "dry-run",
+        "--from-file",
+        "tests/fixtures/nested.xml",
+        "--deep",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Provisioned entitlements:\n- com.apple.developer.team-identifier\n- com.example.settings/com.apple.developer.team-identifier\n"
+    );
+}
+
+#[test]
+fn test_error_format_json() {
+    let output = run(&[
+        "dry-run",
+        "--from-file",
+        "tests/fixtures/missing.xml",
+        "--error-format",
+        "json",
+    ]);
+    assert_eq!(output.status.code(), Some(1));
+    let error: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+    assert_eq!(
+        error["context"],
+        serde_json::json!([
+            "Failed to get entitlements from file",
+            "Failed to read entitlements file"
+        ])
+    );
+    assert!(error["error"].as_str().unwrap().contains("No such file"));
+}
+
+#[test]
+fn test_strip_keep_only() {
+    let output = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "--keep-only",
+        "tests/fixtures/allowlist.txt",
+        "--keep-only",
+        "com.apple.developer.team-identifier",
+        "-o",
+        "-",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>com.apple.developer.team-identifier</key>
+	<string>AAAAAAAAAA</string>
+	<key>com.apple.security.device.camera</key>
+	<true/>
+</dict>
+</plist>"#
+    );
+
+    let output = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "--keep-only",
+        "com.apple.security.device.camera",
+        "--extra",
+        "com.apple.security.device.camera",
+        "-o",
+        "-",
+    ]);
+    assert!(!output.status.success());
+}
+
+#[test]
+fn test_strip_fail_if_no_entitlements() {
+    let args = [
+        "strip",
+        "--from-file",
+        "tests/fixtures/empty.xml",
+        "-o",
+        "-",
+    ];
+    let output = run(&args);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict/>
+</plist>"#
+    );
+
+    let output = run(&[&args[..], &["--fail-if-no-entitlements"]].concat());
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("The app has no entitlements")
+    );
+}
+
+#[test]
+fn test_strip_dedupe_arrays() {
+    let output = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/duplicate-groups.xml",
+        "--keep",
+        "com.apple.security.application-groups",
+        "--dedupe-arrays",
+        "-o",
+        "-",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>com.apple.security.application-groups</key>
+	<array>
+		<string>AAAAAAAAAA.com.example.shared</string>
+		<string>AAAAAAAAAA.com.example.other</string>
+	</array>
+</dict>
+</plist>"#
+    );
+}
+
+#[test]
+fn test_strip_empty() {
+    let args = [
+        "strip",
+        "--from-file",
+        "tests/fixtures/empty-values.xml",
+        "-o",
+        "-",
+    ];
+    let without = run(&args);
+    assert!(without.status.success());
+    let without = String::from_utf8(without.stdout).unwrap();
+    assert!(without.contains("<key>com.example.empty-array</key>"));
+    assert!(without.contains("<key>com.example.empty-string</key>"));
+
+    let output = run(&[&args[..], &["--strip-empty"]].concat());
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>com.apple.security.app-sandbox</key>
+	<false/>
+</dict>
+</plist>"#
+    );
+}
+
+#[test]
+fn test_strip_only_provisioned() {
+    let args = [
+        "strip",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "--only-provisioned",
+        "-o",
+        "-",
+    ];
+    let output = run(&args);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>com.apple.application-identifier</key>
+	<string>AAAAAAAAAA.com.example.example</string>
+	<key>com.apple.developer.team-identifier</key>
+	<string>AAAAAAAAAA</string>
+</dict>
+</plist>"#
+    );
+
+    // Kept entitlements aren't stripped, so they aren't included
+    let keep = run(&[
+        &args[..],
+        &["--keep", "com.apple.developer.team-identifier"],
+    ]
+    .concat());
+    assert!(keep.status.success());
+    let keep = String::from_utf8(keep.stdout).unwrap();
+    assert!(keep.contains("<key>com.apple.application-identifier</key>"));
+    assert!(!keep.contains("team-identifier"));
+
+    assert_eq!(
+        run(&[&args[..], &["--stats"]].concat()).status.code(),
+        Some(2)
+    );
+}
+
+#[test]
+fn test_strip_no_doctype() {
+    let output = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "--no-doctype",
+        "--no-xml-declaration",
+        "-o",
+        "-",
+    ]);
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(!stdout.contains("<!DOCTYPE"));
+    assert_eq!(
+        stdout,
+        "<plist version=\"1.0\">\n<dict>\n\t<key>com.apple.security.device.camera</key>\n\t<true/>\n</dict>\n</plist>"
+    );
+}
+
+#[test]
+fn test_audit_list_online() {
+    let url = format!(
+        "file://{}/tests/fixtures/apple-entitlements.json",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let output = run(&["audit-list", "--online", "--url", &url]);
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "com.apple.developer.healthkit\n"
+    );
+}
+
+#[test]
+fn test_config() {
+    let dir = std::env::temp_dir().join(format!(
+        "provisioned-entitlement-stripper-config-{}",
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join(".stripper.toml"),
+        "extra = [\"com.apple.security.device.camera\"]\noutput-format = \"plist-json\"\n",
+    )
+    .unwrap();
+    let config_path = dir.join("other.toml");
+    std::fs::write(
+        &config_path,
+        "keep = [\"com.apple.developer.team-identifier\"]\n",
+    )
+    .unwrap();
+    let fixture =
+        std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/provisioned.xml");
+    let run_in_dir = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_provisioned-entitlement-stripper"))
+            .args(["strip", "--from-file", fixture.to_str().unwrap(), "-o", "-"])
+            .args(args)
+            .current_dir(&dir)
+            .output()
+            .unwrap()
+    };
+
+    // .stripper.toml in the current directory is used by default
+    let default = run_in_dir(&[]);
+    // Flags take precedence over the config
+    let overridden = run_in_dir(&["--extra", "com.example.unused", "--output-format", "xml"]);
+    // --config replaces the default config rather than adding to it
+    let config = run_in_dir(&["--config", config_path.to_str().unwrap()]);
+    let invalid = run(&[
+        "--config",
+        "tests/fixtures/list.txt",
+        "dry-run",
+        "--from-stdin",
+    ]);
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert!(default.status.success());
+    assert_eq!(String::from_utf8(default.stdout).unwrap(), "{}");
+    assert!(overridden.status.success());
+    assert!(
+        String::from_utf8(overridden.stdout)
+            .unwrap()
+            .contains("<key>com.apple.security.device.camera</key>")
+    );
+    assert!(config.status.success());
+    assert_eq!(
+        String::from_utf8(config.stdout).unwrap(),
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>com.apple.developer.team-identifier</key>
+	<string>AAAAAAAAAA</string>
+	<key>com.apple.security.device.camera</key>
+	<true/>
+</dict>
+</plist>"#
+    );
+    assert!(!invalid.status.success());
+    assert!(
+        String::from_utf8(invalid.stderr)
+            .unwrap()
+            .contains("Failed to parse config tests/fixtures/list.txt")
+    );
+}
+
+#[test]
+fn test_strip_multiple_outputs() {
+    let output_path = |extension: &str| {
+        std::env::temp_dir().join(format!(
+            "provisioned-entitlement-stripper-outputs-{}.{extension}",
+            std::process::id()
+        ))
+    };
+    let (xml_path, binary_path, json_path) = (
+        output_path("xml"),
+        output_path("plist"),
+        output_path("json"),
+    );
+    let output = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "-o",
+        xml_path.to_str().unwrap(),
+        "-o",
+        binary_path.to_str().unwrap(),
+        "-o",
+        json_path.to_str().unwrap(),
+    ]);
+    let xml = std::fs::read(&xml_path).unwrap();
+    let binary = std::fs::read(&binary_path).unwrap();
+    let json = std::fs::read_to_string(&json_path).unwrap();
+    for path in [&xml_path, &binary_path, &json_path] {
+        std::fs::remove_file(path).unwrap();
+    }
+    assert!(output.status.success());
+    assert!(xml.starts_with(b"<?xml"));
+    assert!(binary.starts_with(b"bplist"));
+    assert!(json.starts_with('{'));
+    assert_eq!(
+        plist::Value::from_reader_xml(xml.as_slice()).unwrap(),
+        plist::Value::from_reader(std::io::Cursor::new(binary)).unwrap()
+    );
+
+    let entitlements_path = output_path("entitlements");
+    let output = run(&[
+        "strip",
+        "--from-file",
+        "tests/fixtures/provisioned.xml",
+        "-o",
+        xml_path.to_str().unwrap(),
+        "-o",
+        entitlements_path.to_str().unwrap(),
+    ]);
+    assert!(!output.status.success());
+    assert!(!xml_path.exists());
+    assert!(String::from_utf8(output.stderr).unwrap().contains(&format!(
+        "Can't tell which format to write {} in",
+        entitlements_path.display()
+    )));
+}
+
+#[test]
+fn test_strip_sort_output_keys() {
+    let temp_path = |name: &str| {
+        std::env::temp_dir().join(format!(
+            "provisioned-entitlement-stripper-sort-{name}-{}.xml",
+            std::process::id()
+        ))
+    };
+    let strip_sorted = |input: &str| {
+        let (input_path, output_path) = (temp_path("input"), temp_path("output"));
+        std::fs::write(&input_path, input).unwrap();
+        let output = run(&[
+            "strip",
+            "--from-file",
+            input_path.to_str().unwrap(),
+            "--sort-output-keys",
+            "-o",
+            output_path.to_str().unwrap(),
+        ]);
+        assert!(output.status.success());
+        let written = std::fs::read_to_string(&output_path).unwrap();
+        std::fs::remove_file(&input_path).unwrap();
+        std::fs::remove_file(&output_path).unwrap();
+        written
+    };
+
+    let sorted = strip_sorted(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+	<key>com.example.b</key>
+	<dict>
+		<key>y</key>
+		<true/>
+		<key>x</key>
+		<true/>
+	</dict>
+	<key>com.apple.developer.team-identifier</key>
+	<string>AAAAAAAAAA</string>
+	<key>com.example.a</key>
+	<true/>
+</dict>
+</plist>
+"#,
+    );
+    assert_eq!(
+        sorted,
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>com.example.a</key>
+	<true/>
+	<key>com.example.b</key>
+	<dict>
+		<key>x</key>
+		<true/>
+		<key>y</key>
+		<true/>
+	</dict>
+</dict>
+</plist>"#
+    );
+    let reordered = strip_sorted(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+	<key>com.example.a</key>
+	<true/>
+	<key>com.apple.developer.team-identifier</key>
+	<string>AAAAAAAAAA</string>
+	<key>com.example.b</key>
+	<dict>
+		<key>x</key>
+		<true/>
+		<key>y</key>
+		<true/>
+	</dict>
+</dict>
+</plist>
+"#,
+    );
+    assert_eq!(reordered, sorted);
+    assert_eq!(strip_sorted(&sorted), sorted);
+}
+
+#[test]
+fn test_explain() {
+    let output = run(&[
+        "explain",
+        "com.apple.developer.team-identifier",
+        "com.apple.security.device.camera",
+    ]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "com.apple.developer.team-identifier
+  The team ID of the developer account the app was signed for.
+  Provisioned: only a provisioning profile can grant it, so it's stripped to re-sign the app without one.
+com.apple.security.device.camera
+  No description available.
+"
+    );
+
+    let output = run(&["explain", "--format", "json", "com.example.unknown"]);
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        r#"[{"entitlement":"com.example.unknown","description":null,"provisioned":false,"debug":false}]
+"#
+    );
+
+    assert_eq!(run(&["explain"]).status.code(), Some(2));
+}
+
+#[test]
+fn test_since() {
+    let entitlements = br#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+	<key>com.apple.developer.team-identifier</key>
+	<string>AAAAAAAAAA</string>
+	<key>com.apple.developer.weatherkit</key>
+	<true/>
+</dict>
+</plist>
+"#;
+    let dry_run = |args: &[&str]| {
+        let output = run_with_stdin(&[&["dry-run", "--from-stdin"], args].concat(), entitlements);
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+    assert_eq!(
+        dry_run(&[]),
+        "Provisioned entitlements:\n- com.apple.developer.team-identifier\n- com.apple.developer.weatherkit\n"
+    );
+    assert_eq!(dry_run(&["--since", "13"]), dry_run(&[]),);
+    assert_eq!(
+        dry_run(&["--since", "12.6"]),
+        "Provisioned entitlements:\n- com.apple.developer.team-identifier\n"
+    );
+
+    let output = run_with_stdin(
+        &["strip", "--from-stdin", "--since", "12", "-o", "-"],
+        entitlements,
+    );
+    assert!(output.status.success());
+    let stripped = String::from_utf8(output.stdout).unwrap();
+    assert!(stripped.contains("<key>com.apple.developer.weatherkit</key>"));
+    assert!(!stripped.contains("<key>com.apple.developer.team-identifier</key>"));
+
+    let output = run(&["known-entitlements", "--since", "10.6"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "application-identifier\naps-environment\n"
+    );
+
+    let output = run(&["dry-run", "--from-stdin", "--since", "thirteen"]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("\"thirteen\" is not a macOS version, expected one like 13 or 10.15")
+    );
+    let output = run(&[
+        "dry-run",
+        "--from-stdin",
+        "--since",
+        "13",
+        "--list-file",
+        "tests/fixtures/list.txt",
+    ]);
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_strip_debug() {
+    let entitlements = br#"<?xml version="1.0" encoding="UTF-8"?>
+<plist version="1.0">
+<dict>
+	<key>com.apple.developer.team-identifier</key>
+	<string>AAAAAAAAAA</string>
+	<key>com.apple.security.get-task-allow</key>
+	<true/>
+	<key>get-task-allow</key>
+	<true/>
+	<key>com.apple.security.device.camera</key>
+	<true/>
+</dict>
+</plist>
+"#;
+    let strip = |args: &[&str]| {
+        let output = run_with_stdin(
+            &[&["strip", "--from-stdin", "-o", "-"], args].concat(),
+            entitlements,
+        );
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+    let stripped = strip(&[]);
+    assert!(!stripped.contains("<key>com.apple.developer.team-identifier</key>"));
+    assert!(stripped.contains("<key>com.apple.security.get-task-allow</key>"));
+    assert!(stripped.contains("<key>get-task-allow</key>"));
+    assert!(stripped.contains("<key>com.apple.security.device.camera</key>"));
+
+    let stripped = strip(&["--strip-debug"]);
+    assert!(!stripped.contains("<key>com.apple.security.get-task-allow</key>"));
+    assert!(!stripped.contains("<key>get-task-allow</key>"));
+    assert!(stripped.contains("<key>com.apple.security.device.camera</key>"));
+
+    let stripped = strip(&["--strip-debug", "--keep", "get-task-allow"]);
+    assert!(!stripped.contains("<key>com.apple.security.get-task-allow</key>"));
+    assert!(stripped.contains("<key>get-task-allow</key>"));
+
+    let dry_run = |args: &[&str]| {
+        let output = run_with_stdin(&[&["dry-run", "--from-stdin"], args].concat(), entitlements);
+        String::from_utf8(output.stdout).unwrap()
+    };
+    assert_eq!(
+        dry_run(&[]),
+        "Provisioned entitlements:\n- com.apple.developer.team-identifier\n"
+    );
+    assert_eq!(
+        dry_run(&["--strip-debug"]),
+        "Provisioned entitlements:\n- com.apple.developer.team-identifier\nDebug entitlements:\n- com.apple.security.get-task-allow\n- get-task-allow\n"
+    );
+    assert_eq!(
+        dry_run(&["--strip-debug", "--format", "json"]),
+        r#"{"app":null,"provisioned_entitlements":["com.apple.developer.team-identifier"],"debug_entitlements":["com.apple.security.get-task-allow","get-task-allow"]}
+"#
+    );
+
+    let output = run(&["explain", "com.apple.security.get-task-allow"]);
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "com.apple.security.get-task-allow
+  Lets debuggers attach to the app, which macOS debug builds have.
+  Debug: distribution builds usually don't want it, so it's stripped with --strip-debug.
+"
+    );
+}
@@ -0,0 +1,700 @@
+//! Runs the binary against a fake codesign to cover how codesign is invoked.
+#![cfg(unix)]
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use assert_cmd::cargo::cargo_bin_cmd;
+
+const FAKE_CODESIGN: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/fake-codesign");
+const FAKE_PKGUTIL: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/fake-pkgutil");
+
+fn test_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "provisioned-entitlement-stripper-{}-{}",
+        name,
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn command(dir: &Path, entitlements: &str) -> assert_cmd::Command {
+    let mut command = cargo_bin_cmd!("provisioned-entitlement-stripper");
+    command
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .env("FAKE_CODESIGN_ARGS", dir.join("args"))
+        .env("FAKE_CODESIGN_ENTITLEMENTS", entitlements)
+        .arg("--codesign-path")
+        .arg(FAKE_CODESIGN);
+    command
+}
+
+#[test]
+fn test_strip_app() {
+    let dir = test_dir("fake-codesign-strip");
+    let output_path = dir.join("entitlements.xml");
+    command(&dir, "tests/fixtures/provisioned.xml")
+        .arg("strip")
+        .arg("Example.app")
+        .arg("-o")
+        .arg(&output_path)
+        .assert()
+        .success();
+
+    let args = fs::read_to_string(dir.join("args")).unwrap();
+    let stripped = fs::read_to_string(&output_path).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(args, "--display\n--xml\n--entitlements\n-\nExample.app\n");
+    assert_eq!(
+        stripped,
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>com.apple.security.device.camera</key>
+	<true/>
+</dict>
+</plist>"#
+    );
+}
+
+#[test]
+fn test_extract_app() {
+    let dir = test_dir("fake-codesign-extract");
+    let output_path = dir.join("entitlements.plist");
+    command(&dir, "tests/fixtures/provisioned.xml")
+        .arg("extract")
+        .arg("Example.app")
+        .arg("-o")
+        .arg(&output_path)
+        .arg("--output-format")
+        .arg("binary")
+        .assert()
+        .success();
+
+    let extracted = fs::read(&output_path).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(extracted.starts_with(b"bplist00"));
+    assert_eq!(
+        plist::from_bytes::<plist::Value>(&extracted).unwrap(),
+        plist::Value::from_file("tests/fixtures/provisioned.xml").unwrap()
+    );
+}
+
+#[test]
+fn test_dry_run_pkg() {
+    let dir = test_dir("fake-codesign-pkg");
+    let pkg_path = dir.join("Example.pkg");
+    fs::create_dir_all(pkg_path.join("Example.pkg/Payload/Applications/Example.app/Contents"))
+        .unwrap();
+    fs::create_dir_all(pkg_path.join("Resources")).unwrap();
+    let output = command(&dir, "tests/fixtures/provisioned.xml")
+        .env("PKGUTIL", FAKE_PKGUTIL)
+        .arg("dry-run")
+        .arg(&pkg_path)
+        .output()
+        .unwrap();
+
+    let args = fs::read_to_string(dir.join("args")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Bundle identifier: com.example.example\nProvisioned entitlements:\n- com.apple.application-identifier\n- com.apple.developer.team-identifier\n"
+    );
+    let app_path = Path::new(args.lines().last().unwrap());
+    assert!(app_path.ends_with("Example.pkg/Payload/Applications/Example.app"));
+    // The expanded package is cleaned up afterwards
+    assert!(!app_path.exists());
+}
+
+#[test]
+fn test_dry_run_info_plist_bundle_identifier() {
+    let dir = test_dir("fake-codesign-info-plist");
+    let app_path = dir.join("Example.app");
+    fs::create_dir_all(app_path.join("Contents")).unwrap();
+    fs::write(
+        app_path.join("Contents/Info.plist"),
+        r#"<plist version="1.0"><dict><key>CFBundleIdentifier</key><string>com.example.info</string></dict></plist>"#,
+    )
+    .unwrap();
+    let output = command(&dir, "tests/fixtures/unprovisioned.xml")
+        .arg("dry-run")
+        .arg(&app_path)
+        .output()
+        .unwrap();
+    let no_info_plist = command(&dir, "tests/fixtures/unprovisioned.xml")
+        .args(["dry-run", "Example.app"])
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        String::from_utf8(output.stdout).unwrap(),
+        "Bundle identifier: com.example.info\nNo provisioned entitlements found\n"
+    );
+    assert!(no_info_plist.status.success());
+    assert_eq!(
+        String::from_utf8(no_info_plist.stdout).unwrap(),
+        "No provisioned entitlements found\n"
+    );
+}
+
+#[test]
+fn test_dry_run_binary() {
+    let dir = test_dir("fake-codesign-binary");
+    command(&dir, "tests/fixtures/provisioned.xml")
+        .args([
+            "dry-run",
+            "tests/fixtures/tool",
+            "--target-type",
+            "binary",
+            "--recursive",
+        ])
+        .assert()
+        .success()
+        .stdout(
+            "tests/fixtures/tool:\nBundle identifier: com.example.example\nProvisioned entitlements:\n- com.apple.application-identifier\n- com.apple.developer.team-identifier\n",
+        );
+    let args = fs::read_to_string(dir.join("args")).unwrap();
+
+    let bundle = command(&dir, "tests/fixtures/provisioned.xml")
+        .args(["dry-run", "tests/fixtures/tool", "--target-type", "bundle"])
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(
+        args,
+        "--display\n--xml\n--entitlements\n-\ntests/fixtures/tool\n"
+    );
+    assert!(!bundle.status.success());
+    assert!(
+        String::from_utf8(bundle.stderr)
+            .unwrap()
+            .contains("tests/fixtures/tool is not a bundle directory")
+    );
+}
+
+#[test]
+fn test_dry_run_arch() {
+    let dir = test_dir("fake-codesign-arch");
+    command(&dir, "tests/fixtures/provisioned.xml")
+        .args(["dry-run", "Example.app", "--arch", "arm64"])
+        .assert()
+        .success()
+        .stdout(
+            "Bundle identifier: com.example.example\nProvisioned entitlements:\n- com.apple.application-identifier\n- com.apple.developer.team-identifier\n",
+        );
+
+    let args = fs::read_to_string(dir.join("args")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(
+        args,
+        "--display\n--arch\narm64\n--xml\n--entitlements\n-\nExample.app\n"
+    );
+}
+
+#[test]
+fn test_codesign_arg() {
+    let dir = test_dir("fake-codesign-arg");
+    command(&dir, "tests/fixtures/provisioned.xml")
+        .args(["dry-run", "Example.app", "--codesign-arg", "--deep"])
+        .assert()
+        .success();
+
+    let args = fs::read_to_string(dir.join("args")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(
+        args,
+        "--display\n--xml\n--entitlements\n-\n--deep\nExample.app\n"
+    );
+
+    let dir = test_dir("fake-codesign-arg-reserved");
+    command(&dir, "tests/fixtures/provisioned.xml")
+        .args(["dry-run", "Example.app", "--codesign-arg", "--xml"])
+        .assert()
+        .failure();
+    let ran = dir.join("args").exists();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(!ran);
+}
+
+#[test]
+fn test_strip_in_place() {
+    let dir = test_dir("fake-codesign-in-place");
+    command(&dir, "tests/fixtures/provisioned.xml")
+        .args(["strip", "Example.app", "--in-place", "--sign", "-"])
+        .assert()
+        .success();
+
+    let args = fs::read_to_string(dir.join("args")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    let args: Vec<&str> = args.lines().collect();
+    assert_eq!(args[..4], ["--force", "--sign", "-", "--entitlements"]);
+    assert!(args[4].ends_with(".entitlements"));
+    assert!(!Path::new(args[4]).exists());
+    assert_eq!(args[5..], ["Example.app"]);
+}
+
+#[test]
+fn test_strip_in_place_verify() {
+    let dir = test_dir("fake-codesign-in-place-verify");
+    command(&dir, "tests/fixtures/provisioned.xml")
+        .args([
+            "strip",
+            "Example.app",
+            "--in-place",
+            "--sign",
+            "-",
+            "--verify",
+        ])
+        .assert()
+        .success()
+        .stderr("Example.app has a valid signature\n");
+
+    // The fake codesign records the arguments of the last run, the verification
+    let args = fs::read_to_string(dir.join("args")).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(args, "--verify\n--strict\nExample.app\n");
+}
+
+#[test]
+fn test_verify() {
+    let dir = test_dir("fake-codesign-verify");
+    command(&dir, "tests/fixtures/provisioned.xml")
+        .args(["verify", "Example.app"])
+        .assert()
+        .success()
+        .stdout("Example.app has a valid signature\n");
+    let args = fs::read_to_string(dir.join("args")).unwrap();
+
+    // The fake codesign fails, printing to stderr, when the entitlements don't exist
+    let output = command(&dir, "tests/fixtures/nonexistent.xml")
+        .args(["verify", "Example.app"])
+        .output()
+        .unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(args, "--verify\n--strict\nExample.app\n");
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+    let stderr = String::from_utf8(output.stderr).unwrap();
+    assert!(stderr.contains("Failed to verify the signature of Example.app"));
+    assert!(stderr.contains("The signature is invalid: cat: tests/fixtures/nonexistent.xml"));
+}
+
+#[test]
+fn test_strip_in_place_requires_sign() {
+    let dir = test_dir("fake-codesign-in-place-no-sign");
+    command(&dir, "tests/fixtures/provisioned.xml")
+        .args(["strip", "Example.app", "--in-place"])
+        .assert()
+        .failure();
+    let ran = dir.join("args").exists();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(!ran);
+}
+
+#[test]
+fn test_codesign_failure() {
+    let dir = test_dir("fake-codesign-failure");
+    let output = command(&dir, "tests/fixtures/nonexistent.xml")
+        .args(["dry-run", "Example.app"])
+        .assert()
+        .code(4)
+        .get_output()
+        .stderr
+        .clone();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(
+        String::from_utf8(output)
+            .unwrap()
+            .contains("codesign failed with status")
+    );
+}
+
+#[test]
+fn test_not_signed() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = test_dir("fake-codesign-not-signed");
+    let codesign_path = dir.join("codesign");
+    fs::write(
+        &codesign_path,
+        "#!/bin/sh\necho \"Example.app: code object is not signed at all\" >&2\nexit 1\n",
+    )
+    .unwrap();
+    fs::set_permissions(&codesign_path, fs::Permissions::from_mode(0o755)).unwrap();
+    let output = cargo_bin_cmd!("provisioned-entitlement-stripper")
+        .arg("--codesign-path")
+        .arg(&codesign_path)
+        .args(["dry-run", "Example.app"])
+        .assert()
+        .code(3)
+        .get_output()
+        .stderr
+        .clone();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(
+        String::from_utf8(output)
+            .unwrap()
+            .contains("The app is not signed, sign it first")
+    );
+}
+
+fn write_entitlements(dir: &Path, app: &str, entitlements: &str) {
+    fs::write(
+        dir.join(format!("{}.xml", app)),
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?><plist version="1.0"><dict><key>com.apple.developer.team-identifier</key><string>AAAAAAAAAA</string>{}</dict></plist>"#,
+            entitlements
+        ),
+    )
+    .unwrap();
+}
+
+#[test]
+fn test_strip_output_dir_stats() {
+    let dir = test_dir("fake-codesign-stats");
+    write_entitlements(
+        &dir,
+        "A.app",
+        "<key>com.apple.security.app-sandbox</key><true/>",
+    );
+    write_entitlements(&dir, "B.app", "");
+    let output_dir = dir.join("out");
+    fs::create_dir(&output_dir).unwrap();
+    let output = command(&dir, dir.to_str().unwrap())
+        .args(["strip", "A.app", "B.app", "--stats", "--output-dir"])
+        .arg(&output_dir)
+        .assert()
+        .success()
+        .get_output()
+        .stderr
+        .clone();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "Removed 2 of 3 entitlements (1 remaining)\n"
+    );
+}
+
+#[test]
+fn test_strip_output_dir_threads() {
+    let dir = test_dir("fake-codesign-threads");
+    let apps = ["A.app", "B.app", "C.app", "D.app", "E.app", "F.app"];
+    for (index, app) in apps.iter().enumerate() {
+        write_entitlements(
+            &dir,
+            app,
+            &format!(
+                "<key>com.example.{}</key><integer>{}</integer>",
+                index, index
+            ),
+        );
+    }
+    // Has no entitlements file, so the fake codesign fails
+    let apps = [&apps[..3], &["Missing.app"], &apps[3..]].concat();
+
+    let strip = |threads: &str| {
+        let output_dir = dir.join(format!("out-{}", threads));
+        fs::create_dir(&output_dir).unwrap();
+        let output = command(&dir, dir.to_str().unwrap())
+            .arg("strip")
+            .args(&apps)
+            .arg("--output-dir")
+            .arg(&output_dir)
+            .arg("--json-lines")
+            .arg(output_dir.join("log.jsonl"))
+            .arg("--summary-json")
+            .arg(output_dir.join("summary.json"))
+            .arg("--relative-to")
+            .arg(&output_dir)
+            .args(["--threads", threads])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(1));
+        let mut files: Vec<(String, String)> = fs::read_dir(&output_dir)
+            .unwrap()
+            .map(|entry| {
+                let path = entry.unwrap().path();
+                (
+                    path.file_name().unwrap().to_string_lossy().into_owned(),
+                    fs::read_to_string(&path).unwrap(),
+                )
+            })
+            .collect();
+        files.sort();
+        (String::from_utf8(output.stderr).unwrap(), files)
+    };
+    let serial = strip("1");
+    let parallel = strip("4");
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(serial, parallel);
+    assert_eq!(serial.1.len(), 8);
+    assert!(serial.0.starts_with("Failed to strip Missing.app: "));
+}
+
+#[test]
+fn test_strip_output_dir_name_template() {
+    let dir = test_dir("fake-codesign-name-template");
+    let output_dir = dir.join("out");
+    fs::create_dir(&output_dir).unwrap();
+    command(&dir, "tests/fixtures/provisioned.xml")
+        .args([
+            "strip",
+            "Example.app",
+            "--name-template",
+            "{bundle_id}.entitlements",
+        ])
+        .arg("--output-dir")
+        .arg(&output_dir)
+        .assert()
+        .success();
+    let written = output_dir
+        .join("com.example.example.entitlements")
+        .is_file();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(written);
+}
+
+#[test]
+fn test_strip_merge_output() {
+    let dir = test_dir("fake-codesign-merge");
+    write_entitlements(
+        &dir,
+        "A.app",
+        "<key>com.apple.security.app-sandbox</key><true/><key>com.apple.security.device.camera</key><true/>",
+    );
+    write_entitlements(
+        &dir,
+        "B.app",
+        "<key>com.apple.security.app-sandbox</key><true/><key>com.apple.security.network.client</key><true/>",
+    );
+    let output = command(&dir, dir.to_str().unwrap())
+        .args(["strip", "A.app", "B.app", "--merge-output", "-"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE plist PUBLIC "-//Apple//DTD PLIST 1.0//EN" "http://www.apple.com/DTDs/PropertyList-1.0.dtd">
+<plist version="1.0">
+<dict>
+	<key>com.apple.security.app-sandbox</key>
+	<true/>
+	<key>com.apple.security.device.camera</key>
+	<true/>
+	<key>com.apple.security.network.client</key>
+	<true/>
+</dict>
+</plist>"#
+    );
+}
+
+#[test]
+fn test_strip_merge_output_conflict() {
+    let dir = test_dir("fake-codesign-merge-conflict");
+    write_entitlements(
+        &dir,
+        "A.app",
+        "<key>com.apple.security.app-sandbox</key><true/>",
+    );
+    write_entitlements(
+        &dir,
+        "B.app",
+        "<key>com.apple.security.app-sandbox</key><false/>",
+    );
+    let output = command(&dir, dir.to_str().unwrap())
+        .args(["strip", "A.app", "B.app", "--merge-output", "-"])
+        .assert()
+        .failure()
+        .get_output()
+        .clone();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(output.stdout.is_empty());
+    assert!(
+        String::from_utf8(output.stderr)
+            .unwrap()
+            .contains("com.apple.security.app-sandbox has different values in A.app and B.app")
+    );
+}
+
+#[test]
+fn test_print_command_no_exec() {
+    let dir = test_dir("fake-codesign-no-exec");
+    let output_path = dir.join("entitlements.xml");
+    command(&dir, "tests/fixtures/provisioned.xml")
+        .args(["--print-command", "--no-exec", "strip", "My App.app", "-o"])
+        .arg(&output_path)
+        .assert()
+        .success()
+        .stdout("")
+        .stderr(format!(
+            "{} --display --xml --entitlements - 'My App.app'\n",
+            FAKE_CODESIGN
+        ));
+    let ran = dir.join("args").exists();
+    let wrote = output_path.exists();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(!ran);
+    assert!(!wrote);
+}
+
+#[test]
+fn test_strip_unicode_app_paths() {
+    let dir = test_dir("fake-codesign-unicode");
+    let app_path = dir.join("My Apps").join("Café.app");
+    let output_path = dir.join("Entitlements Für Café.xml");
+    command(&dir, "tests/fixtures/provisioned.xml")
+        .arg("strip")
+        .arg(&app_path)
+        .arg("-o")
+        .arg(&output_path)
+        .assert()
+        .success();
+    let args = fs::read_to_string(dir.join("args")).unwrap();
+    let written = fs::read_to_string(&output_path).unwrap();
+    assert_eq!(
+        args,
+        format!(
+            "--display\n--xml\n--entitlements\n-\n{}\n",
+            app_path.display()
+        )
+    );
+    assert!(written.contains("<key>com.apple.security.device.camera</key>"));
+
+    command(&dir, "tests/fixtures/provisioned.xml")
+        .args(["--print-command", "--no-exec", "strip"])
+        .arg(&app_path)
+        .arg("-o")
+        .arg(&output_path)
+        .assert()
+        .success()
+        .stderr(format!(
+            "{} --display --xml --entitlements - '{}'\n",
+            FAKE_CODESIGN,
+            app_path.display()
+        ));
+
+    let apps = ["Café.app", "My App.app", "日本語.app"];
+    for app in apps {
+        write_entitlements(&dir, app, "");
+    }
+    let output_dir = dir.join("Out Für Apps");
+    fs::create_dir(&output_dir).unwrap();
+    command(&dir, dir.to_str().unwrap())
+        .arg("strip")
+        .args(apps.map(|app| dir.join(app)))
+        .arg("--output-dir")
+        .arg(&output_dir)
+        .args(["--name-template", "{app} ({team}).{ext}"])
+        .assert()
+        .success();
+    let mut files: Vec<String> = fs::read_dir(&output_dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+        .collect();
+    files.sort();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(
+        files,
+        [
+            "Café (AAAAAAAAAA).xml",
+            "My App (AAAAAAAAAA).xml",
+            "日本語 (AAAAAAAAAA).xml"
+        ]
+    );
+}
+
+#[test]
+fn test_compare() {
+    let dir = test_dir("fake-codesign-compare");
+    write_entitlements(
+        &dir,
+        "Debug.app",
+        "<key>com.apple.security.get-task-allow</key><true/>",
+    );
+    fs::write(
+        dir.join("Release.app.xml"),
+        r#"<?xml version="1.0" encoding="UTF-8"?><plist version="1.0"><dict><key>com.apple.developer.team-identifier</key><string>BBBBBBBBBB</string></dict></plist>"#,
+    )
+    .unwrap();
+    let output = command(&dir, dir.to_str().unwrap())
+        .args(["compare", "Debug.app", "Release.app"])
+        .assert()
+        .success()
+        .get_output()
+        .stdout
+        .clone();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(
+        String::from_utf8(output).unwrap(),
+        "Differing entitlements:\n- com.apple.developer.team-identifier (provisioned): \"AAAAAAAAAA\" -> \"BBBBBBBBBB\"\n- com.apple.security.get-task-allow: true -> missing\n"
+    );
+}
+
+#[test]
+fn test_strip_output_dir_relative_to() {
+    let dir = test_dir("fake-codesign-relative-to");
+    let output_dir = dir.join("out");
+    fs::create_dir(&output_dir).unwrap();
+    let json_lines = dir.join("log.jsonl");
+    command(&dir, "tests/fixtures/provisioned.xml")
+        .arg("strip")
+        .arg(dir.join("Example.app"))
+        .arg("--output-dir")
+        .arg(&output_dir)
+        .arg("--json-lines")
+        .arg(&json_lines)
+        .arg("--relative-to")
+        .arg(&dir)
+        .assert()
+        .success();
+    let log = fs::read_to_string(&json_lines).unwrap();
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(
+        log,
+        r#"{"app":"Example.app","removed":["com.apple.application-identifier","com.apple.developer.team-identifier"],"output":"out/Example.entitlements.xml","status":"ok","error":null}"#
+            .to_string()
+            + "\n"
+    );
+}
+
+#[test]
+fn test_codesign_path_env() {
+    let dir = test_dir("fake-codesign-env");
+    cargo_bin_cmd!("provisioned-entitlement-stripper")
+        .current_dir(env!("CARGO_MANIFEST_DIR"))
+        .env("FAKE_CODESIGN_ARGS", dir.join("args"))
+        .env("FAKE_CODESIGN_ENTITLEMENTS", "tests/fixtures/provisioned.xml")
+        .env("STRIPPER_CODESIGN", FAKE_CODESIGN)
+        .env("STRIPPER_FORMAT", "json")
+        .args(["dry-run", "Example.app"])
+        .assert()
+        .success()
+        .stdout(
+            "{\"app\":\"Example.app\",\"bundle_identifier\":\"com.example.example\",\"provisioned_entitlements\":[\"com.apple.application-identifier\",\"com.apple.developer.team-identifier\"]}\n",
+        );
+    let ran = dir.join("args").exists();
+
+    // The flag takes precedence over the environment
+    command(&dir, "tests/fixtures/provisioned.xml")
+        .env("STRIPPER_CODESIGN", "/nonexistent/codesign")
+        .env("STRIPPER_FORMAT", "json")
+        .args(["dry-run", "Example.app", "--format", "text"])
+        .assert()
+        .success()
+        .stdout(
+            "Bundle identifier: com.example.example\nProvisioned entitlements:\n- com.apple.application-identifier\n- com.apple.developer.team-identifier\n",
+        );
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(ran);
+}